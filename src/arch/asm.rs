@@ -1,5 +1,7 @@
-use super::{opcode::Opcode, operand::Operand};
-use crate::VM_MODE;
+use super::{
+    opcode::Opcode,
+    operand::{ImmediateValue, Operand},
+};
 use std::fmt::Debug;
 
 #[derive(Clone)]
@@ -12,76 +14,93 @@ pub struct Assembly {
 
 impl Debug for Assembly {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (base_space_size, addr_space_size) = if unsafe { VM_MODE } { (13, 0) } else { (14, 1) };
+        f.write_str(&self.render(None))
+    }
+}
+
+// VMモード(トレース)用の表示．逆アセンブル時とは桁揃えが異なる
+pub struct Traced<'a>(pub &'a Assembly);
+
+impl Debug for Traced<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.layout(13, 0, &self.0.instruction.render(None)))
+    }
+}
+
+impl Assembly {
+    // 機械語のバイト列．codeには先頭のバイトから上位に詰めてある
+    pub fn bytes(&self) -> Vec<u8> {
+        (0..self.size)
+            .rev()
+            .map(|i| (self.code.checked_shr(8 * i as u32).unwrap_or(0) & 0xff) as u8)
+            .collect()
+    }
 
+    // 逆アセンブル結果の1行．targetがあれば飛び先のアドレスの代わりに表示する
+    pub fn render(&self, target: Option<&str>) -> String {
+        self.layout(14, 1, &self.instruction.render(target))
+    }
+
+    fn layout(&self, base_space_size: usize, addr_space_size: usize, instruction: &str) -> String {
         let base_space = " ".repeat(base_space_size - self.size * 2);
         let addr_space = " ".repeat(addr_space_size);
 
         if self.code == 0 {
             if self.size == 1 {
-                return write!(
-                    f,
-                    "{:04x}:{addr_space}{:02x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                return format!(
+                    "{:04x}:{addr_space}{:02x}{base_space}{instruction}",
+                    self.address, self.code
                 );
             }
 
-            return write!(
-                f,
-                "{:04x}:{addr_space}{:04x}{base_space}{:?}",
-                self.address, self.code, self.instruction
+            return format!(
+                "{:04x}:{addr_space}{:04x}{base_space}{instruction}",
+                self.address, self.code
             );
         }
 
         // 本当はmatch文を使いたくなかったが、0000等の表示が{:0x}だと0になってしまうので、match文を使う
         match self.size {
             1 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:02x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:02x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             2 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:04x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:04x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             3 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:06x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:06x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             4 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:08x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:08x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             5 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:10x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:10x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             6 => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:12x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:12x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
             _ => {
-                write!(
-                    f,
-                    "{:04x}:{addr_space}{:0x}{base_space}{:?}",
-                    self.address, self.code, self.instruction
+                format!(
+                    "{:04x}:{addr_space}{:0x}{base_space}{instruction}",
+                    self.address, self.code
                 )
             }
         }
@@ -97,12 +116,91 @@ pub struct Instruction {
 
 impl Debug for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.operand1 {
-            Some(operand1) => match &self.operand2 {
-                Some(operand2) => write!(f, "{:?} {:?}, {:?}", self.opcode, operand1, operand2),
-                None => write!(f, "{:?} {:?}", self.opcode, operand1),
+        f.write_str(&self.render(None))
+    }
+}
+
+impl Instruction {
+    // 既存VMの書式の命令．targetがあれば飛び先のアドレスの代わりに表示する
+    pub fn render(&self, target: Option<&str>) -> String {
+        let operand1 = match (&self.operand1, target) {
+            (Some(_), Some(label)) if self.target().is_some() => Some(label.to_owned()),
+            (operand, _) => operand.as_ref().map(|operand| format!("{operand:?}")),
+        };
+        match (operand1, &self.operand2) {
+            (Some(operand1), Some(operand2)) => {
+                format!("{:?} {operand1}, {operand2:?}", self.opcode)
+            }
+            (Some(operand1), None) => format!("{:?} {operand1}", self.opcode),
+            (None, _) => format!("{:?}", self.opcode),
+        }
+    }
+}
+
+// 命令の後に実行が進む先．飛び先がレジスタやメモリで決まる場合はNone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    // 次の命令へ進む
+    Next,
+    // 無条件のjmp
+    Jump(Option<u16>),
+    // 条件付きジャンプとloop．成立しなければ次の命令へ進む
+    Branch(u16),
+    // 戻ってきたら次の命令へ進む
+    Call(Option<u16>),
+    Return,
+    Halt,
+}
+
+impl Instruction {
+    // 直接指定された飛び先
+    pub fn target(&self) -> Option<u16> {
+        match (&self.opcode, &self.operand1) {
+            (
+                Opcode::CallWithinDirect
+                | Opcode::JmpDirectWithinSegment
+                | Opcode::JmpDirectWithinSegmentShort
+                | Opcode::Je
+                | Opcode::Jl
+                | Opcode::Jle
+                | Opcode::Jb
+                | Opcode::Jbe
+                | Opcode::Jp
+                | Opcode::Jo
+                | Opcode::Js
+                | Opcode::Jne
+                | Opcode::Jnl
+                | Opcode::Jnle
+                | Opcode::Jnb
+                | Opcode::Jnbe
+                | Opcode::Jnp
+                | Opcode::Jno
+                | Opcode::Jns
+                | Opcode::Loop
+                | Opcode::Loopz
+                | Opcode::Loopnz
+                | Opcode::Jcxz,
+                Some(Operand::Immediate(ImmediateValue::I16(target, _))),
+            ) => Some(*target as u16),
+            _ => None,
+        }
+    }
+
+    pub fn flow(&self) -> Flow {
+        match self.opcode {
+            Opcode::CallWithinDirect => Flow::Call(self.target()),
+            Opcode::JmpDirectWithinSegment
+            | Opcode::JmpDirectWithinSegmentShort
+            | Opcode::JmpIndirectWithinSegment => Flow::Jump(self.target()),
+            Opcode::RetWithinSegment
+            | Opcode::RetWithinSegAddingImmedToSp
+            | Opcode::RetIntersegment
+            | Opcode::RetIntersegmentAddingImmediateToSp => Flow::Return,
+            Opcode::Hlt => Flow::Halt,
+            _ => match self.target() {
+                Some(target) => Flow::Branch(target),
+                None => Flow::Next,
             },
-            None => write!(f, "{:?}", self.opcode),
         }
     }
 }
@@ -111,7 +209,7 @@ impl Debug for Instruction {
 impl Default for Instruction {
     fn default() -> Instruction {
         Instruction {
-            opcode: Opcode::NOP,
+            opcode: Opcode::Nop,
             operand1: None,
             operand2: None,
         }
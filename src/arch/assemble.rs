@@ -0,0 +1,1005 @@
+use super::{
+    asm::Instruction,
+    decode::Decoder,
+    encode::encode,
+    opcode::Opcode,
+    operand::{Disp, ImmediateValue, Operand, EA},
+    reg::{Reg16, Reg8, Register},
+};
+use std::collections::{HashMap, HashSet};
+
+// jmpの長さが決まるまで繰り返す回数の上限
+const MAX_PASSES: usize = 16;
+
+// 逆アセンブラと同じ構文のテキストを機械語にする．
+//
+//     start:  mov bx, [bx+si+4]   ; コメント
+//             jne start
+//     msg:    db "hello", a, 0
+//
+// 数値は逆アセンブラの表示と同じく16進数(0x接頭辞は任意)．
+// `0000: 31ed          xor bp, bp` のような逆アセンブル結果の行もそのまま受け付ける．
+//
+// `.text`，`.data`，`.bss`で以降の行を置くセクションを切り替える(省略時は`.text`)．
+// テキストは`base`から，データは0から，bssはデータの直後から配置される．
+// `.space n`はnバイトの0を置く．`.bss`に置けるのはラベルと`.space`だけ
+pub struct Program {
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    pub bss_size: u16,
+    // 定義された順のラベル
+    pub labels: Vec<Label>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub section: Section,
+    pub address: u16,
+}
+
+enum Item {
+    Instruction {
+        mnemonic: String,
+        size: Option<String>,
+        operands: Vec<String>,
+        // 逆アセンブル結果の行なら，そのときの機械語と命令の表示
+        listing: Option<(Vec<u8>, String)>,
+    },
+    Db(Vec<String>),
+    Dw(Vec<String>),
+    Space(String),
+    Section(Section),
+    // 逆アセンブル結果の(undefined)
+    Raw(Vec<u8>),
+}
+
+struct Line {
+    number: usize,
+    label: Option<String>,
+    item: Option<Item>,
+}
+
+enum Arg {
+    Reg(Register),
+    Mem(EA),
+    Imm(i32),
+}
+
+pub fn assemble(src: &str, base: u16) -> Result<Program, String> {
+    let lines = src
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(i + 1, line).map_err(|err| format!("line {}: {err}", i + 1)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut labels = HashMap::new();
+    for line in &lines {
+        if let Some(label) = &line.label {
+            if labels.insert(label.clone(), base).is_some() {
+                return Err(format!("line {}: duplicate label `{label}`", line.number));
+            }
+        }
+    }
+
+    // 前のパスのラベルのアドレスで全体を組み立て直し，アドレスが変わらなくなるまで繰り返す．
+    // 一度near jmpになったjmpはshortに戻さないので，必ず収束する
+    let mut near = HashSet::new();
+    for _ in 0..MAX_PASSES {
+        let mut section = Section::Text;
+        let mut text = Vec::new();
+        let mut data = Vec::new();
+        let mut bss_size = 0u16;
+        let mut ordered = Vec::new();
+        let mut error = None;
+        let grown = near.len();
+
+        for line in &lines {
+            let address = match section {
+                Section::Text => base.wrapping_add(text.len() as u16),
+                Section::Data => data.len() as u16,
+                // データの大きさが決まってから足す
+                Section::Bss => bss_size,
+            };
+            if let Some(label) = &line.label {
+                ordered.push(Label {
+                    name: label.clone(),
+                    section,
+                    address,
+                });
+            }
+            let Some(item) = &line.item else {
+                continue;
+            };
+            if let Item::Section(next) = item {
+                section = *next;
+                continue;
+            }
+
+            let ctx = Context {
+                labels: &labels,
+                address,
+                near: near.contains(&line.number),
+            };
+            let emitted = match (section, item) {
+                (Section::Bss, Item::Space(size)) => ctx.space(size).map(|code| (code, false)),
+                (Section::Bss, _) => Err("only labels and .space are allowed in .bss".to_owned()),
+                _ => ctx.emit(item),
+            };
+            match emitted {
+                Ok((code, long)) => {
+                    if long {
+                        near.insert(line.number);
+                    }
+                    match section {
+                        Section::Text => text.extend(code),
+                        Section::Data => data.extend(code),
+                        Section::Bss => bss_size = bss_size.wrapping_add(code.len() as u16),
+                    }
+                }
+                Err(err) => {
+                    error.get_or_insert(format!("line {}: {err}", line.number));
+                }
+            }
+        }
+
+        for label in &mut ordered {
+            if label.section == Section::Bss {
+                label.address = label.address.wrapping_add(data.len() as u16);
+            }
+        }
+        let next: HashMap<String, u16> = ordered
+            .iter()
+            .map(|label| (label.name.clone(), label.address))
+            .collect();
+
+        if next == labels && near.len() == grown {
+            return match error {
+                Some(err) => Err(err),
+                None => Ok(Program {
+                    text,
+                    data,
+                    bss_size,
+                    labels: ordered,
+                }),
+            };
+        }
+        labels = next;
+    }
+
+    Err("label addresses do not settle".to_owned())
+}
+
+fn parse_line(number: usize, line: &str) -> Result<Line, String> {
+    let mut line = strip_comment(line).trim();
+    let mut label = None;
+    let mut listing = None;
+
+    if let Some((code, rest)) = strip_listing(line) {
+        if rest == "(undefined)" {
+            return Ok(Line {
+                number,
+                label,
+                item: Some(Item::Raw(code)),
+            });
+        }
+        line = rest;
+        listing = Some((code, rest.to_owned()));
+    } else if let Some((name, rest)) = line.split_once(':') {
+        if is_identifier(name.trim()) {
+            label = Some(name.trim().to_owned());
+            line = rest.trim();
+        }
+    }
+
+    if line.is_empty() {
+        return Ok(Line {
+            number,
+            label,
+            item: None,
+        });
+    }
+
+    let (word, rest) = split_word(line);
+    let item = match word {
+        "db" => Item::Db(split_operands(rest)),
+        "dw" => Item::Dw(split_operands(rest)),
+        ".space" => Item::Space(rest.to_owned()),
+        ".text" | ".data" | ".bss" if rest.is_empty() => Item::Section(match word {
+            ".text" => Section::Text,
+            ".data" => Section::Data,
+            _ => Section::Bss,
+        }),
+        "(undefined)" => return Err("(undefined) needs the listing bytes, use db".to_owned()),
+        _ => {
+            let mut mnemonic = word.to_owned();
+            let mut rest = rest;
+
+            // rep movsb, jmp short, mov byte ...
+            let (next, after) = split_word(rest);
+            if mnemonic == "rep" || mnemonic == "repne" || mnemonic == "repz" {
+                mnemonic = format!("rep {next}");
+                rest = after;
+            }
+            let size = match next {
+                "byte" | "word" | "short" => {
+                    rest = after;
+                    Some(next.to_owned())
+                }
+                _ => None,
+            };
+
+            Item::Instruction {
+                mnemonic,
+                size,
+                operands: split_operands(rest),
+                listing,
+            }
+        }
+    };
+
+    Ok(Line {
+        number,
+        label,
+        item: Some(item),
+    })
+}
+
+// 文字列の中の;はコメントではない
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = None;
+    for (i, c) in line.char_indices() {
+        match (quoted, c) {
+            (None, ';') => return &line[..i],
+            (None, '"' | '\'') => quoted = Some(c),
+            (Some(q), c) if q == c => quoted = None,
+            _ => (),
+        }
+    }
+    line
+}
+
+// "0000: 31ed          xor bp, bp" -> ([0x31, 0xed], "xor bp, bp")
+fn strip_listing(line: &str) -> Option<(Vec<u8>, &str)> {
+    let (addr, rest) = line.split_once(':')?;
+    if addr.len() != 4 || !addr.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let (code, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    if code.len() % 2 != 0 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let code = (0..code.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&code[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    Some((code, rest.trim()))
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+// カンマで区切る．文字列の中のカンマでは区切らない
+fn split_operands(s: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quoted = None;
+
+    for c in s.chars() {
+        match (quoted, c) {
+            (None, ',') => {
+                operands.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            (None, '"' | '\'') => quoted = Some(c),
+            (Some(q), c) if q == c => quoted = None,
+            _ => (),
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_owned());
+    }
+    operands
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && register(s).is_none()
+}
+
+fn register(s: &str) -> Option<Register> {
+    let reg = match s {
+        "ax" => Register::Reg16(Reg16::AX),
+        "cx" => Register::Reg16(Reg16::CX),
+        "dx" => Register::Reg16(Reg16::DX),
+        "bx" => Register::Reg16(Reg16::BX),
+        "sp" => Register::Reg16(Reg16::SP),
+        "bp" => Register::Reg16(Reg16::BP),
+        "si" => Register::Reg16(Reg16::SI),
+        "di" => Register::Reg16(Reg16::DI),
+        "al" => Register::Reg8(Reg8::AL),
+        "cl" => Register::Reg8(Reg8::CL),
+        "dl" => Register::Reg8(Reg8::DL),
+        "bl" => Register::Reg8(Reg8::BL),
+        "ah" => Register::Reg8(Reg8::AH),
+        "ch" => Register::Reg8(Reg8::CH),
+        "dh" => Register::Reg8(Reg8::DH),
+        "bh" => Register::Reg8(Reg8::BH),
+        _ => return None,
+    };
+    Some(reg)
+}
+
+fn is_byte(reg: &Register) -> bool {
+    matches!(reg, Register::Reg8(_))
+}
+
+fn fits_i8(value: i32) -> bool {
+    (-128..=127).contains(&(value as i16))
+}
+
+fn byte(value: i32) -> Result<i8, String> {
+    match value {
+        -128..=255 => Ok(value as i8),
+        _ => Err(format!("{value:x} does not fit in a byte")),
+    }
+}
+
+fn word(value: i32) -> Result<i16, String> {
+    match value {
+        -32768..=65535 => Ok(value as i16),
+        _ => Err(format!("{value:x} does not fit in a word")),
+    }
+}
+
+fn ins(opcode: Opcode, operand1: Option<Operand>, operand2: Option<Operand>) -> Instruction {
+    Instruction {
+        opcode,
+        operand1,
+        operand2,
+    }
+}
+
+fn reg(reg: &Register) -> Option<Operand> {
+    Some(Operand::Register(reg.clone()))
+}
+
+fn mem(ea: &EA) -> Option<Operand> {
+    Some(Operand::EffectiveAddress(ea.clone()))
+}
+
+fn imm(value: ImmediateValue) -> Option<Operand> {
+    Some(Operand::Immediate(value))
+}
+
+struct Context<'a> {
+    labels: &'a HashMap<String, u16>,
+    address: u16,
+    // 前のパスでshortに収まらなかったjmp
+    near: bool,
+}
+
+impl Context<'_> {
+    // (機械語, near jmpにしたか)
+    fn emit(&self, item: &Item) -> Result<(Vec<u8>, bool), String> {
+        match item {
+            Item::Raw(code) => Ok((code.clone(), false)),
+            Item::Space(size) => Ok((self.space(size)?, false)),
+            Item::Section(_) => Ok((Vec::new(), false)),
+            Item::Db(values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    if let Some(s) = value.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        bytes.extend(unescape(s)?);
+                    } else {
+                        bytes.push(byte(self.value(value)?)? as u8);
+                    }
+                }
+                Ok((bytes, false))
+            }
+            Item::Dw(values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    bytes.extend(word(self.value(value)?)?.to_le_bytes());
+                }
+                Ok((bytes, false))
+            }
+            Item::Instruction {
+                mnemonic,
+                size,
+                operands,
+                listing,
+            } => {
+                // 書き換えられていない行は元の機械語のままにして，以降のアドレスを変えない
+                if let Some((code, text)) = listing {
+                    let mut padded = code.clone();
+                    padded.push(0x90);
+                    if let Some(asm) = Decoder::new(&padded, self.address).decode() {
+                        if asm.size == code.len() && format!("{:?}", asm.instruction) == *text {
+                            return Ok((code.clone(), false));
+                        }
+                    }
+                }
+
+                let args = operands
+                    .iter()
+                    .map(|operand| self.arg(operand))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (instruction, long) = self.lower(mnemonic, size.as_deref(), &args)?;
+                Ok((encode(&instruction, self.address)?, long))
+            }
+        }
+    }
+
+    fn space(&self, size: &str) -> Result<Vec<u8>, String> {
+        match self.value(size)? {
+            size @ 0..=0xffff => Ok(vec![0; size as usize]),
+            size => Err(format!("invalid .space size {size:x}")),
+        }
+    }
+
+    fn arg(&self, s: &str) -> Result<Arg, String> {
+        if let Some(reg) = register(s) {
+            return Ok(Arg::Reg(reg));
+        }
+
+        let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) else {
+            return Ok(Arg::Imm(self.value(s)?));
+        };
+
+        let mut regs = Vec::new();
+        let mut disp = 0i32;
+        for (negative, term) in terms(inner) {
+            match register(term) {
+                Some(Register::Reg16(r @ (Reg16::BX | Reg16::BP | Reg16::SI | Reg16::DI)))
+                    if !negative =>
+                {
+                    regs.push(r)
+                }
+                Some(_) => return Err(format!("invalid memory operand `{s}`")),
+                None => {
+                    let value = self.term(term)?;
+                    disp = disp.wrapping_add(if negative { -value } else { value });
+                }
+            }
+        }
+        regs.sort_by_key(|r| *r as u8);
+
+        let d = Disp(word(disp)? as isize);
+        let ea = match regs[..] {
+            [] => EA::DispOnly(Disp(word(disp)? as u16 as isize)),
+            [Reg16::BX, Reg16::SI] => EA::BxSi(d),
+            [Reg16::BX, Reg16::DI] => EA::BxDi(d),
+            [Reg16::BP, Reg16::SI] => EA::BpSi(d),
+            [Reg16::BP, Reg16::DI] => EA::BpDi(d),
+            [Reg16::SI] => EA::Si(d),
+            [Reg16::DI] => EA::Di(d),
+            [Reg16::BP] => EA::Bp(d),
+            [Reg16::BX] => EA::Bx(d),
+            _ => return Err(format!("invalid memory operand `{s}`")),
+        };
+        Ok(Arg::Mem(ea))
+    }
+
+    // ラベルと数値の和
+    fn value(&self, s: &str) -> Result<i32, String> {
+        let mut value = 0i32;
+        let mut empty = true;
+        for (negative, term) in terms(s) {
+            let term = self.term(term)?;
+            value = value.wrapping_add(if negative { -term } else { term });
+            empty = false;
+        }
+        if empty {
+            return Err("missing operand".to_owned());
+        }
+        Ok(value)
+    }
+
+    fn term(&self, s: &str) -> Result<i32, String> {
+        if let Some(&address) = self.labels.get(s) {
+            return Ok(address as i32);
+        }
+        if let Some(c) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+            return match unescape(c)?[..] {
+                [c] => Ok(c as i32),
+                _ => Err(format!("invalid character `{s}`")),
+            };
+        }
+
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        match i32::from_str_radix(digits, 16) {
+            Ok(value) => Ok(value),
+            Err(_) if is_identifier(s) => Err(format!("undefined label `{s}`")),
+            Err(_) => Err(format!("invalid number `{s}`")),
+        }
+    }
+
+    // 命令と，jmpをnearにしたか
+    fn lower(
+        &self,
+        mnemonic: &str,
+        size: Option<&str>,
+        args: &[Arg],
+    ) -> Result<(Instruction, bool), String> {
+        let byte_size = size == Some("byte");
+        if let (Some(opcode), [Arg::Imm(target)]) = (jump(mnemonic), args) {
+            let target = imm(ImmediateValue::I16(word(*target)?, 4));
+            return Ok((ins(opcode, target, None), false));
+        }
+
+        let instruction = match (mnemonic, args) {
+            ("mov", [a, b]) => self.mov(byte_size, a, b)?,
+            ("add" | "or" | "adc" | "sbb" | "and" | "sub" | "xor" | "cmp", [a, b]) => {
+                self.alu(mnemonic, byte_size, a, b)?
+            }
+            ("test", [a, b]) => self.test(size, a, b)?,
+            ("shl" | "sal" | "shr" | "sar" | "rol" | "ror" | "rcl" | "rcr", [a, b]) => {
+                let opcode = match mnemonic {
+                    "shl" | "sal" => Opcode::Shl,
+                    "shr" => Opcode::Shr,
+                    "sar" => Opcode::Sar,
+                    "rol" => Opcode::Rol,
+                    "ror" => Opcode::Ror,
+                    "rcl" => Opcode::Rcl,
+                    _ => Opcode::Rcr,
+                };
+                let count = match b {
+                    Arg::Reg(Register::Reg8(Reg8::CL)) => reg(&Register::Reg8(Reg8::CL)),
+                    Arg::Imm(1) => imm(ImmediateValue::I8(1, 2)),
+                    _ => return Err("shift count must be 1 or cl".to_owned()),
+                };
+                ins(opcode, self.register_memory(byte_size, a)?, count)
+            }
+            ("not" | "neg" | "mul" | "imul" | "div" | "idiv", [a]) => {
+                let opcode = match mnemonic {
+                    "not" => Opcode::Not,
+                    "neg" => Opcode::Neg,
+                    "mul" => Opcode::Mul,
+                    "imul" => Opcode::Imul,
+                    "div" => Opcode::Div,
+                    _ => Opcode::Idiv,
+                };
+                ins(opcode, self.register_memory(byte_size, a)?, None)
+            }
+            ("inc" | "dec", [a]) => {
+                let (register, memory) = if mnemonic == "inc" {
+                    (Opcode::IncRegister, Opcode::IncRegisterMemory)
+                } else {
+                    (Opcode::DecRegister, Opcode::DecRegisterMemory)
+                };
+                match a {
+                    Arg::Reg(r @ Register::Reg16(_)) => ins(register, reg(r), None),
+                    _ => ins(memory, self.register_memory(byte_size, a)?, None),
+                }
+            }
+            ("push" | "pop", [a]) => {
+                let (register, memory) = if mnemonic == "push" {
+                    (Opcode::PushReg, Opcode::PushRegMem)
+                } else {
+                    (Opcode::PopReg, Opcode::PopRegMem)
+                };
+                match a {
+                    Arg::Reg(r) => ins(register, reg(r), None),
+                    Arg::Mem(ea) => ins(memory, mem(ea), None),
+                    Arg::Imm(_) => return Err(format!("{mnemonic} needs a register or memory")),
+                }
+            }
+            ("xchg", [a, b]) => {
+                let ax = Register::Reg16(Reg16::AX);
+                match (a, b) {
+                    (Arg::Reg(r @ Register::Reg16(_)), Arg::Reg(s)) if *s == ax => {
+                        ins(Opcode::XchgRegisterWithAccumulator, reg(r), reg(&ax))
+                    }
+                    (Arg::Reg(r), Arg::Reg(s @ Register::Reg16(_))) if *r == ax => {
+                        ins(Opcode::XchgRegisterWithAccumulator, reg(s), reg(&ax))
+                    }
+                    (Arg::Reg(r), Arg::Mem(ea)) | (Arg::Mem(ea), Arg::Reg(r)) => {
+                        ins(Opcode::XchgRegisterMemoryWithRegister, mem(ea), reg(r))
+                    }
+                    (Arg::Reg(r), Arg::Reg(s)) => {
+                        ins(Opcode::XchgRegisterMemoryWithRegister, reg(r), reg(s))
+                    }
+                    _ => return Err("xchg needs registers or memory".to_owned()),
+                }
+            }
+            ("nop", []) => ins(
+                Opcode::XchgRegisterWithAccumulator,
+                reg(&Register::Reg16(Reg16::AX)),
+                reg(&Register::Reg16(Reg16::AX)),
+            ),
+            ("lea", [Arg::Reg(r), Arg::Mem(ea)]) => ins(Opcode::Lea, reg(r), mem(ea)),
+            ("call", [Arg::Imm(target)]) => ins(
+                Opcode::CallWithinDirect,
+                imm(ImmediateValue::I16(word(*target)?, 4)),
+                None,
+            ),
+            ("call", [a]) => ins(
+                Opcode::CallWithinDirect,
+                self.register_memory(false, a)?,
+                None,
+            ),
+            ("jmp", [Arg::Imm(target)]) => {
+                let target = word(*target)?;
+                // 次の命令からの距離が1byteに収まればshort
+                let disp = (target as u16).wrapping_sub(self.address.wrapping_add(2)) as i16;
+                let long = size != Some("short") && (self.near || !fits_i8(disp as i32));
+                let opcode = if long {
+                    Opcode::JmpDirectWithinSegment
+                } else {
+                    Opcode::JmpDirectWithinSegmentShort
+                };
+                return Ok((ins(opcode, imm(ImmediateValue::I16(target, 4)), None), long));
+            }
+            ("jmp", [a]) => ins(
+                Opcode::JmpIndirectWithinSegment,
+                self.register_memory(false, a)?,
+                None,
+            ),
+            ("ret", []) => ins(Opcode::RetWithinSegment, None, None),
+            ("ret", [Arg::Imm(value)]) => ins(
+                Opcode::RetWithinSegAddingImmedToSp,
+                imm(ImmediateValue::I16(word(*value)?, 4)),
+                None,
+            ),
+            ("int", [Arg::Imm(value)]) => ins(
+                Opcode::IntTypeSpecified,
+                imm(ImmediateValue::I8(byte(*value)?, 2)),
+                None,
+            ),
+            ("in" | "out", [a, b]) => {
+                let (acc, port) = match (a, b) {
+                    (Arg::Reg(r), port)
+                        if matches!(r, Register::Reg16(Reg16::AX) | Register::Reg8(Reg8::AL)) =>
+                    {
+                        (r, port)
+                    }
+                    (port, Arg::Reg(r)) if mnemonic == "out" => (r, port),
+                    _ => return Err(format!("{mnemonic} needs al or ax")),
+                };
+                let (fixed, variable) = if mnemonic == "in" {
+                    (Opcode::InFixedPort, Opcode::InVariablePort)
+                } else {
+                    (Opcode::OutFixedPort, Opcode::OutVariablePort)
+                };
+                match port {
+                    Arg::Imm(port) => {
+                        ins(fixed, reg(acc), imm(ImmediateValue::I8(byte(*port)?, 2)))
+                    }
+                    Arg::Reg(dx @ Register::Reg16(Reg16::DX)) => ins(variable, reg(acc), reg(dx)),
+                    _ => return Err(format!("{mnemonic} needs an immediate port or dx")),
+                }
+            }
+            (_, []) => {
+                let opcode = match mnemonic {
+                    "clc" => Opcode::Clc,
+                    "cmc" => Opcode::Cmc,
+                    "cld" => Opcode::Cld,
+                    "std" => Opcode::Std,
+                    "cli" => Opcode::Cli,
+                    "sti" => Opcode::Sti,
+                    "hlt" => Opcode::Hlt,
+                    "cbw" => Opcode::Cbw,
+                    "cwd" => Opcode::Cwd,
+                    "cmpsb" => Opcode::CompsByte,
+                    "rep movsb" => Opcode::RepMovsb,
+                    "rep movsw" => Opcode::RepMovsw,
+                    "rep stosb" => Opcode::RepStosb,
+                    "rep scasb" => Opcode::RepScasb,
+                    _ => return Err(format!("unknown instruction `{mnemonic}`")),
+                };
+                ins(opcode, None, None)
+            }
+            _ => return Err(format!("invalid operands for `{mnemonic}`")),
+        };
+
+        Ok((instruction, false))
+    }
+
+    fn mov(&self, byte_size: bool, a: &Arg, b: &Arg) -> Result<Instruction, String> {
+        Ok(match (a, b) {
+            // mov byte al, 5 のようにサイズを明示したときはr/m形式にする
+            (Arg::Reg(r), Arg::Imm(value)) if !byte_size => {
+                let value = if is_byte(r) {
+                    byte(*value)? as u8 as i32
+                } else {
+                    word(*value)? as u16 as i32
+                };
+                ins(
+                    Opcode::MovImmediate,
+                    reg(r),
+                    imm(ImmediateValue::I32(value, 4)),
+                )
+            }
+            (Arg::Reg(Register::Reg16(Reg16::AX)), Arg::Mem(ea @ EA::DispOnly(_))) => ins(
+                Opcode::MovMemoryToAccumulator,
+                reg(&Register::Reg16(Reg16::AX)),
+                mem(ea),
+            ),
+            (Arg::Reg(r), Arg::Mem(ea)) => ins(Opcode::MovRmToFromReg, reg(r), mem(ea)),
+            (Arg::Mem(ea), Arg::Reg(r)) => ins(Opcode::MovRmToFromReg, mem(ea), reg(r)),
+            (Arg::Reg(r), Arg::Reg(s)) => ins(Opcode::MovRmToFromReg, reg(r), reg(s)),
+            (a, Arg::Imm(value)) if byte_size => ins(
+                Opcode::MovImmediateRegisterMemoryByte,
+                self.register_memory(false, a)?,
+                imm(ImmediateValue::I8(byte(*value)?, 2)),
+            ),
+            (Arg::Mem(ea), Arg::Imm(value)) => ins(
+                Opcode::MovImmediateRegisterMemory,
+                mem(ea),
+                imm(ImmediateValue::I16(word(*value)?, 4)),
+            ),
+            _ => return Err("invalid operands for `mov`".to_owned()),
+        })
+    }
+
+    fn alu(
+        &self,
+        mnemonic: &str,
+        byte_size: bool,
+        a: &Arg,
+        b: &Arg,
+    ) -> Result<Instruction, String> {
+        // (reg either, immediate to r/m, immediate to accumulator)
+        // デコーダが返すOpcodeに合わせる．xor immediateとadc/sbbのaccumulator形式はデコードできない
+        let (either, immediate, accumulator) = match mnemonic {
+            "add" => (
+                Opcode::AddRegEither,
+                Some(Opcode::AddImmediateRegisterMemory),
+                Some(Opcode::AddImmediateToAccumulator),
+            ),
+            "or" => (
+                Opcode::OrRegEither,
+                Some(Opcode::OrImmediateRegisterMemory),
+                Some(Opcode::OrImmediateFromAccumulator),
+            ),
+            "adc" => (
+                Opcode::AdcRegEither,
+                Some(Opcode::AdcImmediateRegisterMemory),
+                None,
+            ),
+            "sbb" => (
+                Opcode::SsbImmediateRegisterMemory,
+                Some(Opcode::SsbImmediateRegisterMemory),
+                None,
+            ),
+            "and" => (
+                Opcode::AndRegEither,
+                Some(Opcode::AndImmediateRegisterMemory),
+                Some(Opcode::AndImmediateFromAccumulator),
+            ),
+            "sub" => (
+                Opcode::SubRegEither,
+                Some(Opcode::SubImmediateRegisterMemory),
+                Some(Opcode::SubImmediateFromAccumulator),
+            ),
+            "xor" => (Opcode::XorRegEither, None, None),
+            _ => (
+                Opcode::CmpRegEither,
+                Some(Opcode::CmpImmediateWord),
+                Some(Opcode::CmpImmediateFromAccumulator),
+            ),
+        };
+
+        let value = match (a, b) {
+            (Arg::Reg(r), Arg::Mem(ea)) => return Ok(ins(either, reg(r), mem(ea))),
+            (Arg::Mem(ea), Arg::Reg(r)) => return Ok(ins(either, mem(ea), reg(r))),
+            (Arg::Reg(r), Arg::Reg(s)) => return Ok(ins(either, reg(r), reg(s))),
+            (_, Arg::Imm(value)) => *value,
+            _ => return Err(format!("invalid operands for `{mnemonic}`")),
+        };
+        let Some(immediate) = immediate else {
+            return Err(format!("`{mnemonic}` with an immediate is not supported"));
+        };
+
+        Ok(match a {
+            Arg::Reg(r) if is_byte(r) => {
+                let value = imm(ImmediateValue::I8(byte(value)?, 2));
+                match accumulator {
+                    Some(acc) if *r == Register::Reg8(Reg8::AL) => ins(acc, reg(r), value),
+                    _ => ins(immediate, reg(r), value),
+                }
+            }
+            Arg::Reg(r) => {
+                let value = word(value)?;
+                match accumulator {
+                    _ if fits_i8(value as i32) => {
+                        ins(immediate, reg(r), imm(ImmediateValue::I16(value, 1)))
+                    }
+                    Some(acc) if *r == Register::Reg16(Reg16::AX) => {
+                        ins(acc, reg(r), imm(ImmediateValue::I16(value, 4)))
+                    }
+                    _ => ins(immediate, reg(r), imm(ImmediateValue::I16(value, 4))),
+                }
+            }
+            Arg::Mem(ea) if byte_size => {
+                // 直接アドレスではbyteとwordが同じInstructionになり，区別できるのはcmpだけ
+                let opcode = match immediate {
+                    Opcode::CmpImmediateWord => Opcode::CmpImmediateByte,
+                    _ if matches!(ea, EA::DispOnly(_)) => {
+                        return Err(format!(
+                            "`{mnemonic} byte` on a direct address is not supported"
+                        ))
+                    }
+                    opcode => opcode,
+                };
+                ins(opcode, mem(ea), imm(ImmediateValue::I8(byte(value)?, 2)))
+            }
+            Arg::Mem(ea) => {
+                let value = word(value)?;
+                let value = match ea {
+                    EA::DispOnly(_) if fits_i8(value as i32) => ImmediateValue::I8(value as i8, 2),
+                    _ if fits_i8(value as i32) => ImmediateValue::I16(value, 1),
+                    _ => ImmediateValue::I16(value, 4),
+                };
+                ins(immediate, mem(ea), imm(value))
+            }
+            Arg::Imm(_) => return Err(format!("invalid operands for `{mnemonic}`")),
+        })
+    }
+
+    fn test(&self, size: Option<&str>, a: &Arg, b: &Arg) -> Result<Instruction, String> {
+        let value = match (a, b) {
+            (Arg::Reg(r), Arg::Mem(ea)) | (Arg::Mem(ea), Arg::Reg(r)) => {
+                return Ok(ins(Opcode::TestRegisterMemoryAndRegister, mem(ea), reg(r)))
+            }
+            (Arg::Reg(r), Arg::Reg(s)) => {
+                return Ok(ins(Opcode::TestRegisterMemoryAndRegister, reg(r), reg(s)))
+            }
+            (_, Arg::Imm(value)) => *value,
+            _ => return Err("invalid operands for `test`".to_owned()),
+        };
+
+        Ok(match a {
+            Arg::Reg(r @ (Register::Reg8(Reg8::AL) | Register::Reg16(Reg16::AX)))
+                if size.is_none() =>
+            {
+                let value = if is_byte(r) {
+                    ImmediateValue::I8(byte(value)?, 2)
+                } else {
+                    ImmediateValue::I16(word(value)?, 4)
+                };
+                ins(Opcode::TestImmediateDataAndAccumulator, reg(r), imm(value))
+            }
+            Arg::Reg(r) if is_byte(r) => ins(
+                Opcode::TestImmediate,
+                reg(r),
+                imm(ImmediateValue::I8(byte(value)?, 2)),
+            ),
+            Arg::Mem(ea) if size == Some("byte") => {
+                // byteでdisp8を使う形式だけがtest byteと表示される
+                let disp8 = match ea {
+                    EA::DispOnly(_) => false,
+                    EA::Bp(Disp(d)) => fits_i8(*d as i32),
+                    EA::BxSi(Disp(d))
+                    | EA::BxDi(Disp(d))
+                    | EA::BpSi(Disp(d))
+                    | EA::BpDi(Disp(d))
+                    | EA::Si(Disp(d))
+                    | EA::Di(Disp(d))
+                    | EA::Bx(Disp(d)) => *d != 0 && fits_i8(*d as i32),
+                };
+                let opcode = if disp8 {
+                    Opcode::TestImmediateByte
+                } else {
+                    Opcode::TestImmediate
+                };
+                ins(opcode, mem(ea), imm(ImmediateValue::I8(byte(value)?, 2)))
+            }
+            Arg::Reg(_) | Arg::Mem(_) => ins(
+                Opcode::TestImmediate,
+                self.register_memory(false, a)?,
+                imm(ImmediateValue::I16(word(value)?, 4)),
+            ),
+            Arg::Imm(_) => return Err("invalid operands for `test`".to_owned()),
+        })
+    }
+
+    // メモリのbyte/wordはInstructionに残らず，VMはwordとして扱うのでbyteは受け付けない
+    fn register_memory(&self, byte_size: bool, arg: &Arg) -> Result<Option<Operand>, String> {
+        match arg {
+            Arg::Reg(r) => Ok(reg(r)),
+            Arg::Mem(_) if byte_size => {
+                Err("byte-sized memory operands are not supported here".to_owned())
+            }
+            Arg::Mem(ea) => Ok(mem(ea)),
+            Arg::Imm(_) => Err("expected a register or memory operand".to_owned()),
+        }
+    }
+}
+
+fn jump(mnemonic: &str) -> Option<Opcode> {
+    let opcode = match mnemonic {
+        "je" | "jz" => Opcode::Je,
+        "jne" | "jnz" => Opcode::Jne,
+        "jl" | "jnge" => Opcode::Jl,
+        "jnl" | "jge" => Opcode::Jnl,
+        "jle" | "jng" => Opcode::Jle,
+        "jnle" | "jg" => Opcode::Jnle,
+        "jb" | "jc" | "jnae" => Opcode::Jb,
+        "jnb" | "jnc" | "jae" => Opcode::Jnb,
+        "jbe" | "jna" => Opcode::Jbe,
+        "jnbe" | "ja" => Opcode::Jnbe,
+        "jp" | "jpe" => Opcode::Jp,
+        "jnp" | "jpo" => Opcode::Jnp,
+        "jo" => Opcode::Jo,
+        "jno" => Opcode::Jno,
+        "js" => Opcode::Js,
+        "jns" => Opcode::Jns,
+        "loop" => Opcode::Loop,
+        "loopz" | "loope" => Opcode::Loopz,
+        "loopnz" | "loopne" => Opcode::Loopnz,
+        "jcxz" => Opcode::Jcxz,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+// "bx+si-4" -> [(false, "bx"), (false, "si"), (true, "4")]
+fn terms(s: &str) -> Vec<(bool, &str)> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    let mut quoted = false;
+
+    for (i, c) in s.char_indices() {
+        if c == '\'' {
+            quoted = !quoted;
+        }
+        if !quoted && (c == '+' || c == '-') {
+            let term = s[start..i].trim();
+            if !term.is_empty() {
+                terms.push((negative, term));
+            }
+            negative = c == '-';
+            start = i + 1;
+        }
+    }
+    let term = s[start..].trim();
+    if !term.is_empty() {
+        terms.push((negative, term));
+    }
+    terms
+}
+
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"' | '\'')) => c,
+                _ => return Err(format!("invalid escape in `{s}`")),
+            },
+            c => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+    }
+    Ok(bytes)
+}
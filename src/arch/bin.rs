@@ -1,7 +1,6 @@
 use crate::arch::bin;
 use std::iter::Peekable;
 use std::mem::size_of_val;
-use std::u8;
 use std::vec::IntoIter;
 
 pub struct BinaryManager<I: Iterator> {
@@ -10,7 +9,9 @@ pub struct BinaryManager<I: Iterator> {
     text: Option<Text>,
     data: Option<Data>,
     bss: Option<Bss>,
+    symbols: Option<SymbolTable>,
     pointer: usize,
+    #[allow(dead_code)]
     text_pointer: usize,
 }
 
@@ -22,6 +23,7 @@ impl BinaryManager<IntoIter<u8>> {
             text: None,
             data: None,
             bss: None,
+            symbols: None,
             pointer: 0,
             text_pointer: 0,
         };
@@ -34,6 +36,9 @@ impl BinaryManager<IntoIter<u8>> {
     pub fn parse(&mut self) -> Option<()> {
         self.header = self.make_header();
         self.text = self.make_text();
+        self.data = self.make_data();
+        self.bss = self.make_bss();
+        self.symbols = self.make_symbols();
 
         Some(())
     }
@@ -56,7 +61,7 @@ impl BinaryManager<IntoIter<u8>> {
     }
 
     pub fn make_text(&mut self) -> Option<Text> {
-        let size = self.header.as_ref()?.text_size as u32;
+        let size = self.header.as_ref()?.text_size;
 
         let mut text = Vec::new();
 
@@ -69,6 +74,83 @@ impl BinaryManager<IntoIter<u8>> {
         Some(x)
     }
 
+    // データセグメントの初期値はテキストの直後に置かれている
+    pub fn make_data(&mut self) -> Option<Data> {
+        let header = self.header.as_ref()?;
+        let size = header.data_size;
+        let offset = self.pointer as u32;
+
+        let mut data = Vec::new();
+
+        for _ in 0..size {
+            data.push(self.consume_u8()?);
+        }
+
+        Some(Data {
+            data,
+            offset,
+            user_size: size,
+            all_size: size,
+        })
+    }
+
+    // bssはファイルには含まれず，データセグメントのデータの直後に0で確保される
+    pub fn make_bss(&mut self) -> Option<Bss> {
+        let header = self.header.as_ref()?;
+        let offset = header.data_size;
+        let size = header.bss_size;
+
+        Some(Bss {
+            data: Vec::new(),
+            offset,
+            user_size: size,
+            all_size: header.total.saturating_sub(offset),
+        })
+    }
+
+    // シンボルテーブルはデータセグメントの直後に置かれている
+    pub fn make_symbols(&mut self) -> Option<SymbolTable> {
+        let header = self.header.as_ref()?;
+        let syms_size = header.syms as usize;
+
+        let mut bytes = Vec::new();
+
+        for _ in 0..syms_size {
+            bytes.push(self.consume_u8()?);
+        }
+
+        Some(SymbolTable::parse(&bytes))
+    }
+
+    pub fn get_header(&self) -> Option<&AOutHeader> {
+        self.header.as_ref()
+    }
+
+    // ヘッダがあり，テキストとデータがファイルに収まっているか
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.header, &self.text, &self.data) {
+            (None, _, _) => Err("not a MINIX a.out file".to_owned()),
+            (Some(_), Some(_), Some(_)) => Ok(()),
+            _ => Err("truncated a.out file (the text or data runs past the end)".to_owned()),
+        }
+    }
+
+    pub fn get_text(&self) -> &[u8] {
+        self.text.as_ref().map_or(&[], |t| t.text.as_slice())
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        self.data.as_ref().map_or(&[], |d| d.data.as_slice())
+    }
+
+    pub fn get_bss(&self) -> Option<&Bss> {
+        self.bss.as_ref()
+    }
+
+    pub fn get_symbols(&self) -> SymbolTable {
+        self.symbols.clone().unwrap_or_default()
+    }
+
     pub fn get_header_size(&self) -> usize {
         if let Some(val) = self.header.as_ref() {
             size_of_val(val)
@@ -121,6 +203,7 @@ impl BinaryConsume for BinaryManager<IntoIter<u8>> {
     }
 }
 use super::header::{AOutHeader, Bss, Data, Text};
+use super::symbol::SymbolTable;
 
 pub trait BinaryConsume {
     fn consume_u8(&mut self) -> Option<u8>;
@@ -130,7 +213,9 @@ pub trait BinaryConsume {
 
 pub trait BinaryPeek {
     fn peek_u8(&mut self) -> Option<u8>;
+    #[allow(dead_code)]
     fn peek_u16(&mut self) -> Option<u16>;
+    #[allow(dead_code)]
     fn peek_u32(&mut self) -> Option<u32>;
     fn peek_offset(&mut self, offset: usize) -> Option<u8>;
 }
@@ -147,6 +232,7 @@ pub fn bytes_to_16bit_little_endian(bytes: &[u8]) -> u16 {
     (bytes[1] as u16) << 8 | bytes[0] as u16
 }
 
+#[allow(dead_code)]
 pub fn bytes_to_16bit_big_endian(bytes: &[u8]) -> u16 {
     (bytes[0] as u16) << 8 | bytes[1] as u16
 }
@@ -0,0 +1,251 @@
+use super::data::quote;
+use std::io::Write;
+use Type::*;
+
+// 引数の型がわからない関数で表示するスタックのワード数の既定値
+pub const DEFAULT_ARGS: usize = 3;
+// 文字列の引数として表示する最大のバイト数
+const MAX_STRING: usize = 32;
+
+// cdeclの引数と戻り値の型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    Unsigned,
+    Char,
+    Ptr,
+    Str,
+    // 2ワード．戻り値はDX:AX
+    Long,
+    Void,
+}
+
+// 可変長引数の型の決め方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rest {
+    None,
+    // 最後の固定引数の書式の変換指定に従う．scanfはすべてポインタ
+    Printf,
+    Scanf,
+}
+
+// 引数を文字列として表示するlibcの関数．シンボル名は先頭の_を除き，8文字で切れる
+pub struct Signature {
+    pub name: &'static str,
+    pub args: &'static [Type],
+    pub rest: Rest,
+    pub ret: Type,
+}
+
+const fn sig(name: &'static str, args: &'static [Type], rest: Rest, ret: Type) -> Signature {
+    Signature {
+        name,
+        args,
+        rest,
+        ret,
+    }
+}
+
+pub const SIGNATURES: [Signature; 34] = [
+    sig("printf", &[Str], Rest::Printf, Int),
+    sig("fprintf", &[Ptr, Str], Rest::Printf, Int),
+    sig("sprintf", &[Ptr, Str], Rest::Printf, Int),
+    sig("scanf", &[Str], Rest::Scanf, Int),
+    sig("sscanf", &[Str, Str], Rest::Scanf, Int),
+    sig("puts", &[Str], Rest::None, Int),
+    sig("fputs", &[Str, Ptr], Rest::None, Int),
+    sig("putchar", &[Char], Rest::None, Int),
+    sig("getchar", &[], Rest::None, Int),
+    sig("gets", &[Ptr], Rest::None, Ptr),
+    sig("fgets", &[Ptr, Int, Ptr], Rest::None, Ptr),
+    sig("fopen", &[Str, Str], Rest::None, Ptr),
+    sig("fclose", &[Ptr], Rest::None, Int),
+    sig("fflush", &[Ptr], Rest::None, Int),
+    sig("strcpy", &[Ptr, Str], Rest::None, Ptr),
+    sig("strncpy", &[Ptr, Str, Unsigned], Rest::None, Ptr),
+    sig("strcat", &[Ptr, Str], Rest::None, Ptr),
+    sig("strcmp", &[Str, Str], Rest::None, Int),
+    sig("strncmp", &[Str, Str, Unsigned], Rest::None, Int),
+    sig("strlen", &[Str], Rest::None, Unsigned),
+    sig("strchr", &[Str, Char], Rest::None, Ptr),
+    sig("memcpy", &[Ptr, Ptr, Unsigned], Rest::None, Ptr),
+    sig("memset", &[Ptr, Char, Unsigned], Rest::None, Ptr),
+    sig("malloc", &[Unsigned], Rest::None, Ptr),
+    sig("calloc", &[Unsigned, Unsigned], Rest::None, Ptr),
+    sig("realloc", &[Ptr, Unsigned], Rest::None, Ptr),
+    sig("free", &[Ptr], Rest::None, Void),
+    sig("atoi", &[Str], Rest::None, Int),
+    sig("atol", &[Str], Rest::None, Long),
+    sig("exit", &[Int], Rest::None, Void),
+    sig("open", &[Str, Int], Rest::None, Int),
+    sig("creat", &[Str, Int], Rest::None, Int),
+    sig("read", &[Int, Ptr, Unsigned], Rest::None, Int),
+    sig("write", &[Int, Ptr, Unsigned], Rest::None, Int),
+];
+
+// "_printf"のシンボル名から
+pub fn signature(symbol: &str) -> Option<&'static Signature> {
+    let name = symbol.strip_prefix('_')?;
+    SIGNATURES.iter().find(|s| s.name == name)
+}
+
+// データセグメントのワード
+fn word(memory: &[u8], addr: u16) -> u16 {
+    let lo = memory[addr as usize];
+    let hi = memory[addr.wrapping_add(1) as usize];
+    u16::from_le_bytes([lo, hi])
+}
+
+// NULで終わる文字列．長いものは切って...を付ける
+pub fn string(memory: &[u8], addr: u16) -> String {
+    if addr == 0 {
+        return "NULL".to_owned();
+    }
+    let bytes: Vec<u8> = (0..=MAX_STRING as u16)
+        .map(|i| memory[addr.wrapping_add(i) as usize])
+        .take_while(|&b| b != 0)
+        .collect();
+    match bytes.len() > MAX_STRING {
+        true => format!("{}...", quote(&bytes[..MAX_STRING])),
+        false => quote(&bytes),
+    }
+}
+
+fn value(ty: Type, lo: u16, hi: u16, memory: &[u8]) -> String {
+    match ty {
+        Int => (lo as i16).to_string(),
+        Unsigned => lo.to_string(),
+        Char => match lo as u8 {
+            c @ 0x20..=0x7e => format!("'{}'", c as char),
+            c => format!("'\\x{c:02x}'"),
+        },
+        Ptr if lo == 0 => "NULL".to_owned(),
+        Ptr => format!("0x{lo:04x}"),
+        Str => string(memory, lo),
+        Long => (((hi as u32) << 16 | lo as u32) as i32).to_string(),
+        Void => String::new(),
+    }
+}
+
+// --call-traceの出力．呼び出しの深さだけ字下げする
+pub struct CallTrace {
+    out: Box<dyn Write>,
+    // 引数の数がわからない関数で表示するワード数
+    args: usize,
+}
+
+impl CallTrace {
+    pub fn new(out: Box<dyn Write>, args: usize) -> Self {
+        CallTrace { out, args }
+    }
+
+    // callの直後．depthは呼び出し元の深さ，spは戻りアドレスを指す
+    pub fn call(&mut self, name: &str, depth: usize, sp: u16, memory: &[u8]) {
+        let line = format!(
+            "{:indent$}{name}({})",
+            "",
+            arguments(name, sp.wrapping_add(2), memory, self.args),
+            indent = depth * 2
+        );
+        let _ = writeln!(self.out, "{line}");
+    }
+
+    // retの直後．depthは戻った先の深さ
+    pub fn ret(&mut self, name: &str, depth: usize, ax: u16, dx: u16, memory: &[u8]) {
+        let result = match signature(name).map(|s| s.ret) {
+            Some(Void) => "void".to_owned(),
+            Some(ty) => value(ty, ax, dx, memory),
+            None => format!("0x{ax:04x}"),
+        };
+        let _ = writeln!(
+            self.out,
+            "{:indent$}{name} = {result}",
+            "",
+            indent = depth * 2
+        );
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+// printfの書式の変換指定から可変長引数の型を決める
+pub fn format_types(format: &[u8], scanf: bool) -> Vec<Type> {
+    let mut types = Vec::new();
+    let mut chars = format.iter().copied();
+    while let Some(c) = chars.next() {
+        if c != b'%' {
+            continue;
+        }
+        let mut long = false;
+        for c in chars.by_ref() {
+            let ty = match c {
+                b'-' | b'+' | b' ' | b'#' | b'.' | b'0'..=b'9' => continue,
+                b'*' => {
+                    types.push(Int);
+                    continue;
+                }
+                b'l' => {
+                    long = true;
+                    continue;
+                }
+                b'%' => None,
+                _ if scanf => Some(Ptr),
+                b'd' | b'i' if long => Some(Long),
+                b'd' | b'i' => Some(Int),
+                b'u' | b'o' | b'x' | b'X' if long => Some(Long),
+                b'u' | b'o' | b'x' | b'X' => Some(Unsigned),
+                b'c' => Some(Char),
+                b's' => Some(Str),
+                _ => Some(Ptr),
+            };
+            types.extend(ty);
+            break;
+        }
+    }
+    types
+}
+
+// addrから積まれた引数．既知の関数は型に従い，それ以外はwords個のワードを16進数で並べる
+pub fn arguments(name: &str, addr: u16, memory: &[u8], words: usize) -> String {
+    let mut args = Vec::new();
+    let mut addr = addr;
+    let mut next = || {
+        let w = word(memory, addr);
+        addr = addr.wrapping_add(2);
+        w
+    };
+
+    let Some(signature) = signature(name) else {
+        return (0..words)
+            .map(|_| format!("0x{:04x}", next()))
+            .collect::<Vec<_>>()
+            .join(", ");
+    };
+    let mut format = 0;
+    for &ty in signature.args {
+        let lo = next();
+        let hi = if ty == Long { next() } else { 0 };
+        if ty == Str {
+            format = lo;
+        }
+        args.push(value(ty, lo, hi, memory));
+    }
+
+    let format = || {
+        let bytes = memory[format as usize..].iter();
+        bytes.copied().take_while(|&b| b != 0).collect::<Vec<u8>>()
+    };
+    let rest = match signature.rest {
+        Rest::None => Vec::new(),
+        Rest::Printf => format_types(&format(), false),
+        Rest::Scanf => format_types(&format(), true),
+    };
+    for ty in rest {
+        let lo = next();
+        let hi = if ty == Long { next() } else { 0 };
+        args.push(value(ty, lo, hi, memory));
+    }
+    args.join(", ")
+}
@@ -0,0 +1,219 @@
+use super::trace::{registers_json, Registers, REGISTERS};
+use crate::json::Json;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+// 繰り返しとみなす命令列の最大の長さ
+const MAX_PERIOD: usize = 16;
+// 最初の1回の後にこの回数以上繰り返したら1行にまとめる
+const MIN_REPEATS: u64 = 2;
+
+// トレースの1命令分．itemはテキストの行かRecord
+pub struct Step<T> {
+    pub step: u64,
+    pub ip: u16,
+    pub after: Registers,
+    pub item: T,
+}
+
+// まとめた繰り返し．最初の1回はそのまま出力し，残りをこれで表す
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub body: Vec<u16>,
+    pub repeats: u64,
+    pub first_step: u64,
+    pub last_step: u64,
+    // 最初の1回の後と，最後の繰り返しの後のレジスタ
+    pub start: Registers,
+    pub end: Registers,
+}
+
+impl Loop {
+    // 変化したレジスタと差分
+    pub fn deltas(&self) -> Vec<(&'static str, i16)> {
+        REGISTERS
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name, self.end.reg[i].wrapping_sub(self.start.reg[i]) as i16))
+            .filter(|&(_, delta)| delta != 0)
+            .collect()
+    }
+
+    pub fn render(&self) -> String {
+        let body: Vec<String> = self.body.iter().map(|ip| format!("{ip:04x}")).collect();
+        let mut out = format!(
+            "; loop {} repeated {} more times (steps {}-{})",
+            body.join(" "),
+            self.repeats,
+            self.first_step,
+            self.last_step
+        );
+
+        let deltas = self.deltas();
+        if !deltas.is_empty() || self.start.flags != self.end.flags {
+            out.push(':');
+        }
+        for (name, delta) in deltas {
+            let i = REGISTERS.iter().position(|&r| r == name).unwrap();
+            write!(out, " {name}={:04x} ({delta:+})", self.end.reg[i]).unwrap();
+        }
+        if self.start.flags != self.end.flags {
+            write!(out, " flags={:04x}", self.end.flags).unwrap();
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> Json {
+        let deltas = self
+            .deltas()
+            .into_iter()
+            .map(|(name, delta)| (name, Json::from(delta as i64)));
+        Json::object([(
+            "loop",
+            Json::object([
+                ("body", self.body.clone().into()),
+                ("repeats", self.repeats.into()),
+                ("first_step", self.first_step.into()),
+                ("last_step", self.last_step.into()),
+                ("after", registers_json(&self.end)),
+                ("delta", Json::object(deltas)),
+            ]),
+        )])
+    }
+}
+
+pub enum Entry<T> {
+    Step(T),
+    Loop(Loop),
+}
+
+// 実行中の繰り返し
+struct Current<T> {
+    repeat: Loop,
+    // まとめると決まるまでの繰り返しと，途中までの繰り返し
+    held: Vec<Step<T>>,
+    partial: Vec<Step<T>>,
+}
+
+// 同じipの並びの繰り返しを検出してまとめる．検出のため直近の命令を最大MAX_PERIODの2倍だけ遅らせて出力する
+pub struct Compressor<T> {
+    history: VecDeque<Step<T>>,
+    current: Option<Current<T>>,
+}
+
+impl<T> Default for Compressor<T> {
+    fn default() -> Self {
+        Compressor {
+            history: VecDeque::new(),
+            current: None,
+        }
+    }
+}
+
+impl<T> Compressor<T> {
+    pub fn push(&mut self, step: Step<T>) -> Vec<Entry<T>> {
+        let mut out = Vec::new();
+
+        if let Some(current) = self.current.as_mut() {
+            if current.repeat.body[current.partial.len()] == step.ip {
+                current.partial.push(step);
+                if current.partial.len() == current.repeat.body.len() {
+                    current.complete();
+                }
+                return out;
+            }
+            // 繰り返しを抜けた
+            out.extend(self.end_loop());
+        }
+
+        self.history.push_back(step);
+        match self.period() {
+            Some(period) => out.extend(self.start_loop(period)),
+            None if self.history.len() > 2 * MAX_PERIOD => {
+                let step = self.history.pop_front().unwrap();
+                out.push(Entry::Step(step.item));
+            }
+            None => {}
+        }
+        out
+    }
+
+    // 残りをすべて出力する
+    pub fn finish(&mut self) -> Vec<Entry<T>> {
+        let mut out = self.end_loop();
+        out.extend(self.history.drain(..).map(|s| Entry::Step(s.item)));
+        out
+    }
+
+    // 直近の命令が同じipの並びの2回分になる最短の長さ
+    fn period(&self) -> Option<usize> {
+        let n = self.history.len();
+        (1..=MAX_PERIOD.min(n / 2))
+            .find(|&p| (0..p).all(|i| self.history[n - 2 * p + i].ip == self.history[n - p + i].ip))
+    }
+
+    fn start_loop(&mut self, period: usize) -> Vec<Entry<T>> {
+        let n = self.history.len();
+        let mut out: Vec<Entry<T>> = self
+            .history
+            .drain(..n - 2 * period)
+            .map(|s| Entry::Step(s.item))
+            .collect();
+
+        let first: Vec<Step<T>> = self.history.drain(..period).collect();
+        let second: Vec<Step<T>> = self.history.drain(..).collect();
+        let start = first.last().unwrap().after;
+        let last = second.last().unwrap();
+
+        let mut current = Current {
+            repeat: Loop {
+                body: first.iter().map(|s| s.ip).collect(),
+                repeats: 0,
+                first_step: second[0].step,
+                last_step: last.step,
+                start,
+                end: last.after,
+            },
+            held: Vec::new(),
+            partial: second,
+        };
+        current.complete();
+
+        out.extend(first.into_iter().map(|s| Entry::Step(s.item)));
+        self.current = Some(current);
+        out
+    }
+
+    // 繰り返しが終わった．回数が少なければまとめずに出力する
+    fn end_loop(&mut self) -> Vec<Entry<T>> {
+        let Some(current) = self.current.take() else {
+            return Vec::new();
+        };
+
+        let mut out = Vec::new();
+        if current.repeat.repeats >= MIN_REPEATS {
+            out.push(Entry::Loop(current.repeat));
+        } else {
+            out.extend(current.held.into_iter().map(|s| Entry::Step(s.item)));
+        }
+        out.extend(current.partial.into_iter().map(|s| Entry::Step(s.item)));
+        out
+    }
+}
+
+impl<T> Current<T> {
+    // 1回分の繰り返しを終えた
+    fn complete(&mut self) {
+        let last = self.partial.last().unwrap();
+        self.repeat.repeats += 1;
+        self.repeat.last_step = last.step;
+        self.repeat.end = last.after;
+
+        if self.repeat.repeats < MIN_REPEATS {
+            self.held.append(&mut self.partial);
+        } else {
+            self.held.clear();
+            self.partial.clear();
+        }
+    }
+}
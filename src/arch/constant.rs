@@ -1,3 +1,6 @@
+// ビットのグループ分けは命令フォーマットのフィールド境界に合わせている
+#![allow(clippy::unusual_byte_groupings)]
+
 pub mod reg {
     // 16bit(w = 1)
     pub const AX: isize = 0b000;
@@ -19,10 +22,14 @@ pub mod reg {
     pub const DH: isize = 0b110;
     pub const BH: isize = 0b111;
 
+    #[allow(dead_code)]
     // Segment
     pub const ES: isize = 0b00;
+    #[allow(dead_code)]
     pub const CS: isize = 0b01;
+    #[allow(dead_code)]
     pub const SS: isize = 0b10;
+    #[allow(dead_code)]
     pub const DS: isize = 0b11;
 }
 
@@ -78,6 +85,7 @@ pub mod opcode {
 
     // SSB
     pub const SSB_REG_EITHER: isize = 0b000110;
+    #[allow(dead_code)]
     pub const SSB_IMMEDIATE_REGISTER_MEMORY_8BIT: isize = 0b100000;
     pub const SSB_IMMEDIATE_WITH_REGISTER_MEMORY_3BIT: isize = 0b011;
     pub const SSB_IMMEDIATE_WITH_ACCUMULATOR: isize = 0b000111;
@@ -107,12 +115,16 @@ pub mod opcode {
 
     // Load
     pub const LEA: isize = 0b_1000_1101;
+    #[allow(dead_code)]
     pub const LDS: isize = 0b_1100_0101;
+    #[allow(dead_code)]
     pub const LES: isize = 0b_1100_0100;
 
     pub const RET_WITHIN_SEGMENT: isize = 0b_1100_0011;
     pub const RET_WITHIN_SEG_ADDING_IMMED_TO_SP: isize = 0b_1100_0010;
+    #[allow(dead_code)]
     pub const RET_INTERSEGMENT: isize = 0b_1100_1011;
+    #[allow(dead_code)]
     pub const RET_INTERSEGMENT_ADDING_IMMEDIATE_TO_SP: isize = 0b_1100_1010;
 
     pub const JE: isize = 0b_0111_0100;
@@ -191,13 +203,16 @@ pub mod opcode {
     pub const POP_REG: isize = 0b_01011;
     pub const DEC_REGISTER: isize = 0b_01001;
 
+    #[allow(dead_code)]
     pub const SHL_6BIT: isize = 0b_110100;
     pub const SHL_3BIT: isize = 0b_100;
 
+    #[allow(dead_code)]
     pub const SHL: isize = 0b_110100;
 }
 
 pub mod size {
+    #[allow(dead_code)]
     pub const REG_EITHER_SIZE: usize = 2;
 }
 
@@ -207,6 +222,7 @@ pub mod syscall {
     pub const WRITE: u16 = 4;
     pub const OPEN: u16 = 5;
     pub const CLOSE: u16 = 6;
+    pub const CREAT: u16 = 8;
     pub const BRK: u16 = 17;
     pub const LSEEK: u16 = 19;
     pub const IOCTL: u16 = 54;
@@ -0,0 +1,195 @@
+use super::asm::{Assembly, Flow};
+use super::symbol::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+
+// 実行した命令の回数と，条件分岐ごとの分岐した回数・しなかった回数
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Coverage {
+    pub hits: BTreeMap<u16, u64>,
+    pub branches: BTreeMap<u16, (u64, u64)>,
+}
+
+impl Coverage {
+    pub fn record(&mut self, asm: &Assembly, taken: bool) {
+        *self.hits.entry(asm.address).or_default() += 1;
+
+        if let Flow::Branch(_) = asm.instruction.flow() {
+            let (yes, no) = self.branches.entry(asm.address).or_default();
+            if taken {
+                *yes += 1;
+            } else {
+                *no += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&addr, &n) in &other.hits {
+            *self.hits.entry(addr).or_default() += n;
+        }
+        for (&addr, &(yes, no)) in &other.branches {
+            let branch = self.branches.entry(addr).or_default();
+            branch.0 += yes;
+            branch.1 += no;
+        }
+    }
+
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits.get(&addr).copied().unwrap_or(0)
+    }
+
+    // lcovのトレースファイルの1レコード．行番号の代わりに命令のアドレス(10進数)を使い，
+    // 関数はシンボルテーブルの関数，呼び出し回数は先頭の命令の実行回数とする．
+    // codeにない命令でも実行したものは含める
+    pub fn to_lcov(&self, file: &str, code: &[Assembly], symbols: &SymbolTable) -> String {
+        let lines: BTreeSet<u16> = code
+            .iter()
+            .map(|a| a.address)
+            .chain(self.hits.keys().copied())
+            .collect();
+        let branches: BTreeSet<u16> = code
+            .iter()
+            .filter(|a| matches!(a.instruction.flow(), Flow::Branch(_)))
+            .map(|a| a.address)
+            .chain(self.branches.keys().copied())
+            .collect();
+
+        let mut out = String::new();
+        writeln!(out, "TN:\nSF:{file}").unwrap();
+
+        let functions: Vec<(&str, u16)> = symbols
+            .functions()
+            .map(|s| (s.name.as_str(), s.value as u16))
+            .collect();
+        for (name, addr) in &functions {
+            writeln!(out, "FN:{addr},{name}").unwrap();
+        }
+        for (name, addr) in &functions {
+            writeln!(out, "FNDA:{},{name}", self.hits(*addr)).unwrap();
+        }
+        let hit = functions.iter().filter(|(_, a)| self.hits(*a) > 0).count();
+        writeln!(out, "FNF:{}\nFNH:{hit}", functions.len()).unwrap();
+
+        let (mut found, mut taken) = (0, 0);
+        for &addr in &branches {
+            let counts = match (self.hits(addr), self.branches.get(&addr)) {
+                (0, _) => ["-".to_owned(), "-".to_owned()],
+                (_, Some((yes, no))) => [yes.to_string(), no.to_string()],
+                (_, None) => ["0".to_owned(), "0".to_owned()],
+            };
+            for (i, count) in counts.iter().enumerate() {
+                writeln!(out, "BRDA:{addr},0,{i},{count}").unwrap();
+                found += 1;
+                if !matches!(count.as_str(), "-" | "0") {
+                    taken += 1;
+                }
+            }
+        }
+        writeln!(out, "BRF:{found}\nBRH:{taken}").unwrap();
+
+        for &addr in &lines {
+            writeln!(out, "DA:{addr},{}", self.hits(addr)).unwrap();
+        }
+        writeln!(
+            out,
+            "LF:{}\nLH:{}\nend_of_record",
+            lines.len(),
+            self.hits.len()
+        )
+        .unwrap();
+        out
+    }
+
+    // 逆アセンブル結果に付ける実行回数
+    pub fn comments(&self, code: &[&Assembly]) -> BTreeMap<u16, String> {
+        code.iter()
+            .map(|asm| {
+                let addr = asm.address;
+                let comment = match (self.hits(addr), self.branches.get(&addr)) {
+                    (0, _) => "never executed".to_owned(),
+                    (n, Some((yes, no))) => format!("{n} hits, taken {yes}, not taken {no}"),
+                    (n, None) => format!("{n} hits"),
+                };
+                (addr, comment)
+            })
+            .collect()
+    }
+}
+
+// SFのパスがfileと同じファイルを指すか
+pub fn same_file(sf: &str, file: &str) -> bool {
+    sf == file || fs::canonicalize(sf).is_ok_and(|a| fs::canonicalize(file).is_ok_and(|b| a == b))
+}
+
+// トレースファイルをend_of_recordまでのレコードごとのテキストに分ける．(SF, テキスト)
+pub fn split_lcov(src: &str) -> Vec<(&str, &str)> {
+    let mut records = Vec::new();
+    let (mut start, mut end) = (0, 0);
+    let mut sf = "";
+
+    for line in src.split_inclusive('\n') {
+        end += line.len();
+        let line = line.trim_end();
+        if let Some(path) = line.strip_prefix("SF:") {
+            sf = path;
+        } else if line == "end_of_record" {
+            records.push((sf, &src[start..end]));
+            (start, sf) = (end, "");
+        }
+    }
+    if start < src.len() {
+        records.push((sf, &src[start..]));
+    }
+
+    records
+}
+
+// to_lcovで書いたトレースファイルを読む．SFごとのレコード
+pub fn parse_lcov(src: &str) -> Result<Vec<(String, Coverage)>, String> {
+    let mut records: Vec<(String, Coverage)> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err = || format!("line {}: invalid record '{line}'", i + 1);
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        if key == "SF" {
+            records.push((value.to_owned(), Coverage::default()));
+            continue;
+        }
+        let Some((_, coverage)) = records.last_mut() else {
+            continue;
+        };
+
+        let fields: Vec<&str> = value.split(',').collect();
+        match (key, fields.as_slice()) {
+            ("DA", [addr, count]) => {
+                let addr: u16 = addr.parse().map_err(|_| err())?;
+                let count: u64 = count.parse().map_err(|_| err())?;
+                if count > 0 {
+                    coverage.hits.insert(addr, count);
+                }
+            }
+            ("BRDA", [addr, _, branch, count]) => {
+                let addr: u16 = addr.parse().map_err(|_| err())?;
+                let count: u64 = match *count {
+                    "-" => continue,
+                    n => n.parse().map_err(|_| err())?,
+                };
+                let entry = coverage.branches.entry(addr).or_default();
+                match *branch {
+                    "0" => entry.0 = count,
+                    "1" => entry.1 = count,
+                    _ => return Err(err()),
+                }
+            }
+            ("DA" | "BRDA", _) => return Err(err()),
+            _ => {}
+        }
+    }
+
+    Ok(records)
+}
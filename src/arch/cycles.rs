@@ -0,0 +1,312 @@
+use super::asm::Assembly;
+use super::opcode::Opcode;
+use super::operand::{Operand, EA};
+use super::reg::{Reg8, Register};
+
+pub const CPUS: [&str; 2] = ["8086", "8088"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cpu {
+    I8086,
+    // 外部バスが8bitなので，ワードの転送ごとに4クロック多くかかる
+    I8088,
+}
+
+impl Cpu {
+    pub fn from_name(name: &str) -> Option<Cpu> {
+        match name {
+            "8086" => Some(Cpu::I8086),
+            "8088" => Some(Cpu::I8088),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cpu::I8086 => "8086",
+            Cpu::I8088 => "8088",
+        }
+    }
+}
+
+// 8088でワードの転送1回ごとに加えるクロック数
+const WORD_TRANSFER_PENALTY: u32 = 4;
+
+// オペランドの組み合わせ．1つ目が書き込み先
+#[derive(Clone, Copy, PartialEq)]
+enum Form {
+    RegReg,
+    RegMem,
+    MemReg,
+    RegImm,
+    MemImm,
+    Reg,
+    Mem,
+    Imm,
+    None,
+}
+
+// 命令を1回実行するクロック数．Intel 8086 Family User's Manualの命令ごとの表による．
+// takenは分岐した場合，countはrepの繰り返し回数かclによるシフトの回数．
+// 範囲で与えられている乗除算は最小値とし，プリフェッチキューと奇数番地のワードアクセスは考えない
+pub fn cycles(cpu: Cpu, asm: &Assembly, taken: bool, count: u16) -> u32 {
+    let instruction = &asm.instruction;
+    let form = form(&instruction.operand1, &instruction.operand2);
+    let ea = [&instruction.operand1, &instruction.operand2]
+        .into_iter()
+        .find_map(|operand| match operand {
+            Some(Operand::EffectiveAddress(ea)) => Some(ea_cycles(ea)),
+            _ => None,
+        })
+        .unwrap_or(0);
+
+    let (clocks, transfers) = base(instruction.opcode, form, taken, count as u32, is_byte(asm));
+    // アキュムレータとメモリの転送はアドレスを直接持つので，実効アドレスの計算がない
+    let accumulator = instruction.opcode == Opcode::MovMemoryToAccumulator;
+    let clocks = if ea > 0 && has_memory(form) && !accumulator {
+        clocks + ea
+    } else {
+        clocks
+    };
+
+    match cpu {
+        Cpu::I8086 => clocks,
+        Cpu::I8088 if is_byte(asm) && !is_stack(instruction.opcode) => clocks,
+        Cpu::I8088 => clocks + transfers * WORD_TRANSFER_PENALTY,
+    }
+}
+
+// 実効アドレスの計算にかかるクロック数
+pub fn ea_cycles(ea: &EA) -> u32 {
+    let disp = |d: isize, without: u32, with: u32| if d == 0 { without } else { with };
+    match ea {
+        EA::DispOnly(_) => 6,
+        EA::Bx(d) | EA::Bp(d) | EA::Si(d) | EA::Di(d) => disp(d.0, 5, 9),
+        EA::BpDi(d) | EA::BxSi(d) => disp(d.0, 7, 11),
+        EA::BpSi(d) | EA::BxDi(d) => disp(d.0, 8, 12),
+    }
+}
+
+fn form(dst: &Option<Operand>, src: &Option<Operand>) -> Form {
+    use Operand::*;
+    match (dst, src) {
+        (Some(Register(_)), Some(Register(_))) => Form::RegReg,
+        (Some(Register(_)), Some(EffectiveAddress(_))) => Form::RegMem,
+        (Some(EffectiveAddress(_)), Some(Register(_))) => Form::MemReg,
+        (Some(Register(_)), Some(Immediate(_))) => Form::RegImm,
+        (Some(EffectiveAddress(_)), Some(Immediate(_))) => Form::MemImm,
+        (Some(Register(_)), None) => Form::Reg,
+        (Some(EffectiveAddress(_)), None) => Form::Mem,
+        (Some(Immediate(_)), _) => Form::Imm,
+        _ => Form::None,
+    }
+}
+
+fn has_memory(form: Form) -> bool {
+    matches!(form, Form::RegMem | Form::MemReg | Form::MemImm | Form::Mem)
+}
+
+fn is_byte(asm: &Assembly) -> bool {
+    let instruction = &asm.instruction;
+    let reg8 = [&instruction.operand1, &instruction.operand2]
+        .into_iter()
+        .any(|operand| matches!(operand, Some(Operand::Register(Register::Reg8(_)))));
+    reg8 || matches!(
+        instruction.opcode,
+        Opcode::MovImmediateRegisterMemoryByte
+            | Opcode::CmpImmediateByte
+            | Opcode::TestImmediateByte
+            | Opcode::RepMovsb
+            | Opcode::RepStosb
+            | Opcode::RepScasb
+            | Opcode::CompsByte
+    )
+}
+
+// スタックへの転送は常にワード
+fn is_stack(opcode: Opcode) -> bool {
+    use Opcode::*;
+    matches!(
+        opcode,
+        PushReg
+            | PushRegMem
+            | PushSegReg
+            | PopReg
+            | PopRegMem
+            | PopSegReg
+            | CallWithinDirect
+            | RetWithinSegment
+            | RetWithinSegAddingImmedToSp
+            | RetIntersegment
+            | RetIntersegmentAddingImmediateToSp
+            | IntTypeSpecified
+    )
+}
+
+// EAを除いたクロック数と，メモリとのワードの転送回数
+fn base(opcode: Opcode, form: Form, taken: bool, n: u32, byte: bool) -> (u32, u32) {
+    use Opcode::*;
+    let branch = |yes: u32, no: u32| if taken { (yes, 0) } else { (no, 0) };
+
+    match opcode {
+        MovRmToFromReg => match form {
+            Form::RegReg => (2, 0),
+            Form::RegMem => (8, 1),
+            _ => (9, 1),
+        },
+        MovImmediate => (4, 0),
+        MovImmediateRegisterMemory
+        | MovImmediateRegisterMemoryWord
+        | MovImmediateRegisterMemoryByte => match form {
+            Form::RegImm => (4, 0),
+            _ => (10, 1),
+        },
+        MovMemoryToAccumulator => (10, 1),
+
+        PushReg => (11, 1),
+        PushSegReg => (10, 1),
+        PushRegMem => match form {
+            Form::Reg => (11, 1),
+            _ => (16, 2),
+        },
+        PopReg | PopSegReg => (8, 1),
+        PopRegMem => match form {
+            Form::Reg => (8, 1),
+            _ => (17, 2),
+        },
+
+        XchgRegisterWithAccumulator => (3, 0),
+        XchgRegisterMemoryWithRegister => match form {
+            Form::RegReg => (4, 0),
+            _ => (17, 2),
+        },
+
+        AddRegEither
+        | AddImmediateRegisterMemory
+        | AddImmediateFromAccumulator
+        | AddImmediateToAccumulator
+        | OrRegEither
+        | OrImmediateRegisterMemory
+        | OrImmediateFromAccumulator
+        | SubRegEither
+        | SubImmediateRegisterMemory
+        | SubImmediateFromAccumulator
+        | AdcRegEither
+        | AdcImmediateRegisterMemory
+        | AdcImmediateFromAccumulator
+        | SsbRegEither
+        | SsbImmediateRegisterMemory
+        | SsbImmediateFromAccumulator
+        | AndRegEither
+        | AndImmediateRegisterMemory
+        | AndImmediateFromAccumulator
+        | XorRegEither => match form {
+            Form::RegReg => (3, 0),
+            Form::RegMem => (9, 1),
+            Form::MemReg => (16, 2),
+            Form::MemImm => (17, 2),
+            _ => (4, 0),
+        },
+        CmpRegEither | CmpImmediateWord | CmpImmediateByte | CmpImmediateFromAccumulator => {
+            match form {
+                Form::RegReg => (3, 0),
+                Form::RegMem | Form::MemReg => (9, 1),
+                Form::MemImm => (10, 1),
+                _ => (4, 0),
+            }
+        }
+        TestRegisterMemoryAndRegister => match form {
+            Form::RegReg => (3, 0),
+            _ => (9, 1),
+        },
+        TestImmediate | TestImmediateByte => match form {
+            Form::RegImm => (5, 0),
+            _ => (11, 1),
+        },
+        TestImmediateDataAndAccumulator => (4, 0),
+
+        IncRegister | DecRegister => (2, 0),
+        IncRegisterMemory | DecRegisterMemory => match form {
+            Form::Reg => (3, 0),
+            _ => (15, 2),
+        },
+        Neg | Not => match form {
+            Form::Reg => (3, 0),
+            _ => (16, 2),
+        },
+        // 2つ目のオペランドがclなら回数に比例する
+        Shl | Shr | Sar | Rol | Ror | Rcl | Rcr => match form {
+            Form::RegReg => (8 + 4 * n, 0),
+            Form::MemReg => (20 + 4 * n, 2),
+            Form::Mem | Form::MemImm => (15, 2),
+            _ => (2, 0),
+        },
+        Mul | Imul | Div | Idiv => {
+            let (reg8, reg16) = match opcode {
+                Mul => (70, 118),
+                Imul => (80, 128),
+                Div => (80, 144),
+                _ => (101, 165),
+            };
+            let clocks = if byte { reg8 } else { reg16 };
+            match form {
+                Form::Reg => (clocks, 0),
+                _ => (clocks + 6, 1),
+            }
+        }
+        Cbw => (2, 0),
+        Cwd => (5, 0),
+        Lea => (2, 0),
+        Lds | Les => (16, 2),
+
+        CallWithinDirect => match form {
+            Form::Imm => (19, 1),
+            Form::Reg => (16, 1),
+            _ => (21, 2),
+        },
+        JmpDirectWithinSegment | JmpDirectWithinSegmentShort => (15, 0),
+        JmpIndirectWithinSegment => match form {
+            Form::Reg => (11, 0),
+            _ => (18, 1),
+        },
+        RetWithinSegment => (8, 1),
+        RetWithinSegAddingImmedToSp => (12, 1),
+        RetIntersegment => (18, 2),
+        RetIntersegmentAddingImmediateToSp => (17, 2),
+        Je | Jl | Jle | Jb | Jbe | Jp | Jo | Js | Jne | Jnl | Jnle | Jnb | Jnbe | Jnp | Jno
+        | Jns => branch(16, 4),
+        Loop => branch(17, 5),
+        Loopz => branch(18, 6),
+        Loopnz => branch(19, 5),
+        Jcxz => branch(18, 6),
+        // 3ワードのpushと割り込みベクタの2ワードの読み出し
+        IntTypeSpecified => (51, 5),
+
+        RepMovsb | RepMovsw => (9 + 17 * n, 2 * n),
+        RepStosb => (9 + 10 * n, n),
+        RepScasb => (9 + 15 * n, n),
+        CompsByte | CompsWord => (22, 2),
+        Rep => (2, 0),
+
+        Clc | Cmc | Stc | Cld | Std | Cli | Sti | Hlt | Lock => (2, 0),
+        Nop | Wait => (3, 0),
+        Esc => (8, 1),
+        InFixedPort | OutFixedPort => (10, 0),
+        InVariablePort | OutVariablePort => (8, 0),
+        Undefined => (0, 0),
+    }
+}
+
+// シフトの回数のオペランドがclか
+pub fn shifts_by_cl(asm: &Assembly) -> bool {
+    matches!(
+        asm.instruction.opcode,
+        Opcode::Shl
+            | Opcode::Shr
+            | Opcode::Sar
+            | Opcode::Rol
+            | Opcode::Ror
+            | Opcode::Rcl
+            | Opcode::Rcr
+    ) && asm.instruction.operand2 == Some(Operand::Register(Register::Reg8(Reg8::CL)))
+}
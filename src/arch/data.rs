@@ -0,0 +1,206 @@
+use super::bin;
+use super::symbol::{section, Symbol, SymbolTable};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// 文字列とみなす最短の長さ(終端の0を除く)
+const MIN_STRING: usize = 3;
+// 1行に並べるバイト数
+const ROW: usize = 8;
+
+// データセグメントの注釈付きのダンプ．
+//
+//     ; data 0x0000..0x0214 (532 bytes)
+//     ___stdin:
+//     0012: 00 00 00 00 00 00 00 00  ........
+//     .Mdivz:
+//     0154: "Divide by 0\n"
+//     020c: 36 00 74 02              6.t.      ; 0x0036 -> ___iotab, 0x0274 -> _end
+//
+//     ; bss 0x0214..0x0274 (96 bytes)
+//     0214: __bottom (32 bytes)
+//
+// ラベルはシンボルテーブルのデータとbssのシンボル，文字列は0で終わる表示可能な文字の並び，
+// 偶数番地のワードがシンボルや文字列の先頭を指していればポインタとして注釈する
+pub struct DataView<'a> {
+    data: &'a [u8],
+    bss_size: u16,
+    symbols: &'a SymbolTable,
+    // データセグメントのアドレスごとのラベル
+    labels: BTreeMap<u16, Vec<&'a str>>,
+    // 文字列の先頭と終端の0の次のアドレス
+    strings: BTreeMap<u16, u16>,
+}
+
+impl<'a> DataView<'a> {
+    pub fn new(data: &'a [u8], bss_size: u16, symbols: &'a SymbolTable) -> Self {
+        let mut labels: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+        for symbol in symbols.iter().filter(|s| is_data(s)) {
+            labels
+                .entry(symbol.value as u16)
+                .or_default()
+                .push(&symbol.name);
+        }
+
+        DataView {
+            data,
+            bss_size,
+            symbols,
+            strings: strings(data, &labels),
+            labels,
+        }
+    }
+
+    // addrから始まる文字列
+    pub fn string_at(&self, addr: u16) -> Option<&'a [u8]> {
+        let end = *self.strings.get(&addr)?;
+        Some(&self.data[addr as usize..end as usize - 1])
+    }
+
+    // ポインタの値の説明．データのシンボル，文字列，関数の先頭を指していなければNone
+    pub fn pointer(&self, value: u16) -> Option<String> {
+        if value == 0 {
+            return None;
+        }
+        if let Some(names) = self.labels.get(&value) {
+            return Some(names[0].to_owned());
+        }
+        if let Some(s) = self.string_at(value) {
+            return Some(quote(s));
+        }
+        self.symbols
+            .function_starting_at(value)
+            .map(|s| format!("{} (text)", s.name))
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let size = self.data.len();
+        writeln!(out, "; data 0x0000..0x{size:04x} ({size} bytes)").unwrap();
+
+        let mut offset = 0;
+        while offset < size {
+            let addr = offset as u16;
+            self.write_labels(&mut out, addr);
+
+            if let Some(&end) = self.strings.get(&addr) {
+                let s = &self.data[offset..end as usize - 1];
+                writeln!(out, "{addr:04x}: {}", quote(s)).unwrap();
+                offset = end as usize;
+                continue;
+            }
+
+            // ラベルと文字列の手前，またはROWの倍数で行を区切る
+            let end = (offset + 1..size)
+                .find(|&o| {
+                    o % ROW == 0
+                        || self.labels.contains_key(&(o as u16))
+                        || self.strings.contains_key(&(o as u16))
+                })
+                .unwrap_or(size);
+            self.write_row(&mut out, offset, end);
+            offset = end;
+        }
+
+        let start = size as u16;
+        let end = start as usize + self.bss_size as usize;
+        writeln!(
+            out,
+            "\n; bss 0x{start:04x}..0x{end:04x} ({} bytes)",
+            self.bss_size
+        )
+        .unwrap();
+        let bss: Vec<(&u16, &Vec<&str>)> = self
+            .labels
+            .range(start..)
+            .filter(|(&a, _)| (a as usize) < end)
+            .collect();
+        for (i, (&addr, names)) in bss.iter().enumerate() {
+            let next = bss.get(i + 1).map_or(end, |(&a, _)| a as usize);
+            for name in names.iter() {
+                writeln!(out, "{addr:04x}: {name} ({} bytes)", next - addr as usize).unwrap();
+            }
+        }
+
+        out
+    }
+
+    fn write_labels(&self, out: &mut String, addr: u16) {
+        for name in self.labels.get(&addr).into_iter().flatten() {
+            writeln!(out, "{name}:").unwrap();
+        }
+    }
+
+    fn write_row(&self, out: &mut String, start: usize, end: usize) {
+        let bytes = &self.data[start..end];
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+
+        let pointers: Vec<String> = (start..end.saturating_sub(1))
+            .filter(|o| o % 2 == 0)
+            .filter_map(|o| {
+                let value = bin::bytes_to_16bit_little_endian(&self.data[o..o + 2]);
+                self.pointer(value).map(|p| format!("0x{value:04x} -> {p}"))
+            })
+            .collect();
+
+        let line = format!("{start:04x}: {:<24} {ascii}", hex.join(" "));
+        if pointers.is_empty() {
+            writeln!(out, "{line}").unwrap();
+        } else {
+            writeln!(out, "{line:<40}; {}", pointers.join(", ")).unwrap();
+        }
+    }
+}
+
+fn is_data(symbol: &Symbol) -> bool {
+    !symbol.name.is_empty() && matches!(symbol.section(), section::DATA | section::BSS)
+}
+
+// 文字列の先頭と，終端の0の次のアドレス．ラベルの位置からは別の値とする
+fn strings(data: &[u8], labels: &BTreeMap<u16, Vec<&str>>) -> BTreeMap<u16, u16> {
+    let mut strings = BTreeMap::new();
+    let mut start = 0;
+
+    for (i, &b) in data.iter().enumerate() {
+        if labels.contains_key(&(i as u16)) {
+            start = i;
+        }
+        if is_printable(b) {
+            continue;
+        }
+        if b == 0 && i - start >= MIN_STRING {
+            strings.insert(start as u16, i as u16 + 1);
+        }
+        start = i + 1;
+    }
+
+    strings
+}
+
+fn is_printable(b: u8) -> bool {
+    matches!(b, 0x20..=0x7e | b'\n' | b'\t' | b'\r')
+}
+
+// "Divide by 0\n"
+pub fn quote(s: &[u8]) -> String {
+    let mut out = String::from('"');
+    for &b in s {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            _ => out.push(b as char),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -21,7 +21,6 @@ use super::constant::opcode::PUSH_REG;
 use super::constant::opcode::PUSH_RM;
 use super::constant::opcode::SHL_3BIT;
 use super::operand::ImmediateValue;
-use super::vm::VM;
 use super::{
     asm::Instruction,
     bin::{self, reverse_order_u16},
@@ -52,31 +51,58 @@ use super::constant::opcode::{
     XCHG_REGISTER_MEMORY_WITH_REGISTER, XCHG_REGISTER_WITH_ACCUMULATOR, XOR_REG_EITHER,
 };
 
-impl VM {
+// バイト列を機械語として解釈するデコーダ．VMの状態には依存しない
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    // bytes[0]のアドレス
+    base: u16,
+    // 次にデコードする命令のアドレス
+    pub ip: u16,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8], base: u16) -> Self {
+        Self::at(bytes, base, base)
+    }
+
+    // ipからデコードを始める
+    pub fn at(bytes: &'a [u8], base: u16, ip: u16) -> Self {
+        Decoder { bytes, base, ip }
+    }
+
+    fn byte(&self, addr: u16) -> Option<u8> {
+        let offset = addr.checked_sub(self.base)? as usize;
+        self.bytes.get(offset).copied()
+    }
+
+    // 1命令をデコードしてipを進める．解釈できないバイトや途中で終わる命令は1byteの(undefined)とする
     pub fn decode(&mut self) -> Option<Assembly> {
-        if self.ip as usize >= self.text_size {
-            return None;
-        }
+        let start = self.ip;
+        self.byte(start)?;
 
-        if !cfg!(feature = "vm") && self.ip as usize == self.text_size - 1 {
-            return self.undefined();
+        match self.decode_instruction() {
+            Some(asm) => Some(asm),
+            None => {
+                self.ip = start;
+                self.undefined()
+            }
         }
+    }
 
+    fn decode_instruction(&mut self) -> Option<Assembly> {
         let cur_8bits = self.peek_u8()? as isize;
         let next_8bits = self.peek_offset(1);
 
-        let cur_upper_4bits = (cur_8bits >> 4 & 0xff) as isize;
-        let cur_upper_5bits = (cur_8bits >> 3 & 0xff) as isize;
-        let cur_upper_6bits = (cur_8bits >> 2 & 0xff) as isize;
-        let cur_upper_7bits = (cur_8bits >> 1 & 0xff) as isize;
+        let cur_upper_4bits = cur_8bits >> 4 & 0xff;
+        let cur_upper_5bits = cur_8bits >> 3 & 0xff;
+        let cur_upper_6bits = cur_8bits >> 2 & 0xff;
+        let cur_upper_7bits = cur_8bits >> 1 & 0xff;
 
-        let next_3bits = next_8bits? as isize >> 3 & 0b111;
+        // 最後の1byteでは次のbyteがないが，1byteの命令はデコードできる
+        let next_3bits = next_8bits.map_or(0, |b| b as isize >> 3 & 0b111);
 
-        match cur_upper_4bits {
-            MOV_IMMEDIATE => {
-                return self.immediate_register();
-            }
-            _ => (),
+        if cur_upper_4bits == MOV_IMMEDIATE {
+            return self.immediate_register();
         }
 
         if let Some(opcode) = match cur_upper_5bits {
@@ -401,10 +427,34 @@ impl VM {
             _ => (),
         }
 
-        return self.undefined();
+        self.undefined()
+    }
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Assembly;
+
+    fn next(&mut self) -> Option<Assembly> {
+        self.decode()
     }
 }
 
+// 先頭から順にデコードする(linear sweep)．既存VMに合わせ，最初の(undefined)で止める
+pub fn disassemble(bytes: &[u8], base: u16) -> Vec<Assembly> {
+    let mut v = Vec::new();
+
+    for asm in Decoder::new(bytes, base) {
+        let undefined = asm.instruction.opcode == Opcode::Undefined;
+        v.push(asm);
+
+        if undefined {
+            break;
+        }
+    }
+
+    v
+}
+
 pub trait TextParser {
     fn memory_to_register(&mut self, opcode: Opcode) -> Option<Assembly>;
     fn immediate_register(&mut self) -> Option<Assembly>;
@@ -420,6 +470,7 @@ pub trait TextParser {
     // Load {EA to Register | Pointer to DS | Pointer to ES}
     fn load(&mut self, opcode: Opcode) -> Option<Assembly>;
 
+    #[allow(dead_code)]
     fn sub_immediate(&mut self) -> Option<Assembly>;
 
     // fn shit_control(&mut self) -> Option<Assembly>;
@@ -468,6 +519,7 @@ pub trait TextParser {
     // In, Out
     fn fixed_port(&mut self, opcode: Opcode) -> Option<Assembly>;
 
+    #[allow(dead_code)]
     // Variable port
     // In, Out
     fn variable_port(&mut self, opcode: Opcode) -> Option<Assembly>;
@@ -482,7 +534,7 @@ pub trait TextParser {
     fn undefined(&mut self) -> Option<Assembly>;
 }
 
-impl TextParser for VM {
+impl TextParser for Decoder<'_> {
     fn immediate_register(&mut self) -> Option<Assembly> {
         let address = self.ip;
         let target = self.consume_u8()?;
@@ -492,21 +544,19 @@ impl TextParser for VM {
         let reg = lower_4bits & 0b111;
 
         let upper_data_byte = self.consume_u8()? as u16;
-        let lower_data_byte = self.consume_u8()? as u16;
-
-        let code =
-            (target as usize) << 16 | (upper_data_byte as usize) << 8 | (lower_data_byte as usize);
 
-        let mut register: Register = Register::None;
-        let mut value: u16 = 0;
-
-        if w == 1 {
-            register = Register::Reg16(Reg16::from(reg));
-            value = (lower_data_byte << 8) | upper_data_byte as u16; // 即値はリトルエンディアンなので入れ替える
+        // 8bitレジスタへの即値は1byteだけ
+        let (register, value, code, size) = if w == 1 {
+            let lower_data_byte = self.consume_u8()? as u16;
+            let value = (lower_data_byte << 8) | upper_data_byte; // 即値はリトルエンディアンなので入れ替える
+            let code = (target as usize) << 16
+                | (upper_data_byte as usize) << 8
+                | (lower_data_byte as usize);
+            (Register::Reg16(Reg16::from(reg)), value, code, 3)
         } else {
-            register = Register::Reg8(Reg8::from(reg));
-            value = lower_data_byte as u16;
-        }
+            let code = (target as usize) << 8 | (upper_data_byte as usize);
+            (Register::Reg8(Reg8::from(reg)), upper_data_byte, code, 2)
+        };
 
         let instruction = Instruction {
             opcode: Opcode::MovImmediate,
@@ -516,7 +566,7 @@ impl TextParser for VM {
 
         let asm = Assembly {
             address,
-            size: 3,
+            size,
             code,
             instruction,
         };
@@ -558,35 +608,33 @@ impl TextParser for VM {
         let (mod_, _, rm) = bin::get_reg_mem_element(value);
 
         let sw = opcode & 0b11;
-        if sw == 0b11 {
-            if mod_ == 0b00 && rm == 0b110 {
-                let mut disp = self.consume_u16()?;
+        if sw == 0b11 && mod_ == 0b00 && rm == 0b110 {
+            let mut disp = self.consume_u16()?;
 
-                disp = reverse_order_u16(disp)?;
+            disp = reverse_order_u16(disp)?;
 
-                let ea = EA::DispOnly(Disp(disp as isize));
-                let immediate_value = self.consume_u8()? as i8;
+            let ea = EA::DispOnly(Disp(disp as isize));
+            let immediate_value = self.consume_u8()? as i8;
 
-                let instruction = Instruction {
-                    opcode: Opcode::SubImmediateRegisterMemory,
-                    operand1: Some(Operand::EffectiveAddress(ea)),
-                    operand2: Some(Operand::Immediate(I8(immediate_value as i8, 2))),
-                };
+            let instruction = Instruction {
+                opcode: Opcode::SubImmediateRegisterMemory,
+                operand1: Some(Operand::EffectiveAddress(ea)),
+                operand2: Some(Operand::Immediate(I8(immediate_value, 2))),
+            };
 
-                let code: usize = (opcode as usize) << 32
-                    | (value as usize) << 24
-                    | (disp as usize) << 16
-                    | immediate_value as usize;
+            let code: usize = (opcode as usize) << 32
+                | (value as usize) << 24
+                | (disp as usize) << 16
+                | immediate_value as usize;
 
-                let asm = Assembly {
-                    address,
-                    size: 5,
-                    code,
-                    instruction,
-                };
+            let asm = Assembly {
+                address,
+                size: 5,
+                code,
+                instruction,
+            };
 
-                return Some(asm);
-            }
+            return Some(asm);
         }
 
         None
@@ -605,22 +653,18 @@ impl TextParser for VM {
         let (mod_, _, rm) = bin::get_reg_mem_element(next_byte);
 
         let sw = cur_byte & 0b11;
-        let s = cur_byte >> 1 & 0b1;
         let w = cur_byte & 0b1;
 
-        let mut register: Register = Register::None;
-        let mut value: u16 = 0;
-
         // w == 1 then 16bit length
         // if sw == 0b01 {
         if mod_ == 0b00 && rm == 0b110 {
             let third_byte = self.consume_u8()?;
             size += 1;
-            let mut disp = self.consume_u8()?;
+            let disp = self.consume_u8()?;
             size += 1;
 
-            code = (code as usize) << 8 | third_byte as usize;
-            code = (code as usize) << 8 | disp as usize;
+            code = code << 8 | third_byte as usize;
+            code = code << 8 | disp as usize;
 
             let data = (third_byte as u16) << 8 | (disp as u16);
 
@@ -632,13 +676,19 @@ impl TextParser for VM {
             size += 1;
             code = code << 8 | immediate_value_first as usize;
 
-            let operand2 = if w == 1 && opcode == Opcode::MovImmediateRegisterMemory {
+            // sw = 01のときと，s bitを持たないmov, testのwordは即値が2byteになる
+            let word_data = w == 1
+                && matches!(
+                    opcode,
+                    Opcode::MovImmediateRegisterMemory | Opcode::TestImmediate
+                );
+            let operand2 = if word_data || sw == 0b01 {
                 let immediate_value_second = self.consume_u8()? as i8;
                 let immediate_value: i16 =
                     (immediate_value_second as i16) << 8 | immediate_value_first as i16;
 
                 size += 1;
-                code = (code as usize) << 8 | immediate_value_second as usize;
+                code = code << 8 | immediate_value_second as usize;
 
                 Some(Operand::Immediate(I16(immediate_value, 4)))
             } else {
@@ -688,7 +738,6 @@ impl TextParser for VM {
                 code = code << 16 | disp as usize;
                 size += 2;
                 disp = reverse_order_u16(disp)?;
-                let reg = Register::gen(rm, w);
                 let ea = EA::new(rm, (disp as i16) as isize);
 
                 Some(Operand::EffectiveAddress(ea))
@@ -826,14 +875,9 @@ impl TextParser for VM {
                 let disp_low_comp = -(!disp_low_extend + 1); // 補数を取得
 
                 size += 1;
+                code = code << 8 | third_byte as usize;
 
-                let ea = match rm {
-                    0b110 | 0b101 | 0b011 | 0b100 | 0b111 => {
-                        code = code << 8 | third_byte as usize;
-                        EA::new(rm, disp_low_comp as isize)
-                    }
-                    _ => EA::new(rm, 0),
-                };
+                let ea = EA::new(rm, disp_low_comp as isize);
 
                 Some(Operand::EffectiveAddress(ea))
             }
@@ -885,7 +929,7 @@ impl TextParser for VM {
 
         let cur_byte = self.consume_u8()?;
         let next_byte = self.consume_u8()?;
-        let mut code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+        let mut code = (cur_byte as usize) << 8 | next_byte as usize;
         size += 2;
 
         let (_, w) = bin::get_dw(cur_byte);
@@ -896,7 +940,19 @@ impl TextParser for VM {
 
         let operand2 = match mod_ {
             0b00 => {
-                let ea = EA::DispOnly(Disp(0));
+                let ea = if rm == 0b110 {
+                    let disp_low = self.consume_u8()?;
+                    let disp_high = self.consume_u8()?;
+                    size += 2;
+
+                    code = code << 8 | disp_low as usize;
+                    code = code << 8 | disp_high as usize;
+
+                    EA::DispOnly(Disp(((disp_high as u16) << 8 | disp_low as u16) as isize))
+                } else {
+                    EA::new(rm, 0)
+                };
+
                 Some(Operand::EffectiveAddress(ea))
             }
             0b01 => {
@@ -950,10 +1006,10 @@ impl TextParser for VM {
 
         let cur_byte = self.consume_u8()?;
         let next_byte = self.consume_u8()?;
-        let code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+        let code = (cur_byte as usize) << 8 | next_byte as usize;
 
         // 現在のアドレス + disp
-        let disp = self.ip as i16 + (next_byte as i8) as i16;
+        let disp = (self.ip as i16).wrapping_add((next_byte as i8) as i16);
 
         let instruction = Instruction {
             opcode,
@@ -977,7 +1033,7 @@ impl TextParser for VM {
         let cur_byte = self.consume_u8()?;
         let next_byte = self.consume_u8()?;
         let mut size = 2;
-        let mut code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+        let mut code = (cur_byte as usize) << 8 | next_byte as usize;
 
         let (v, w) = bin::get_dw(cur_byte);
         let (mod_, reg_, rm) = bin::get_reg_mem_element(next_byte);
@@ -1015,7 +1071,6 @@ impl TextParser for VM {
                 size += 2;
                 disp = reverse_order_u16(disp)?;
 
-                let reg = Register::gen(rm, w);
                 let ea = EA::new(rm, (disp as i16) as isize);
 
                 Some(Operand::EffectiveAddress(ea))
@@ -1108,7 +1163,7 @@ impl TextParser for VM {
         let disp = self.consume_u16()?;
         let rev_disp = reverse_order_u16(disp)?;
 
-        let code = ((cur_byte as usize) << 16 | disp as usize) as usize;
+        let code = (cur_byte as usize) << 16 | disp as usize;
         let target_addr = self.ip as isize + rev_disp as isize;
 
         let instruction = Instruction {
@@ -1137,15 +1192,13 @@ impl TextParser for VM {
         let disp = self.consume_u16()?;
         let rev_disp = reverse_order_u16(disp)?;
 
-        let code = ((cur_byte as usize) << 16 | disp as usize) as usize;
+        let code = (cur_byte as usize) << 16 | disp as usize;
         let target_addr = rev_disp as isize;
 
         let instruction = Instruction {
             opcode,
             operand1: Some(Operand::Register(Register::Reg16(Reg16::AX))),
-            operand2: Some(Operand::EffectiveAddress(EA::DispOnly(Disp(
-                target_addr as isize,
-            )))),
+            operand2: Some(Operand::EffectiveAddress(EA::DispOnly(Disp(target_addr)))),
         };
 
         let asm = Assembly {
@@ -1165,7 +1218,7 @@ impl TextParser for VM {
         let data = self.consume_u16()?;
         let rev_data = reverse_order_u16(data)?;
 
-        let code = ((cur_byte as usize) << 16 | data as usize) as usize;
+        let code = (cur_byte as usize) << 16 | data as usize;
 
         let instruction = Instruction {
             opcode,
@@ -1209,15 +1262,12 @@ impl TextParser for VM {
         let address = self.ip;
         let cur_byte = self.consume_u8()?;
         let next_byte = self.consume_u8()?;
-        let code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+        let code = (cur_byte as usize) << 8 | next_byte as usize;
 
         let disp_low: i8 = next_byte as i8;
         let disp_low: i16 = -(!(disp_low as i16) + 1);
-        let jmp_target = self.ip as i16 + disp_low;
-        let disp = Some(Operand::Immediate(ImmediateValue::I16(
-            jmp_target as i16,
-            4,
-        )));
+        let jmp_target = (self.ip as i16).wrapping_add(disp_low);
+        let disp = Some(Operand::Immediate(ImmediateValue::I16(jmp_target, 4)));
 
         let instruction = Instruction {
             opcode,
@@ -1254,7 +1304,7 @@ impl TextParser for VM {
         let operand2 = match opcode {
             Opcode::InFixedPort | Opcode::OutFixedPort => {
                 let next_byte = self.consume_u8()?;
-                code = ((code as usize) << 8 | next_byte as usize) as usize;
+                code = code << 8 | next_byte as usize;
                 size += 1;
                 Some(Operand::Immediate(ImmediateValue::I8(next_byte as i8, 2)))
             }
@@ -1287,7 +1337,7 @@ impl TextParser for VM {
         let next_byte = self.consume_u8()?;
 
         let size = 2;
-        let code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+        let code = (cur_byte as usize) << 8 | next_byte as usize;
 
         let (_v, w) = bin::get_dw(cur_byte);
 
@@ -1315,7 +1365,7 @@ impl TextParser for VM {
         Some(asm)
     }
 
-    fn string_manipulation(&mut self, mut opcode: Opcode) -> Option<Assembly> {
+    fn string_manipulation(&mut self, _opcode: Opcode) -> Option<Assembly> {
         let address = self.ip;
 
         let cur_byte = self.consume_u8()?;
@@ -1326,7 +1376,7 @@ impl TextParser for VM {
 
         let (_v, _) = bin::get_dw(cur_byte);
 
-        opcode = match next_byte {
+        let opcode = match next_byte {
             0xa5 => Opcode::RepMovsw,
             0xa4 => Opcode::RepMovsb,
             0xaa => Opcode::RepStosb,
@@ -1337,7 +1387,7 @@ impl TextParser for VM {
         if Opcode::CompsByte != opcode {
             self.consume_u8()?;
             size += 1;
-            code = ((cur_byte as usize) << 8 | next_byte as usize) as usize;
+            code = (cur_byte as usize) << 8 | next_byte as usize;
         }
 
         let instruction = Instruction {
@@ -1405,10 +1455,10 @@ impl TextParser for VM {
     }
 }
 
-impl BinaryConsume for VM {
+impl BinaryConsume for Decoder<'_> {
     fn consume_u8(&mut self) -> Option<u8> {
-        let out = self.ram.read_text(self.ip);
-        self.ip += 1;
+        let out = self.byte(self.ip)?;
+        self.ip = self.ip.wrapping_add(1);
 
         Some(out)
     }
@@ -1424,10 +1474,9 @@ impl BinaryConsume for VM {
     }
 }
 
-impl BinaryPeek for VM {
+impl BinaryPeek for Decoder<'_> {
     fn peek_u8(&mut self) -> Option<u8> {
-        let out = self.ram.read_text(self.ip);
-        Some(out)
+        self.byte(self.ip)
     }
 
     fn peek_u16(&mut self) -> Option<u16> {
@@ -1441,8 +1490,6 @@ impl BinaryPeek for VM {
     }
 
     fn peek_offset(&mut self, offset: usize) -> Option<u8> {
-        let out = self.ram.read_text(self.ip + offset as u16);
-
-        Some(out)
+        self.byte(self.ip.wrapping_add(offset as u16))
     }
 }
@@ -0,0 +1,449 @@
+use super::{
+    asm::Instruction,
+    decode::Decoder,
+    opcode::Opcode,
+    operand::{Disp, ImmediateValue, Operand, EA},
+    reg::{Reg16, Reg8, Register},
+};
+
+// displacementを1byteで表すかどうか．mod = 01 の解釈が命令によって異なるため呼び出し側で選ぶ
+#[derive(Clone, Copy, PartialEq)]
+enum Disp8 {
+    Allow,
+    Forbid,
+    Force,
+}
+
+// Instructionをaddressに置いたときの機械語にする．デコードすると同じInstructionになる最短のバイト列を返す
+pub fn encode(instruction: &Instruction, address: u16) -> Result<Vec<u8>, String> {
+    let bytes = encode_unchecked(instruction, address)?;
+
+    // デコーダには命令ごとの癖があるので，実際にデコードし直して確かめる．
+    // cmpsbは次のbyteを先読みするので，後ろにnop(xchg ax, ax)を置いておく
+    let mut buf = bytes.clone();
+    buf.push(0x90);
+
+    match Decoder::new(&buf, address).decode() {
+        Some(asm) if asm.size == bytes.len() && asm.instruction == *instruction => Ok(bytes),
+        Some(asm) => Err(format!(
+            "`{instruction:?}` cannot be encoded (it would decode as `{:?}`)",
+            asm.instruction
+        )),
+        None => Err(format!("`{instruction:?}` cannot be encoded")),
+    }
+}
+
+fn encode_unchecked(instruction: &Instruction, address: u16) -> Result<Vec<u8>, String> {
+    let op1 = instruction.operand1.as_ref();
+    let op2 = instruction.operand2.as_ref();
+    let unsupported = || Err(format!("`{instruction:?}` has no 8086 encoding"));
+
+    match instruction.opcode {
+        Opcode::AddRegEither => reg_either(0x00, op1, op2),
+        Opcode::OrRegEither => reg_either(0x08, op1, op2),
+        Opcode::AdcRegEither => reg_either(0x10, op1, op2),
+        Opcode::SsbRegEither => reg_either(0x18, op1, op2),
+        Opcode::AndRegEither => reg_either(0x20, op1, op2),
+        Opcode::SubRegEither => reg_either(0x28, op1, op2),
+        Opcode::XorRegEither => reg_either(0x30, op1, op2),
+        Opcode::CmpRegEither => reg_either(0x38, op1, op2),
+        Opcode::MovRmToFromReg => reg_either(0x88, op1, op2),
+
+        // デコーダはsbbのreg either形式もSsbImmediateRegisterMemoryとして返す
+        Opcode::SsbImmediateRegisterMemory => match op2 {
+            Some(Operand::Immediate(_)) => immediate_register_memory(0b011, instruction),
+            _ => reg_either(0x18, op1, op2),
+        },
+        Opcode::AddImmediateRegisterMemory => immediate_register_memory(0b000, instruction),
+        Opcode::OrImmediateRegisterMemory => immediate_register_memory(0b001, instruction),
+        Opcode::AdcImmediateRegisterMemory => immediate_register_memory(0b010, instruction),
+        Opcode::AndImmediateRegisterMemory => immediate_register_memory(0b100, instruction),
+        Opcode::SubImmediateRegisterMemory => immediate_register_memory(0b101, instruction),
+        Opcode::CmpImmediateWord | Opcode::CmpImmediateByte => {
+            immediate_register_memory(0b111, instruction)
+        }
+
+        Opcode::MovImmediateRegisterMemory => {
+            let mut bytes = vec![0xc7];
+            bytes.extend(modrm(op1, 0b000, Disp8::Allow)?);
+            bytes.extend(imm16(op2)?);
+            Ok(bytes)
+        }
+        Opcode::MovImmediateRegisterMemoryByte => {
+            let mut bytes = vec![0xc6];
+            bytes.extend(modrm(op1, 0b000, Disp8::Allow)?);
+            bytes.push(imm8(op2)?);
+            Ok(bytes)
+        }
+        Opcode::MovImmediate => {
+            let (reg, w) = register(op1)?;
+            let Some(Operand::Immediate(imm)) = op2 else {
+                return unsupported();
+            };
+            let value = u16::from(*imm);
+            if w == 1 {
+                Ok(vec![0xb8 | reg, value as u8, (value >> 8) as u8])
+            } else {
+                Ok(vec![0xb0 | reg, value as u8])
+            }
+        }
+        Opcode::MovMemoryToAccumulator => match op2 {
+            Some(Operand::EffectiveAddress(EA::DispOnly(Disp(addr)))) => {
+                let addr = word(*addr)?;
+                Ok(vec![0xa1, addr as u8, (addr >> 8) as u8])
+            }
+            _ => unsupported(),
+        },
+
+        // test r/m, immediate はs bitを持たず，mod = 01 のbyte版だけtest byteと表示される
+        Opcode::TestImmediate => match op2 {
+            Some(Operand::Immediate(ImmediateValue::I16(..))) => {
+                let mut bytes = vec![0xf7];
+                bytes.extend(modrm(op1, 0b000, Disp8::Allow)?);
+                bytes.extend(imm16(op2)?);
+                Ok(bytes)
+            }
+            _ => {
+                let mut bytes = vec![0xf6];
+                bytes.extend(modrm(op1, 0b000, Disp8::Forbid)?);
+                bytes.push(imm8(op2)?);
+                Ok(bytes)
+            }
+        },
+        Opcode::TestImmediateByte => {
+            let mut bytes = vec![0xf6];
+            bytes.extend(modrm(op1, 0b000, Disp8::Force)?);
+            bytes.push(imm8(op2)?);
+            Ok(bytes)
+        }
+
+        Opcode::AddImmediateToAccumulator => accumulator(0x04, op1, op2),
+        Opcode::OrImmediateFromAccumulator => accumulator(0x0c, op1, op2),
+        Opcode::AndImmediateFromAccumulator => accumulator(0x24, op1, op2),
+        Opcode::SubImmediateFromAccumulator => accumulator(0x2c, op1, op2),
+        Opcode::CmpImmediateFromAccumulator => accumulator(0x3c, op1, op2),
+        Opcode::TestImmediateDataAndAccumulator => accumulator(0xa8, op1, op2),
+
+        Opcode::Rol => shift(0b000, op1, op2),
+        Opcode::Ror => shift(0b001, op1, op2),
+        Opcode::Rcl => shift(0b010, op1, op2),
+        Opcode::Rcr => shift(0b011, op1, op2),
+        Opcode::Shl => shift(0b100, op1, op2),
+        Opcode::Shr => shift(0b101, op1, op2),
+        Opcode::Sar => shift(0b111, op1, op2),
+
+        Opcode::Not => register_memory(0xf6, 0b010, op1),
+        Opcode::Neg => register_memory(0xf6, 0b011, op1),
+        Opcode::Mul => register_memory(0xf6, 0b100, op1),
+        Opcode::Imul => register_memory(0xf6, 0b101, op1),
+        Opcode::Div => register_memory(0xf6, 0b110, op1),
+        Opcode::Idiv => register_memory(0xf6, 0b111, op1),
+        Opcode::IncRegisterMemory => register_memory(0xfe, 0b000, op1),
+        Opcode::DecRegisterMemory => register_memory(0xfe, 0b001, op1),
+
+        Opcode::TestRegisterMemoryAndRegister => register_with_register(0x84, op1, op2),
+        Opcode::XchgRegisterMemoryWithRegister => register_with_register(0x86, op1, op2),
+
+        Opcode::PushRegMem => word_register_memory(0xff, 0b110, op1),
+        Opcode::PopRegMem => word_register_memory(0x8f, 0b000, op1),
+        Opcode::JmpIndirectWithinSegment => word_register_memory(0xff, 0b100, op1),
+
+        // callは即値なら直接，それ以外はレジスタ/メモリ経由の間接呼び出し
+        Opcode::CallWithinDirect => match op1 {
+            Some(Operand::Immediate(target)) => near(0xe8, *target, address),
+            _ => word_register_memory(0xff, 0b010, op1),
+        },
+        Opcode::JmpDirectWithinSegment => near(0xe9, immediate(op1)?, address),
+
+        Opcode::IncRegister => reg_series(0x40, op1),
+        Opcode::DecRegister => reg_series(0x48, op1),
+        Opcode::PushReg => reg_series(0x50, op1),
+        Opcode::PopReg => reg_series(0x58, op1),
+        Opcode::XchgRegisterWithAccumulator => match op2 {
+            Some(Operand::Register(Register::Reg16(Reg16::AX))) => reg_series(0x90, op1),
+            _ => unsupported(),
+        },
+
+        Opcode::Lea => {
+            let (reg, w) = register(op1)?;
+            if w != 1 {
+                return unsupported();
+            }
+            let mut bytes = vec![0x8d];
+            bytes.extend(modrm(op2, reg, Disp8::Allow)?);
+            Ok(bytes)
+        }
+
+        Opcode::JmpDirectWithinSegmentShort => short(0xeb, immediate(op1)?, address),
+        Opcode::Jo => short(0x70, immediate(op1)?, address),
+        Opcode::Jno => short(0x71, immediate(op1)?, address),
+        Opcode::Jb => short(0x72, immediate(op1)?, address),
+        Opcode::Jnb => short(0x73, immediate(op1)?, address),
+        Opcode::Je => short(0x74, immediate(op1)?, address),
+        Opcode::Jne => short(0x75, immediate(op1)?, address),
+        Opcode::Jbe => short(0x76, immediate(op1)?, address),
+        Opcode::Jnbe => short(0x77, immediate(op1)?, address),
+        Opcode::Js => short(0x78, immediate(op1)?, address),
+        Opcode::Jns => short(0x79, immediate(op1)?, address),
+        Opcode::Jp => short(0x7a, immediate(op1)?, address),
+        Opcode::Jnp => short(0x7b, immediate(op1)?, address),
+        Opcode::Jl => short(0x7c, immediate(op1)?, address),
+        Opcode::Jnl => short(0x7d, immediate(op1)?, address),
+        Opcode::Jle => short(0x7e, immediate(op1)?, address),
+        Opcode::Jnle => short(0x7f, immediate(op1)?, address),
+        Opcode::Loopnz => short(0xe0, immediate(op1)?, address),
+        Opcode::Loopz => short(0xe1, immediate(op1)?, address),
+        Opcode::Loop => short(0xe2, immediate(op1)?, address),
+        Opcode::Jcxz => short(0xe3, immediate(op1)?, address),
+
+        Opcode::RetWithinSegAddingImmedToSp => {
+            let mut bytes = vec![0xc2];
+            bytes.extend(imm16(op1)?);
+            Ok(bytes)
+        }
+        Opcode::IntTypeSpecified => Ok(vec![0xcd, imm8(op1)?]),
+
+        Opcode::InFixedPort => Ok(vec![0xe4 | accumulator_w(op1)?, imm8(op2)?]),
+        Opcode::OutFixedPort => Ok(vec![0xe6 | accumulator_w(op1)?, imm8(op2)?]),
+        Opcode::InVariablePort => Ok(vec![0xec | accumulator_w(op1)?]),
+        Opcode::OutVariablePort => Ok(vec![0xee | accumulator_w(op1)?]),
+
+        Opcode::RepMovsb => Ok(vec![0xf3, 0xa4]),
+        Opcode::RepMovsw => Ok(vec![0xf3, 0xa5]),
+        Opcode::RepStosb => Ok(vec![0xf3, 0xaa]),
+        Opcode::RepScasb => Ok(vec![0xf2, 0xae]),
+        Opcode::CompsByte => Ok(vec![0xa6]),
+
+        Opcode::RetWithinSegment => Ok(vec![0xc3]),
+        Opcode::Cbw => Ok(vec![0x98]),
+        Opcode::Cwd => Ok(vec![0x99]),
+        Opcode::Hlt => Ok(vec![0xf4]),
+        Opcode::Cmc => Ok(vec![0xf5]),
+        Opcode::Clc => Ok(vec![0xf8]),
+        Opcode::Cli => Ok(vec![0xfa]),
+        Opcode::Sti => Ok(vec![0xfb]),
+        Opcode::Cld => Ok(vec![0xfc]),
+        Opcode::Std => Ok(vec![0xfd]),
+
+        _ => unsupported(),
+    }
+}
+
+// (レジスタ番号, w)
+fn register(operand: Option<&Operand>) -> Result<(u8, u8), String> {
+    match operand {
+        Some(Operand::Register(Register::Reg16(reg))) if *reg != Reg16::None => Ok((*reg as u8, 1)),
+        Some(Operand::Register(Register::Reg8(reg))) if *reg != Reg8::None => Ok((*reg as u8, 0)),
+        Some(operand) => Err(format!("expected a register, found `{operand:?}`")),
+        None => Err("missing register operand".to_owned()),
+    }
+}
+
+fn immediate(operand: Option<&Operand>) -> Result<ImmediateValue, String> {
+    match operand {
+        Some(Operand::Immediate(imm)) => Ok(*imm),
+        Some(operand) => Err(format!("expected an immediate, found `{operand:?}`")),
+        None => Err("missing immediate operand".to_owned()),
+    }
+}
+
+fn imm8(operand: Option<&Operand>) -> Result<u8, String> {
+    Ok(u16::from(immediate(operand)?) as u8)
+}
+
+fn imm16(operand: Option<&Operand>) -> Result<[u8; 2], String> {
+    Ok(u16::from(immediate(operand)?).to_le_bytes())
+}
+
+fn word(value: isize) -> Result<u16, String> {
+    u16::try_from(value).map_err(|_| format!("address {value:x} is out of range"))
+}
+
+// mod reg r/m と，それに続くdisplacement
+fn modrm(operand: Option<&Operand>, reg: u8, disp8: Disp8) -> Result<Vec<u8>, String> {
+    let ea = match operand {
+        Some(Operand::EffectiveAddress(ea)) => ea,
+        Some(Operand::Register(_)) => {
+            let (rm, _) = register(operand)?;
+            return Ok(vec![0b11 << 6 | reg << 3 | rm]);
+        }
+        Some(operand) => {
+            return Err(format!(
+                "expected a register or memory, found `{operand:?}`"
+            ))
+        }
+        None => return Err("missing register or memory operand".to_owned()),
+    };
+
+    let (rm, disp) = match ea {
+        EA::BxSi(Disp(d)) => (0b000, *d),
+        EA::BxDi(Disp(d)) => (0b001, *d),
+        EA::BpSi(Disp(d)) => (0b010, *d),
+        EA::BpDi(Disp(d)) => (0b011, *d),
+        EA::Si(Disp(d)) => (0b100, *d),
+        EA::Di(Disp(d)) => (0b101, *d),
+        EA::Bp(Disp(d)) => (0b110, *d),
+        EA::Bx(Disp(d)) => (0b111, *d),
+        EA::DispOnly(Disp(addr)) => {
+            let addr = word(*addr)?;
+            return Ok(vec![reg << 3 | 0b110, addr as u8, (addr >> 8) as u8]);
+        }
+    };
+
+    let disp =
+        i16::try_from(disp).map_err(|_| format!("displacement in `{ea:?}` is out of range"))?;
+    let fits_i8 = i8::try_from(disp).is_ok();
+
+    // mod = 00, r/m = 110 は直接アドレスなので，[bp]はdisp 0として表す
+    Ok(match disp8 {
+        Disp8::Force if fits_i8 => vec![0b01 << 6 | reg << 3 | rm, disp as u8],
+        Disp8::Force => return Err(format!("displacement in `{ea:?}` does not fit in a byte")),
+        _ if disp == 0 && rm != 0b110 => vec![reg << 3 | rm],
+        Disp8::Allow if fits_i8 => vec![0b01 << 6 | reg << 3 | rm, disp as u8],
+        _ => {
+            let [low, high] = disp.to_le_bytes();
+            vec![0b10 << 6 | reg << 3 | rm, low, high]
+        }
+    })
+}
+
+// Register/Memory with Register to Either
+// ------dw, mod reg r/m
+fn reg_either(base: u8, op1: Option<&Operand>, op2: Option<&Operand>) -> Result<Vec<u8>, String> {
+    let (d, reg, rm) = match (op1, op2) {
+        (Some(Operand::Register(_)), Some(Operand::EffectiveAddress(_))) => (1, op1, op2),
+        _ => (0, op2, op1),
+    };
+    let (reg, w) = register(reg)?;
+
+    let mut bytes = vec![base | d << 1 | w];
+    bytes.extend(modrm(rm, reg, Disp8::Allow)?);
+    Ok(bytes)
+}
+
+// Immediate to Register/Memory
+// 100000sw, mod --- r/m, data, data if sw = 01
+fn immediate_register_memory(ext: u8, instruction: &Instruction) -> Result<Vec<u8>, String> {
+    let op1 = instruction.operand1.as_ref();
+    let op2 = instruction.operand2.as_ref();
+    let direct = matches!(op1, Some(Operand::EffectiveAddress(EA::DispOnly(_))));
+
+    // 直接アドレスのときは，wによらず即値は1byteで，I8として表示される
+    let first = match (immediate(op2)?, instruction.opcode) {
+        (ImmediateValue::I8(..), Opcode::CmpImmediateByte) => 0x80,
+        (ImmediateValue::I8(..), _) if direct => 0x83,
+        (ImmediateValue::I8(..), _) => 0x80,
+        (ImmediateValue::I16(value, 1), _) if i8::try_from(value).is_ok() => 0x83,
+        (ImmediateValue::I16(..), _) => 0x81,
+        (ImmediateValue::I32(..), _) => {
+            return Err(format!("`{instruction:?}` has no 8086 encoding"))
+        }
+    };
+
+    let mut bytes = vec![first];
+    bytes.extend(modrm(op1, ext, Disp8::Allow)?);
+    if first == 0x81 {
+        bytes.extend(imm16(op2)?);
+    } else {
+        bytes.push(imm8(op2)?);
+    }
+    Ok(bytes)
+}
+
+// Immediate to Accumulator
+// -------w, data, data if w = 1
+fn accumulator(base: u8, op1: Option<&Operand>, op2: Option<&Operand>) -> Result<Vec<u8>, String> {
+    let w = accumulator_w(op1)?;
+    let mut bytes = vec![base | w];
+    if w == 1 {
+        bytes.extend(imm16(op2)?);
+    } else {
+        bytes.push(imm8(op2)?);
+    }
+    Ok(bytes)
+}
+
+fn accumulator_w(operand: Option<&Operand>) -> Result<u8, String> {
+    match operand {
+        Some(Operand::Register(Register::Reg16(Reg16::AX))) => Ok(1),
+        Some(Operand::Register(Register::Reg8(Reg8::AL))) => Ok(0),
+        _ => Err("expected the accumulator (ax or al)".to_owned()),
+    }
+}
+
+// メモリのときはデコーダがwを区別しないので，VMと同じくwordとして扱う
+fn operand_w(operand: Option<&Operand>) -> Result<u8, String> {
+    match operand {
+        Some(Operand::Register(_)) => Ok(register(operand)?.1),
+        _ => Ok(1),
+    }
+}
+
+// -------w, mod ext r/m
+fn register_memory(base: u8, ext: u8, operand: Option<&Operand>) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![base | operand_w(operand)?];
+    bytes.extend(modrm(operand, ext, Disp8::Allow)?);
+    Ok(bytes)
+}
+
+// wを持たないword命令 (push, pop, call, jmp)
+fn word_register_memory(base: u8, ext: u8, operand: Option<&Operand>) -> Result<Vec<u8>, String> {
+    if operand_w(operand)? != 1 {
+        return Err("expected a 16-bit operand".to_owned());
+    }
+    let mut bytes = vec![base];
+    bytes.extend(modrm(operand, ext, Disp8::Allow)?);
+    Ok(bytes)
+}
+
+// shl/sal | shr | sar | rol | ror | rcl | rcr
+// 110100vw, mod ext r/m
+fn shift(ext: u8, op1: Option<&Operand>, op2: Option<&Operand>) -> Result<Vec<u8>, String> {
+    let v = match op2 {
+        Some(Operand::Register(Register::Reg8(Reg8::CL))) => 1,
+        Some(Operand::Immediate(imm)) if u16::from(*imm) == 1 => 0,
+        _ => return Err("shift count must be 1 or cl".to_owned()),
+    };
+
+    let mut bytes = vec![0xd0 | v << 1 | operand_w(op1)?];
+    bytes.extend(modrm(op1, ext, Disp8::Allow)?);
+    Ok(bytes)
+}
+
+// test, xchg: r/mが先，regが後
+fn register_with_register(
+    base: u8,
+    op1: Option<&Operand>,
+    op2: Option<&Operand>,
+) -> Result<Vec<u8>, String> {
+    let (reg, w) = register(op2)?;
+    let mut bytes = vec![base | w];
+    bytes.extend(modrm(op1, reg, Disp8::Allow)?);
+    Ok(bytes)
+}
+
+// XXXXX_reg
+fn reg_series(base: u8, operand: Option<&Operand>) -> Result<Vec<u8>, String> {
+    match register(operand)? {
+        (reg, 1) => Ok(vec![base | reg]),
+        _ => Err("expected a 16-bit register".to_owned()),
+    }
+}
+
+// 分岐先は次の命令のアドレスからの相対値になる
+fn short(opcode: u8, target: ImmediateValue, address: u16) -> Result<Vec<u8>, String> {
+    let target = u16::from(target);
+    let disp = target.wrapping_sub(address.wrapping_add(2)) as i16;
+
+    match i8::try_from(disp) {
+        Ok(disp) => Ok(vec![opcode, disp as u8]),
+        Err(_) => Err(format!("jump target {target:04x} is out of short range")),
+    }
+}
+
+fn near(opcode: u8, target: ImmediateValue, address: u16) -> Result<Vec<u8>, String> {
+    let disp = u16::from(target).wrapping_sub(address.wrapping_add(3));
+    let [low, high] = disp.to_le_bytes();
+
+    Ok(vec![opcode, low, high])
+}
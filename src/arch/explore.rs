@@ -0,0 +1,121 @@
+use super::asm::{Assembly, Flow};
+use super::decode::Decoder;
+use super::opcode::Opcode;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+// 1行のdbに並べるバイト数．逆アセンブル結果の機械語の欄に収まる数
+const DATA_ROW: usize = 6;
+
+// テキストセグメントのうち，命令として辿れた部分とそれ以外
+#[derive(Clone)]
+pub enum Chunk {
+    Code(Assembly),
+    Data { address: u16, bytes: Vec<u8> },
+}
+
+impl Chunk {
+    pub fn address(&self) -> u16 {
+        match self {
+            Chunk::Code(asm) => asm.address,
+            Chunk::Data { address, .. } => *address,
+        }
+    }
+
+    pub fn as_code(&self) -> Option<&Assembly> {
+        match self {
+            Chunk::Code(asm) => Some(asm),
+            Chunk::Data { .. } => None,
+        }
+    }
+}
+
+// "013d: 000000        db 00, 00, 00"．アセンブラでそのまま組み立て直せる
+impl Debug for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chunk::Code(asm) => write!(f, "{asm:?}"),
+            Chunk::Data { address, bytes } => {
+                let code: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                let values: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                write!(f, "{address:04x}: {code:<14}db {}", values.join(", "))
+            }
+        }
+    }
+}
+
+// 線形に読んだ命令をそのままChunkにする
+pub fn linear(asm: Vec<Assembly>) -> Vec<Chunk> {
+    asm.into_iter().map(Chunk::Code).collect()
+}
+
+// entriesから分岐と呼び出しを辿ってデコードする(recursive descent)．
+// 辿れなかったバイトと(undefined)はdbにする．既にデコードした命令と重なる命令は採らない
+pub fn explore(bytes: &[u8], base: u16, entries: impl IntoIterator<Item = u16>) -> Vec<Chunk> {
+    let mut code: BTreeMap<u16, Assembly> = BTreeMap::new();
+    let mut covered = vec![false; bytes.len()];
+    let mut pending: Vec<u16> = entries.into_iter().collect();
+    pending.reverse();
+
+    while let Some(start) = pending.pop() {
+        let mut addr = start;
+
+        while let Some(offset) = addr.checked_sub(base).map(usize::from) {
+            if offset >= bytes.len() || covered[offset] {
+                break;
+            }
+
+            let Some(asm) = Decoder::at(bytes, base, addr).decode() else {
+                break;
+            };
+            let range = offset..offset + asm.size;
+            if asm.instruction.opcode == Opcode::Undefined
+                || range.end > bytes.len()
+                || covered[range.clone()].iter().any(|&c| c)
+            {
+                break;
+            }
+            covered[range].fill(true);
+
+            let flow = asm.instruction.flow();
+            let next = addr.wrapping_add(asm.size as u16);
+            code.insert(addr, asm);
+
+            match flow {
+                Flow::Next | Flow::Call(None) => addr = next,
+                Flow::Call(Some(target)) | Flow::Branch(target) => {
+                    pending.push(target);
+                    addr = next;
+                }
+                Flow::Jump(Some(target)) => {
+                    pending.push(target);
+                    break;
+                }
+                Flow::Jump(None) | Flow::Return | Flow::Halt => break,
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u16);
+        if let Some(asm) = code.remove(&address) {
+            offset += asm.size;
+            chunks.push(Chunk::Code(asm));
+            continue;
+        }
+
+        let end = (offset..bytes.len())
+            .take(DATA_ROW)
+            .find(|&o| covered[o])
+            .unwrap_or(bytes.len().min(offset + DATA_ROW));
+        chunks.push(Chunk::Data {
+            address,
+            bytes: bytes[offset..end].to_vec(),
+        });
+        offset = end;
+    }
+
+    chunks
+}
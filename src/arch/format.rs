@@ -0,0 +1,446 @@
+use super::asm::Assembly;
+use super::explore::Chunk;
+use super::opcode::Opcode;
+use super::operand::{Disp, ImmediateValue, Operand, EA};
+use super::reg::Register;
+use crate::json::Json;
+
+// 逆アセンブル結果の書式．`--syntax`で選ぶ
+pub const SYNTAXES: [&str; 4] = ["legacy", "nasm", "att", "json"];
+
+pub trait AsmFormatter {
+    // 出力の先頭に置く行
+    fn prologue(&self, _base: u16) -> Option<String> {
+        None
+    }
+
+    // targetは飛び先のアドレスの代わりに表示するラベル
+    fn instruction(&self, asm: &Assembly, target: Option<&str>) -> String;
+
+    // 命令として辿れなかったバイト
+    fn data(&self, address: u16, bytes: &[u8]) -> String;
+
+    fn label(&self, name: &str) -> String {
+        format!("{}:", self.symbol(name))
+    }
+
+    // ラベルとして使える名前にする
+    fn symbol(&self, name: &str) -> String {
+        name.to_owned()
+    }
+
+    fn comment(&self, text: &str) -> String {
+        format!("; {text}")
+    }
+
+    // 1行に1つの値を出力する書式では，ラベル，コメント，空行を出力しない
+    fn is_structured(&self) -> bool {
+        false
+    }
+
+    fn chunk(&self, chunk: &Chunk, target: Option<&str>) -> String {
+        match chunk {
+            Chunk::Code(asm) => self.instruction(asm, target),
+            Chunk::Data { address, bytes } => self.data(*address, bytes),
+        }
+    }
+}
+
+pub fn formatter(syntax: &str) -> Option<Box<dyn AsmFormatter>> {
+    let formatter: Box<dyn AsmFormatter> = match syntax {
+        "legacy" => Box::new(Legacy),
+        "nasm" => Box::new(Nasm),
+        "att" => Box::new(Att),
+        "json" => Box::new(JsonLines),
+        _ => return None,
+    };
+    Some(formatter)
+}
+
+// 既存VMと同じ書式
+pub struct Legacy;
+
+impl AsmFormatter for Legacy {
+    fn instruction(&self, asm: &Assembly, target: Option<&str>) -> String {
+        asm.render(target)
+    }
+
+    fn data(&self, address: u16, bytes: &[u8]) -> String {
+        format!(
+            "{:?}",
+            Chunk::Data {
+                address,
+                bytes: bytes.to_vec(),
+            }
+        )
+    }
+}
+
+// nasm -f binでそのまま組み立て直せる書式
+pub struct Nasm;
+
+impl AsmFormatter for Nasm {
+    fn prologue(&self, base: u16) -> Option<String> {
+        Some(format!("bits 16\ncpu 8086\norg 0x{base:x}\n"))
+    }
+
+    fn instruction(&self, asm: &Assembly, target: Option<&str>) -> String {
+        let Some(mnemonic) = mnemonic(asm) else {
+            return self.data(asm.address, &asm.bytes());
+        };
+        let target = target.map(|t| self.symbol(t));
+        let instruction = &asm.instruction;
+
+        let mut operands: Vec<String> = operands(asm)
+            .iter()
+            .map(|operand| match (operand, &target) {
+                (Operand::Immediate(_), Some(target)) if instruction.target().is_some() => {
+                    target.clone()
+                }
+                (Operand::Immediate(imm), _) if instruction.target().is_some() => {
+                    format!("0x{:x}", u16::from(*imm))
+                }
+                (Operand::Immediate(imm), _) => number(immediate(asm, imm)),
+                (Operand::Register(reg), _) => format!("{reg:?}"),
+                (Operand::EffectiveAddress(ea), _) => {
+                    let size = match memory_size(asm) {
+                        Some(8) => "byte ",
+                        Some(_) => "word ",
+                        None => "",
+                    };
+                    format!("{size}[{}]", intel_ea(ea))
+                }
+            })
+            .collect();
+
+        let mnemonic = match instruction.opcode {
+            Opcode::JmpDirectWithinSegment => "jmp near",
+            Opcode::JmpDirectWithinSegmentShort => "jmp short",
+            _ => &mnemonic,
+        };
+        if operands.is_empty() {
+            return format!("    {mnemonic}");
+        }
+        if is_out(instruction.opcode) {
+            operands.reverse();
+        }
+        format!("    {mnemonic} {}", operands.join(", "))
+    }
+
+    fn data(&self, _address: u16, bytes: &[u8]) -> String {
+        let values: Vec<String> = bytes.iter().map(|b| format!("0x{b:02x}")).collect();
+        format!("    db {}", values.join(", "))
+    }
+
+    // nasmでは.で始まる名前は直前のラベルのローカルラベルになる
+    fn symbol(&self, name: &str) -> String {
+        match name.strip_prefix('.') {
+            Some(rest) => format!("L.{rest}"),
+            None => name.to_owned(),
+        }
+    }
+}
+
+// GNU asのAT&T構文
+pub struct Att;
+
+impl AsmFormatter for Att {
+    fn instruction(&self, asm: &Assembly, target: Option<&str>) -> String {
+        let Some(mnemonic) = mnemonic(asm) else {
+            return self.data(asm.address, &asm.bytes());
+        };
+        let instruction = &asm.instruction;
+        let mnemonic = match instruction.opcode {
+            Opcode::Cbw => "cbtw",
+            Opcode::Cwd => "cwtd",
+            _ => &mnemonic,
+        };
+
+        let branch = instruction.target().is_some();
+        let indirect = matches!(
+            instruction.opcode,
+            Opcode::CallWithinDirect | Opcode::JmpIndirectWithinSegment
+        ) && !branch;
+        let variable_port = matches!(
+            instruction.opcode,
+            Opcode::InVariablePort | Opcode::OutVariablePort
+        );
+
+        let mut operands: Vec<String> = operands(asm)
+            .iter()
+            .map(|operand| match operand {
+                Operand::Immediate(_) if branch => match target {
+                    Some(target) => target.to_owned(),
+                    None => format!("0x{:x}", u16::from(*instruction_imm(asm))),
+                },
+                Operand::Immediate(imm) => format!("${}", number(immediate(asm, imm))),
+                Operand::Register(reg) if variable_port && *reg == dx() => "(%dx)".to_owned(),
+                Operand::Register(reg) if indirect => format!("*%{reg:?}"),
+                Operand::Register(reg) => format!("%{reg:?}"),
+                Operand::EffectiveAddress(ea) if indirect => format!("*{}", att_ea(ea)),
+                Operand::EffectiveAddress(ea) => att_ea(ea),
+            })
+            .collect();
+
+        // 大きさがレジスタから決まらない場合だけ接尾辞を付ける
+        let suffix = match memory_size(asm) {
+            Some(8) => "b",
+            Some(_) => "w",
+            None => "",
+        };
+        if operands.is_empty() {
+            return format!("    {mnemonic}");
+        }
+        if !is_out(instruction.opcode) {
+            operands.reverse();
+        }
+        format!("    {mnemonic}{suffix} {}", operands.join(", "))
+    }
+
+    fn data(&self, _address: u16, bytes: &[u8]) -> String {
+        let values: Vec<String> = bytes.iter().map(|b| format!("0x{b:02x}")).collect();
+        format!("    .byte {}", values.join(", "))
+    }
+
+    fn comment(&self, text: &str) -> String {
+        format!("# {text}")
+    }
+}
+
+// 1行に1命令ずつのJSON
+pub struct JsonLines;
+
+impl JsonLines {
+    pub fn value(&self, chunk: &Chunk, target: Option<&str>) -> Json {
+        let asm = match chunk {
+            Chunk::Code(asm) if mnemonic(asm).is_some() => asm,
+            _ => {
+                let (address, bytes) = match chunk {
+                    Chunk::Code(asm) => (asm.address, asm.bytes()),
+                    Chunk::Data { address, bytes } => (*address, bytes.clone()),
+                };
+                return Json::object([
+                    ("address", address.into()),
+                    ("size", bytes.len().into()),
+                    ("bytes", Json::Str(hex(&bytes))),
+                    ("data", true.into()),
+                ]);
+            }
+        };
+
+        let instruction = &asm.instruction;
+        let list = operands(asm);
+        let operands = list.iter().map(|operand| match operand {
+            Operand::Register(reg) => Json::object([
+                ("type", Json::Str("register".to_owned())),
+                ("name", Json::Str(format!("{reg:?}"))),
+                ("size", register_size(reg).into()),
+            ]),
+            Operand::Immediate(imm) if instruction.target().is_some() => Json::object([
+                ("type", Json::Str("address".to_owned())),
+                ("value", u16::from(*imm).into()),
+            ]),
+            Operand::Immediate(imm) => Json::object([
+                ("type", Json::Str("immediate".to_owned())),
+                ("value", immediate(asm, imm).into()),
+            ]),
+            Operand::EffectiveAddress(ea) => {
+                let (base, index, Disp(disp)) = parts(ea);
+                let name = |r: Option<&str>| r.map_or(Json::Null, |r| Json::Str(r.to_owned()));
+                Json::object([
+                    ("type", Json::Str("memory".to_owned())),
+                    ("base", name(base)),
+                    ("index", name(index)),
+                    ("disp", (*disp as i64).into()),
+                    ("size", memory_size(asm).unwrap_or(16).into()),
+                ])
+            }
+        });
+
+        let mut pairs = vec![
+            ("address", asm.address.into()),
+            ("size", asm.size.into()),
+            ("bytes", Json::Str(hex(&asm.bytes()))),
+            ("mnemonic", Json::Str(mnemonic(asm).unwrap())),
+            ("operands", Json::Array(operands.collect())),
+            ("text", Json::Str(format!("{instruction:?}"))),
+        ];
+        if let Some(target) = target {
+            pairs.push(("target", Json::Str(target.to_owned())));
+        }
+        Json::object(pairs)
+    }
+}
+
+impl AsmFormatter for JsonLines {
+    fn instruction(&self, asm: &Assembly, target: Option<&str>) -> String {
+        self.value(&Chunk::Code(asm.clone()), target).to_string()
+    }
+
+    fn data(&self, address: u16, bytes: &[u8]) -> String {
+        let chunk = Chunk::Data {
+            address,
+            bytes: bytes.to_vec(),
+        };
+        self.value(&chunk, None).to_string()
+    }
+
+    fn is_structured(&self) -> bool {
+        true
+    }
+}
+
+// 書式によらない命令名．(undefined)ならNone
+fn mnemonic(asm: &Assembly) -> Option<String> {
+    let mnemonic = match asm.instruction.opcode {
+        Opcode::Undefined => return None,
+        // xchg ax, axの別名
+        Opcode::XchgRegisterWithAccumulator if asm.bytes() == [0x90] => "nop",
+        opcode => opcode.mnemonic(),
+    };
+    Some(mnemonic.to_owned())
+}
+
+fn operands(asm: &Assembly) -> Vec<Operand> {
+    if mnemonic(asm).as_deref() == Some("nop") {
+        return Vec::new();
+    }
+    let instruction = &asm.instruction;
+    [&instruction.operand1, &instruction.operand2]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+fn is_out(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode::OutFixedPort | Opcode::OutVariablePort)
+}
+
+fn dx() -> Register {
+    Register::Reg16(super::reg::Reg16::DX)
+}
+
+fn instruction_imm(asm: &Assembly) -> &ImmediateValue {
+    match &asm.instruction.operand1 {
+        Some(Operand::Immediate(imm)) => imm,
+        _ => unreachable!("branch without an immediate target"),
+    }
+}
+
+fn register_size(reg: &Register) -> usize {
+    match reg {
+        Register::Reg8(_) => 8,
+        _ => 16,
+    }
+}
+
+// メモリオペランドの大きさをレジスタから決められない場合の大きさ．
+// 機械語の先頭バイトのwビットで決まる
+fn memory_size(asm: &Assembly) -> Option<usize> {
+    let instruction = &asm.instruction;
+    let has_memory = operands(asm)
+        .iter()
+        .any(|o| matches!(o, Operand::EffectiveAddress(_)));
+    let has_register = operands(asm)
+        .iter()
+        .any(|o| matches!(o, Operand::Register(_)));
+    let implied = matches!(
+        instruction.opcode,
+        Opcode::Lea
+            | Opcode::Lds
+            | Opcode::Les
+            | Opcode::CallWithinDirect
+            | Opcode::JmpIndirectWithinSegment
+    );
+    if !has_memory || has_register || implied {
+        return None;
+    }
+
+    Some(if asm.bytes()[0] & 1 == 0 { 8 } else { 16 })
+}
+
+// 即値を符号付きの値にする．8bitの演算，割り込み番号，ポート番号ではそのバイトの値
+fn immediate(asm: &Assembly, imm: &ImmediateValue) -> i64 {
+    let byte_operation = match &asm.instruction.operand1 {
+        _ if matches!(
+            asm.instruction.opcode,
+            Opcode::IntTypeSpecified | Opcode::InFixedPort | Opcode::OutFixedPort
+        ) =>
+        {
+            true
+        }
+        Some(Operand::Register(Register::Reg8(_))) => true,
+        Some(Operand::EffectiveAddress(_)) => memory_size(asm) == Some(8),
+        _ => false,
+    };
+
+    match imm {
+        ImmediateValue::I8(v, _) if byte_operation => *v as u8 as i64,
+        ImmediateValue::I8(v, _) => *v as i64,
+        ImmediateValue::I16(v, _) => *v as i64,
+        ImmediateValue::I32(v, _) => *v as i64,
+    }
+}
+
+// 10未満は10進数，それ以外は0x付きの16進数
+fn number(value: i64) -> String {
+    match value {
+        -9..=9 => format!("{value}"),
+        v if v < 0 => format!("-0x{:x}", -v),
+        v => format!("0x{v:x}"),
+    }
+}
+
+fn parts(ea: &EA) -> (Option<&'static str>, Option<&'static str>, &Disp) {
+    match ea {
+        EA::BxSi(d) => (Some("bx"), Some("si"), d),
+        EA::BxDi(d) => (Some("bx"), Some("di"), d),
+        EA::BpSi(d) => (Some("bp"), Some("si"), d),
+        EA::BpDi(d) => (Some("bp"), Some("di"), d),
+        EA::Si(d) => (Some("si"), None, d),
+        EA::Di(d) => (Some("di"), None, d),
+        EA::Bp(d) => (Some("bp"), None, d),
+        EA::Bx(d) => (Some("bx"), None, d),
+        EA::DispOnly(d) => (None, None, d),
+    }
+}
+
+// "bx+si+0x4"
+fn intel_ea(ea: &EA) -> String {
+    let (base, index, Disp(disp)) = parts(ea);
+    let mut s = [base, index]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("+");
+    match (*disp, s.is_empty()) {
+        (d, true) => s = format!("0x{:x}", d as u16),
+        (0, false) => (),
+        (d, false) if d < 0 => s.push_str(&number(d as i64)),
+        (d, false) => s.push_str(&format!("+{}", number(d as i64))),
+    }
+    s
+}
+
+// "0x4(%bx,%si)"
+fn att_ea(ea: &EA) -> String {
+    let (base, index, Disp(disp)) = parts(ea);
+    let Some(base) = base else {
+        return format!("0x{:x}", *disp as u16);
+    };
+
+    let disp = if *disp == 0 {
+        String::new()
+    } else {
+        number(*disp as i64)
+    };
+    match index {
+        Some(index) => format!("{disp}(%{base},%{index})"),
+        None => format!("{disp}(%{base})"),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,161 @@
+use super::asm::{Assembly, Flow};
+use super::decode::Decoder;
+use super::opcode::Opcode;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    // 次のブロックへそのまま進む．条件付きジャンプが成立しなかった場合も含む
+    Fallthrough,
+    Conditional,
+    Unconditional,
+    Call,
+    Return,
+}
+
+impl EdgeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Conditional => "conditional",
+            EdgeKind::Unconditional => "unconditional",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+        }
+    }
+}
+
+// toがNoneなのは，飛び先がレジスタやメモリで決まるものとret
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: u16,
+    pub to: Option<u16>,
+    pub kind: EdgeKind,
+}
+
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<Assembly>,
+}
+
+impl Block {
+    // ブロックの直後のアドレス
+    pub fn end(&self) -> u16 {
+        let last = self.instructions.last().unwrap();
+        last.address.wrapping_add(last.size as u16)
+    }
+
+    // 最後の命令が分岐でなく，次の命令へ進む
+    fn falls_through(&self) -> bool {
+        let last = self.instructions.last().unwrap();
+        matches!(last.instruction.flow(), Flow::Next | Flow::Call(_))
+    }
+}
+
+pub struct Function {
+    pub name: String,
+    pub start: u16,
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+}
+
+impl Function {
+    // 関数の先頭から，関数の中への分岐だけを辿ってブロックに分ける．
+    // 他の関数の先頭への分岐(末尾呼び出し)と呼び出しは関数の外への辺にする
+    pub fn build(text: &[u8], start: u16, name: String, functions: &BTreeSet<u16>) -> Self {
+        let outside = |addr: u16| addr != start && functions.contains(&addr);
+
+        let mut code: BTreeMap<u16, Assembly> = BTreeMap::new();
+        let mut leaders = BTreeSet::from([start]);
+        let mut pending = vec![start];
+
+        while let Some(mut addr) = pending.pop() {
+            while !code.contains_key(&addr) && !outside(addr) {
+                let Some(asm) = Decoder::at(text, 0, addr).decode() else {
+                    break;
+                };
+                if asm.instruction.opcode == Opcode::Undefined {
+                    break;
+                }
+
+                let flow = asm.instruction.flow();
+                let next = addr.wrapping_add(asm.size as u16);
+                code.insert(addr, asm);
+
+                match flow {
+                    Flow::Next | Flow::Call(_) => addr = next,
+                    Flow::Branch(target) => {
+                        leaders.extend([target, next]);
+                        pending.push(target);
+                        addr = next;
+                    }
+                    Flow::Jump(target) => {
+                        if let Some(target) = target {
+                            leaders.insert(target);
+                            pending.push(target);
+                        }
+                        break;
+                    }
+                    Flow::Return | Flow::Halt => break,
+                }
+            }
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        for (addr, asm) in code {
+            match blocks.last_mut() {
+                Some(block)
+                    if block.end() == addr && !leaders.contains(&addr) && block.falls_through() =>
+                {
+                    block.instructions.push(asm)
+                }
+                _ => blocks.push(Block {
+                    start: addr,
+                    instructions: vec![asm],
+                }),
+            }
+        }
+
+        let mut edges = Vec::new();
+        for block in &blocks {
+            let from = block.start;
+            for asm in &block.instructions {
+                if let Flow::Call(to) = asm.instruction.flow() {
+                    edges.push(Edge {
+                        from,
+                        to,
+                        kind: EdgeKind::Call,
+                    });
+                }
+            }
+
+            let next = Some(block.end());
+            let (to, kind) = match block.instructions.last().unwrap().instruction.flow() {
+                Flow::Next | Flow::Call(_) => (next, EdgeKind::Fallthrough),
+                Flow::Branch(target) => {
+                    edges.push(Edge {
+                        from,
+                        to: Some(target),
+                        kind: EdgeKind::Conditional,
+                    });
+                    (next, EdgeKind::Fallthrough)
+                }
+                Flow::Jump(target) => (target, EdgeKind::Unconditional),
+                Flow::Return => (None, EdgeKind::Return),
+                Flow::Halt => continue,
+            };
+            edges.push(Edge { from, to, kind });
+        }
+
+        Function {
+            name,
+            start,
+            blocks,
+            edges,
+        }
+    }
+
+    pub fn contains_block(&self, addr: u16) -> bool {
+        self.blocks.iter().any(|b| b.start == addr)
+    }
+}
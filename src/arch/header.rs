@@ -1,6 +1,15 @@
 use std::fmt;
 
 use crate::arch::asm::Assembly;
+use crate::arch::symbol::{Symbol, NAME_SIZE, SYMBOL_SIZE};
+
+pub const HEADER_SIZE: usize = 32;
+pub const MAGIC_NUMBER: [u8; 2] = [0x01, 0x03];
+// 命令とデータを別の空間に置く(separate I&D)
+pub const A_SEP: u8 = 0x20;
+pub const A_I8086: u8 = 0x04;
+// 実行ファイルに割り当てるメモリの既定値．データ，bss，スタックがこの中に収まる
+pub const DEFAULT_TOTAL: u32 = 0x10000;
 
 #[repr(C)]
 #[derive(Clone)]
@@ -24,12 +33,149 @@ pub struct AOutHeader {
     pub syms: u32,        /* size of symbol table */
 }
 
+impl AOutHeader {
+    // ファイル先頭の32バイト．読み込み(BinaryManager::make_header)と同じ並び
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend(self.magic_number);
+        bytes.extend([self.flags, self.cpu_id, self.length, self.unused]);
+        bytes.extend(self.version.to_be_bytes());
+        for n in [
+            self.text_size,
+            self.data_size,
+            self.bss_size,
+            self.entry_point,
+            self.total,
+            self.syms,
+        ] {
+            bytes.extend(n.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+// MINIXのa.outを組み立てる．
+//
+//     let bytes = AOutBuilder::new()
+//         .text(program.text)
+//         .data(program.data)
+//         .bss_size(0x10)
+//         .symbols(symbols)
+//         .build()?;
+#[derive(Default)]
+pub struct AOutBuilder {
+    text: Vec<u8>,
+    data: Vec<u8>,
+    bss_size: u32,
+    entry_point: u32,
+    stack_size: Option<u32>,
+    symbols: Vec<Symbol>,
+}
+
+impl AOutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, text: Vec<u8>) -> Self {
+        self.text = text;
+        self
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn bss_size(mut self, size: u32) -> Self {
+        self.bss_size = size;
+        self
+    }
+
+    pub fn entry_point(mut self, entry_point: u32) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    // 指定しなければtotalはDEFAULT_TOTALになる
+    pub fn stack_size(mut self, size: u32) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    pub fn symbols(mut self, symbols: Vec<Symbol>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn header(&self) -> AOutHeader {
+        let total = match self.stack_size {
+            Some(stack) => self.data.len() as u32 + self.bss_size + stack,
+            None => DEFAULT_TOTAL,
+        };
+
+        AOutHeader {
+            magic_number: MAGIC_NUMBER,
+            flags: A_SEP,
+            cpu_id: A_I8086,
+            length: HEADER_SIZE as u8,
+            unused: 0,
+            version: 0,
+            text_size: self.text.len() as u32,
+            data_size: self.data.len() as u32,
+            bss_size: self.bss_size,
+            entry_point: self.entry_point,
+            total,
+            syms: (self.symbols.len() * SYMBOL_SIZE) as u32,
+        }
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>, String> {
+        let header = self.header();
+
+        // テキストとデータはそれぞれ64KBのセグメントに収まらなければならない
+        if header.text_size > 0x10000 {
+            return Err(format!("text is too large ({} bytes)", header.text_size));
+        }
+        if header.total > 0x10000 || header.data_size + header.bss_size > 0x10000 {
+            return Err(format!(
+                "data, bss and stack do not fit in 64KB ({} bytes)",
+                header.data_size + header.bss_size + self.stack_size.unwrap_or(0)
+            ));
+        }
+        if header.entry_point >= header.text_size.max(1) {
+            return Err(format!(
+                "entry point {:04x} is outside the text",
+                header.entry_point
+            ));
+        }
+        if let Some(symbol) = self.symbols.iter().find(|s| s.name.len() > NAME_SIZE) {
+            return Err(format!(
+                "symbol name `{}` is longer than {NAME_SIZE} bytes",
+                symbol.name
+            ));
+        }
+
+        let mut bytes = header.to_bytes();
+        bytes.extend(&self.text);
+        bytes.extend(&self.data);
+        for symbol in &self.symbols {
+            bytes.extend(symbol.to_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Text {
     pub text: Vec<u8>,
+    #[allow(dead_code)]
     pub offset: usize,
+    #[allow(dead_code)]
     pub size: usize,
+    #[allow(dead_code)]
     pub asm: Vec<Assembly>,
+    #[allow(dead_code)]
     pointer: usize,
 }
 
@@ -46,17 +192,26 @@ impl Text {
     }
 }
 
+// offsetはファイル中の位置
 pub struct Data {
     pub data: Vec<u8>,
+    #[allow(dead_code)]
     pub offset: u32,
+    #[allow(dead_code)]
     pub user_size: u32,
+    #[allow(dead_code)]
     pub all_size: u32,
 }
 
+// offsetはデータセグメント中の位置．user_sizeはbss自体の大きさ，
+// all_sizeはヒープとスタックを含めたbss以降の大きさ
 pub struct Bss {
+    #[allow(dead_code)]
     pub data: Vec<u8>,
+    #[allow(dead_code)]
     pub offset: u32,
     pub user_size: u32,
+    #[allow(dead_code)]
     pub all_size: u32,
 }
 
@@ -65,9 +220,9 @@ fn stringfy_u32(n: u32) -> String {
     format!(
         "{:02x} {:02x} {:02x} {:02x}",
         (n >> 24) as u8,
-        (n >> 16) as u8 & 0xFF,
-        (n >> 8) as u8 & 0xFF,
-        n as u8 & 0xFF
+        ((n >> 16) as u8),
+        ((n >> 8) as u8),
+        (n as u8)
     )
 }
 
@@ -0,0 +1,173 @@
+use super::asm::{Assembly, Flow};
+use super::explore::Chunk;
+use super::format::{AsmFormatter, Legacy};
+use super::symbol::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// 飛び先ごとの参照元のアドレス
+#[derive(Default)]
+struct References {
+    calls: Vec<u16>,
+    jumps: Vec<u16>,
+}
+
+// ラベル付きの逆アセンブル結果．
+//
+//     ; function _main
+//     _main:              ; called from 0x0031
+//     0039: 55            push bp
+//     ...
+//     0048: e84100        call _write
+//
+//     L_004b:             ; jumped from 0x0060
+//     004b: 83c406        add sp, 6
+//
+// 飛び先にはシンボルがあればその名前，なければL_xxxxの名前を付け，
+// 基本ブロックの間に空行を入れる
+pub struct Listing<'a> {
+    symbols: &'a SymbolTable,
+    references: BTreeMap<u16, References>,
+    functions: BTreeSet<u16>,
+    // 命令の先頭アドレス．命令の途中への飛び先にはラベルを付けない
+    starts: BTreeSet<u16>,
+    // 命令の後ろに付けるコメント
+    comments: BTreeMap<u16, String>,
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(chunks: &[Chunk], symbols: &'a SymbolTable, entry: u16) -> Self {
+        let mut references: BTreeMap<u16, References> = BTreeMap::new();
+        let mut functions: BTreeSet<u16> = symbols.functions().map(|s| s.value as u16).collect();
+        functions.insert(entry);
+
+        for a in chunks.iter().filter_map(Chunk::as_code) {
+            match a.instruction.flow() {
+                Flow::Call(Some(target)) => {
+                    references.entry(target).or_default().calls.push(a.address);
+                    functions.insert(target);
+                }
+                Flow::Jump(Some(target)) | Flow::Branch(target) => {
+                    references.entry(target).or_default().jumps.push(a.address);
+                }
+                _ => (),
+            }
+        }
+
+        Listing {
+            symbols,
+            references,
+            functions,
+            starts: chunks
+                .iter()
+                .filter_map(Chunk::as_code)
+                .map(|a| a.address)
+                .collect(),
+            comments: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_comments(mut self, comments: BTreeMap<u16, String>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn label(&self, addr: u16) -> Option<String> {
+        if let Some(symbol) = self.symbols.function_starting_at(addr) {
+            return Some(symbol.name.clone());
+        }
+
+        let referenced = self.references.contains_key(&addr) || self.functions.contains(&addr);
+        (referenced && self.starts.contains(&addr)).then(|| format!("L_{addr:04x}"))
+    }
+
+    pub fn is_function(&self, addr: u16) -> bool {
+        self.functions.contains(&addr)
+    }
+
+    // "called from 0x0031; jumped from 0x0017, 0x001c"
+    pub fn cross_references(&self, addr: u16) -> Option<String> {
+        let references = self.references.get(&addr)?;
+        let list = |addrs: &[u16]| {
+            addrs
+                .iter()
+                .map(|a| format!("0x{a:04x}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut parts = Vec::new();
+        if !references.calls.is_empty() {
+            parts.push(format!("called from {}", list(&references.calls)));
+        }
+        if !references.jumps.is_empty() {
+            parts.push(format!("jumped from {}", list(&references.jumps)));
+        }
+        Some(parts.join("; "))
+    }
+
+    // 飛び先のアドレスをラベルに置き換えた1行
+    pub fn line(&self, asm: &Assembly) -> String {
+        self.format(&Legacy, &Chunk::Code(asm.clone()))
+    }
+
+    fn format(&self, fmt: &dyn AsmFormatter, chunk: &Chunk) -> String {
+        let target = chunk
+            .as_code()
+            .and_then(|a| a.instruction.target())
+            .and_then(|t| self.label(t));
+        fmt.chunk(chunk, target.as_deref())
+    }
+
+    pub fn render(&self, chunks: &[Chunk], fmt: &dyn AsmFormatter) -> String {
+        let mut out = String::new();
+        if fmt.is_structured() {
+            for chunk in chunks {
+                writeln!(out, "{}", self.format(fmt, chunk)).unwrap();
+            }
+            return out;
+        }
+
+        let mut block_ended = false;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            // データはまとめて1つのブロックにする
+            let a = match chunk {
+                Chunk::Code(a) => a,
+                Chunk::Data { .. } => {
+                    if i > 0 && !matches!(chunks[i - 1], Chunk::Data { .. }) {
+                        out.push('\n');
+                    }
+                    writeln!(out, "{}", self.format(fmt, chunk)).unwrap();
+                    block_ended = true;
+                    continue;
+                }
+            };
+            let label = self.label(a.address);
+
+            if i > 0 && (block_ended || label.is_some()) {
+                out.push('\n');
+            }
+            if let (true, Some(label)) = (self.is_function(a.address), &label) {
+                writeln!(out, "{}", fmt.comment(&format!("function {label}"))).unwrap();
+            }
+            if let Some(label) = label {
+                match self.cross_references(a.address) {
+                    Some(refs) => writeln!(out, "{:<20}{}", fmt.label(&label), fmt.comment(&refs)),
+                    None => writeln!(out, "{}", fmt.label(&label)),
+                }
+                .unwrap();
+            }
+            let line = self.format(fmt, chunk);
+            match self.comments.get(&a.address) {
+                Some(comment) => writeln!(out, "{line:<40}{}", fmt.comment(comment)),
+                None => writeln!(out, "{line}"),
+            }
+            .unwrap();
+
+            block_ended = !matches!(a.instruction.flow(), Flow::Next | Flow::Call(_));
+        }
+
+        out
+    }
+}
@@ -0,0 +1,176 @@
+use super::symbol::SymbolTable;
+use super::trace::{access_value, Access};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// ヒートマップで1行にまとめるバイト数
+pub const LINE_SIZE: u16 = 16;
+// ヒートマップの棒の最大の長さ
+const BAR_WIDTH: u64 = 20;
+
+// データセグメント内の領域
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Data,
+    Bss,
+    Heap,
+    Stack,
+}
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Data => "data",
+            Region::Bss => "bss",
+            Region::Heap => "heap",
+            Region::Stack => "stack",
+        }
+    }
+}
+
+// データセグメントの配置．bssの終わりからbrkまでをヒープ，それより上をスタックとみなす
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub bss: u16,
+    pub heap: u16,
+    pub brk: u16,
+}
+
+impl Layout {
+    pub fn new(data_size: u16, bss_size: u16) -> Self {
+        let heap = data_size.wrapping_add(bss_size);
+        Layout {
+            bss: data_size,
+            heap,
+            brk: heap,
+        }
+    }
+
+    pub fn region(&self, addr: u16) -> Region {
+        if addr < self.bss {
+            Region::Data
+        } else if addr < self.heap {
+            Region::Bss
+        } else if addr < self.brk {
+            Region::Heap
+        } else {
+            Region::Stack
+        }
+    }
+
+    // アドレスを含む変数．ヒープとスタックは領域の名前
+    pub fn describe(&self, addr: u16, symbols: &SymbolTable) -> String {
+        let region = self.region(addr);
+        match region {
+            Region::Data | Region::Bss => symbols.variable_at(addr).map(|s| s.name.clone()),
+            Region::Heap | Region::Stack => None,
+        }
+        .unwrap_or_else(|| format!("[{}]", region.name()))
+    }
+}
+
+// --mem-traceの1行．ステップ数，命令のip，読み書き，アドレス，バイト数，値，領域
+pub fn render_access(step: usize, ip: u16, write: bool, access: &Access, region: Region) -> String {
+    format!(
+        "{step:>8} {ip:04x} {} {:04x} {} {:>4} {}",
+        if write { 'w' } else { 'r' },
+        access.addr,
+        access.width,
+        access_value(access),
+        region.name()
+    )
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counts {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl Counts {
+    fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    fn add(&mut self, other: Counts) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+    }
+}
+
+// --mem-heatmap．アドレスごとの読み書きの回数を数え，終了時に行と変数ごとにまとめる
+#[derive(Debug, Default)]
+pub struct Heatmap {
+    counts: BTreeMap<u16, Counts>,
+}
+
+impl Heatmap {
+    // ワードのアクセスは先頭のアドレスで数える
+    pub fn record(&mut self, access: &Access, write: bool) {
+        let counts = self.counts.entry(access.addr).or_default();
+        if write {
+            counts.writes += 1;
+        } else {
+            counts.reads += 1;
+        }
+    }
+
+    // LINE_SIZEバイトごとの回数．キーは行の先頭のアドレス
+    pub fn lines(&self) -> BTreeMap<u16, Counts> {
+        let mut lines: BTreeMap<u16, Counts> = BTreeMap::new();
+        for (&addr, &counts) in &self.counts {
+            lines
+                .entry(addr & !(LINE_SIZE - 1))
+                .or_default()
+                .add(counts);
+        }
+        lines
+    }
+
+    // 変数ごとの回数．回数の多い順
+    pub fn variables(&self, layout: &Layout, symbols: &SymbolTable) -> Vec<(String, Counts)> {
+        let mut variables: BTreeMap<String, Counts> = BTreeMap::new();
+        for (&addr, &counts) in &self.counts {
+            let name = layout.describe(addr, symbols);
+            variables.entry(name).or_default().add(counts);
+        }
+        let mut variables: Vec<(String, Counts)> = variables.into_iter().collect();
+        variables.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(&b.0)));
+        variables
+    }
+
+    pub fn render(&self, layout: &Layout, symbols: &SymbolTable) -> String {
+        let mut out = String::new();
+        let lines = self.lines();
+        let max = lines.values().map(Counts::total).max().unwrap_or(0).max(1);
+
+        writeln!(out, "memory accesses per {LINE_SIZE}-byte line").unwrap();
+        writeln!(
+            out,
+            "{:<6}{:>10}{:>10}  {:<7}{:<10}  heat",
+            "line", "reads", "writes", "region", "variable"
+        )
+        .unwrap();
+        for (&line, counts) in &lines {
+            // 行の中で最初にアクセスしたアドレスの変数
+            let first = self.counts.range(line..).next().map_or(line, |(&a, _)| a);
+            let bar = "#".repeat((counts.total() * BAR_WIDTH).div_ceil(max) as usize);
+            writeln!(
+                out,
+                "{line:04x}  {:>10}{:>10}  {:<7}{:<10}  {bar}",
+                counts.reads,
+                counts.writes,
+                layout.region(first).name(),
+                layout.describe(first, symbols),
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\nmemory accesses per variable").unwrap();
+        writeln!(out, "{:>10}{:>10}  variable", "reads", "writes").unwrap();
+        for (name, counts) in self.variables(layout, symbols) {
+            writeln!(out, "{:>10}{:>10}  {name}", counts.reads, counts.writes).unwrap();
+        }
+        out
+    }
+}
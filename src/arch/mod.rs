@@ -1,9 +1,26 @@
 pub mod asm;
+pub mod assemble;
 pub mod bin;
+pub mod calls;
+pub mod compress;
 pub mod constant;
+pub mod coverage;
+pub mod cycles;
+pub mod data;
 pub mod decode;
+pub mod encode;
+pub mod explore;
+pub mod format;
+pub mod function;
 pub mod header;
+pub mod listing;
+pub mod memory;
 pub mod opcode;
 pub mod operand;
+pub mod profile;
 pub mod reg;
+pub mod stack;
+pub mod stats;
+pub mod symbol;
+pub mod trace;
 pub mod vm;
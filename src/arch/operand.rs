@@ -19,6 +19,7 @@ impl Debug for Operand {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[allow(dead_code)]
 struct Memory {
     base: Register,
     index: Register,
@@ -54,22 +55,22 @@ impl Debug for ImmediateValue {
     }
 }
 
-impl Into<u16> for ImmediateValue {
-    fn into(self) -> u16 {
-        match self {
-            Self::I8(val, _) => val as u16,
-            Self::I16(val, _) => val as u16,
-            Self::I32(val, _) => val as u16,
+impl From<ImmediateValue> for u16 {
+    fn from(value: ImmediateValue) -> Self {
+        match value {
+            ImmediateValue::I8(val, _) => val as u16,
+            ImmediateValue::I16(val, _) => val as u16,
+            ImmediateValue::I32(val, _) => val as u16,
         }
     }
 }
 
-impl Into<i16> for ImmediateValue {
-    fn into(self) -> i16 {
-        match self {
-            Self::I8(val, _) => val as i16,
-            Self::I16(val, _) => val as i16,
-            Self::I32(val, _) => val as i16,
+impl From<ImmediateValue> for i16 {
+    fn from(value: ImmediateValue) -> Self {
+        match value {
+            ImmediateValue::I8(val, _) => val as i16,
+            ImmediateValue::I16(val, _) => val,
+            ImmediateValue::I32(val, _) => val as i16,
         }
     }
 }
@@ -90,7 +91,7 @@ impl Debug for Disp {
                     return write!(f, "-{:0x}", value);
                 }
 
-                return write!(f, "");
+                write!(f, "")
             }
         }
     }
@@ -0,0 +1,226 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+pub const FORMATS: [&str; 3] = ["flat", "folded", "tree"];
+
+// 動的な呼び出し木の節．同じ呼び出し元からの同じ関数の呼び出しは1つにまとめる
+struct Node {
+    name: String,
+    children: Vec<usize>,
+    calls: usize,
+    // この関数の中で実行した命令数(呼び出し先を含まない)．--cyclesならクロック数
+    own: u64,
+}
+
+// 関数ごとの集計
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub calls: usize,
+    pub own: u64,
+    // 呼び出し先を含む命令数．再帰呼び出しは二重に数えない
+    pub total: u64,
+}
+
+// --profileの呼び出し木．callで子に降り，retで呼び出し元に戻る
+pub struct Profile {
+    nodes: Vec<Node>,
+    // 呼び出し元の節
+    stack: Vec<usize>,
+    current: usize,
+}
+
+impl Profile {
+    pub fn new(root: String) -> Self {
+        Profile {
+            nodes: vec![Node {
+                name: root,
+                children: Vec::new(),
+                calls: 1,
+                own: 0,
+            }],
+            stack: Vec::new(),
+            current: 0,
+        }
+    }
+
+    // 現在の関数で命令を実行した
+    pub fn count(&mut self, weight: u64) {
+        self.nodes[self.current].own += weight;
+    }
+
+    pub fn call(&mut self, name: String) {
+        let found = self.nodes[self.current]
+            .children
+            .iter()
+            .copied()
+            .find(|&i| self.nodes[i].name == name);
+        let child = found.unwrap_or_else(|| {
+            self.nodes.push(Node {
+                name,
+                children: Vec::new(),
+                calls: 0,
+                own: 0,
+            });
+            let child = self.nodes.len() - 1;
+            self.nodes[self.current].children.push(child);
+            child
+        });
+
+        self.nodes[child].calls += 1;
+        self.stack.push(self.current);
+        self.current = child;
+    }
+
+    // count個の呼び出しから戻った
+    pub fn ret(&mut self, count: usize) {
+        let pos = self.stack.len().saturating_sub(count);
+        if let Some(&parent) = self.stack.get(pos) {
+            self.current = parent;
+            self.stack.truncate(pos);
+        }
+    }
+
+    pub fn functions(&self) -> Vec<Function> {
+        let mut functions: Vec<Function> = Vec::new();
+        let mut path = Vec::new();
+        self.collect(0, &mut path, &mut functions);
+
+        functions.sort_by(|a, b| b.own.cmp(&a.own).then(a.name.cmp(&b.name)));
+        functions
+    }
+
+    fn collect<'a>(&'a self, i: usize, path: &mut Vec<&'a str>, out: &mut Vec<Function>) {
+        let node = &self.nodes[i];
+        let index = match out.iter().position(|f| f.name == node.name) {
+            Some(index) => index,
+            None => {
+                out.push(Function {
+                    name: node.name.clone(),
+                    calls: 0,
+                    own: 0,
+                    total: 0,
+                });
+                out.len() - 1
+            }
+        };
+        out[index].calls += node.calls;
+        out[index].own += node.own;
+
+        // 経路上の関数それぞれの呼び出し先を含む命令数に加える
+        path.push(&node.name);
+        let names: BTreeSet<&str> = path.iter().copied().collect();
+        for f in out.iter_mut().filter(|f| names.contains(f.name.as_str())) {
+            f.total += node.own;
+        }
+        for &child in &node.children {
+            self.collect(child, path, out);
+        }
+        path.pop();
+    }
+
+    // flamegraph.plなどが読む "crtso;_main;_printf 123" の形式
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        let mut path = Vec::new();
+        self.write_folded(0, &mut path, &mut out);
+        out
+    }
+
+    fn write_folded<'a>(&'a self, i: usize, path: &mut Vec<&'a str>, out: &mut String) {
+        let node = &self.nodes[i];
+        path.push(&node.name);
+        if node.own > 0 {
+            writeln!(out, "{} {}", path.join(";"), node.own).unwrap();
+        }
+        for &child in &node.children {
+            self.write_folded(child, path, out);
+        }
+        path.pop();
+    }
+
+    // gprofのflat profileに倣った表．単位は命令数かクロック数
+    pub fn flat(&self) -> String {
+        let functions = self.functions();
+        let all: u64 = functions.iter().map(|f| f.own).sum();
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "  %   cumulative     self                self     total"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            " time      count    count    calls  per call  per call  name"
+        )
+        .unwrap();
+
+        let mut cumulative = 0;
+        for f in &functions {
+            cumulative += f.own;
+            let percent = f.own as f64 * 100.0 / all.max(1) as f64;
+            let calls = f.calls.max(1) as f64;
+            writeln!(
+                out,
+                "{percent:5.1} {cumulative:>10} {:>8} {:>8} {:>9.2} {:>9.2}  {}",
+                f.own,
+                f.calls,
+                f.own as f64 / calls,
+                f.total as f64 / calls,
+                f.name
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // 呼び出し木．子は呼び出し先を含む命令数の多い順
+    pub fn tree(&self) -> String {
+        let totals = self.totals();
+        let mut out = String::new();
+        writeln!(out, "     total       self    calls  function").unwrap();
+        self.write_tree(0, 0, &totals, &mut out);
+        out
+    }
+
+    fn write_tree(&self, i: usize, depth: usize, totals: &[u64], out: &mut String) {
+        let node = &self.nodes[i];
+        writeln!(
+            out,
+            "{:>10} {:>10} {:>8}  {:indent$}{}",
+            totals[i],
+            node.own,
+            node.calls,
+            "",
+            node.name,
+            indent = depth * 2
+        )
+        .unwrap();
+
+        let mut children = node.children.clone();
+        children.sort_by_key(|&c| std::cmp::Reverse(totals[c]));
+        for child in children {
+            self.write_tree(child, depth + 1, totals, out);
+        }
+    }
+
+    // 節ごとの部分木の命令数．子は必ず親より後に作られる
+    fn totals(&self) -> Vec<u64> {
+        let mut totals: Vec<u64> = self.nodes.iter().map(|n| n.own).collect();
+        for i in (0..self.nodes.len()).rev() {
+            for &child in &self.nodes[i].children {
+                totals[i] += totals[child];
+            }
+        }
+        totals
+    }
+
+    pub fn render(&self, format: &str) -> String {
+        match format {
+            "folded" => self.folded(),
+            "tree" => self.tree(),
+            _ => self.flat(),
+        }
+    }
+}
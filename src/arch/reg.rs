@@ -6,15 +6,16 @@ use std::fmt::Debug;
 pub enum Register {
     Reg16(Reg16),
     Reg8(Reg8),
+    #[allow(dead_code)]
     None,
 }
 
 impl Register {
     pub fn gen(num: u8, w: u8) -> Self {
         if w == 1 {
-            return Self::Reg16(Reg16::from(num));
+            Self::Reg16(Reg16::from(num))
         } else {
-            return Self::Reg8(Reg8::from(num));
+            Self::Reg8(Reg8::from(num))
         }
     }
 }
@@ -0,0 +1,255 @@
+use super::function::{EdgeKind, Function};
+use super::memory::Layout;
+use super::opcode::Opcode;
+use super::operand::{ImmediateValue, Operand};
+use super::reg::{Reg16, Register};
+use super::symbol::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// 実行中の関数．entryは呼び出し前のsp(戻りアドレスを含めて数える)
+struct Frame {
+    name: String,
+    entry: u16,
+    // 呼び出し先を含めた最小のsp
+    lowest: u16,
+}
+
+// 関数ごとのスタックの使用量(バイト)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub calls: usize,
+    // その関数を実行している間の最大．呼び出し先を含まない
+    pub frame: u16,
+    // 呼び出し先を含む最大
+    pub total: u16,
+}
+
+// --stack-usage．命令ごとのspと呼び出しから，全体と関数ごとの最小のspを記録する
+pub struct StackUsage {
+    initial: u16,
+    lowest: u16,
+    lowest_step: usize,
+    // 最小のspに達したときの呼び出しの列
+    chain: Vec<String>,
+    max_depth: usize,
+    frames: Vec<Frame>,
+    functions: BTreeMap<String, Usage>,
+}
+
+impl StackUsage {
+    pub fn new(root: String, sp: u16) -> Self {
+        let mut functions = BTreeMap::new();
+        functions.insert(
+            root.clone(),
+            Usage {
+                calls: 1,
+                ..Usage::default()
+            },
+        );
+        StackUsage {
+            initial: sp,
+            lowest: sp,
+            lowest_step: 0,
+            chain: vec![root.clone()],
+            max_depth: 1,
+            frames: vec![Frame {
+                name: root,
+                entry: sp,
+                lowest: sp,
+            }],
+            functions,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn lowest(&self) -> u16 {
+        self.lowest
+    }
+
+    #[cfg(test)]
+    // 最小のspに達したときの呼び出しの列
+    pub fn chain(&self) -> &[String] {
+        &self.chain
+    }
+
+    // callの直後．spは戻りアドレスを指す
+    pub fn call(&mut self, name: String, sp: u16) {
+        self.functions.entry(name.clone()).or_default().calls += 1;
+        let entry = sp.wrapping_add(2);
+        self.frames.push(Frame {
+            name,
+            entry,
+            lowest: entry,
+        });
+        self.max_depth = self.max_depth.max(self.frames.len());
+    }
+
+    // count個の呼び出しから戻った．最初の関数からは戻らない
+    pub fn ret(&mut self, count: usize) {
+        let pos = self.frames.len().saturating_sub(count).max(1);
+        while self.frames.len() > pos {
+            let frame = self.frames.pop().unwrap();
+            self.close(&frame);
+        }
+    }
+
+    // 命令を実行した後のsp
+    pub fn observe(&mut self, sp: u16, step: usize) {
+        let frame = self.frames.last_mut().unwrap();
+        if sp >= frame.lowest {
+            return;
+        }
+        frame.lowest = sp;
+        let used = frame.entry.wrapping_sub(sp);
+        let usage = self.functions.get_mut(&frame.name).unwrap();
+        usage.frame = usage.frame.max(used);
+
+        if sp < self.lowest {
+            self.lowest = sp;
+            self.lowest_step = step;
+            self.chain = self.frames.iter().map(|f| f.name.clone()).collect();
+        }
+    }
+
+    // 戻った関数の呼び出し先を含めた使用量を記録し，呼び出し元に伝える
+    fn close(&mut self, frame: &Frame) {
+        let usage = self.functions.get_mut(&frame.name).unwrap();
+        usage.total = usage.total.max(frame.entry.wrapping_sub(frame.lowest));
+        if let Some(parent) = self.frames.last_mut() {
+            parent.lowest = parent.lowest.min(frame.lowest);
+        }
+    }
+
+    // 関数ごとの使用量．実行中の関数も含める
+    pub fn functions(&self) -> BTreeMap<String, Usage> {
+        let mut functions = self.functions.clone();
+        let mut lowest = u16::MAX;
+        for frame in self.frames.iter().rev() {
+            lowest = lowest.min(frame.lowest);
+            let usage = functions.get_mut(&frame.name).unwrap();
+            usage.total = usage.total.max(frame.entry.wrapping_sub(lowest));
+        }
+        functions
+    }
+
+    pub fn render(&self, layout: &Layout, estimates: &BTreeMap<String, u16>) -> String {
+        let mut out = String::new();
+        let used = self.initial.wrapping_sub(self.lowest);
+        writeln!(out, "stack usage").unwrap();
+        writeln!(out, "  initial sp: {:04x}", self.initial).unwrap();
+        writeln!(
+            out,
+            "  lowest sp:  {:04x} ({used} bytes) at step {}",
+            self.lowest, self.lowest_step
+        )
+        .unwrap();
+        // brkとの間が残りのスタックとヒープの余裕
+        writeln!(
+            out,
+            "  headroom:   {} bytes above brk {:04x}",
+            self.lowest.saturating_sub(layout.brk),
+            layout.brk
+        )
+        .unwrap();
+        writeln!(out, "  max call depth: {}", self.max_depth).unwrap();
+        writeln!(
+            out,
+            "  call chain at the lowest sp: {}",
+            self.chain.join(" > ")
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "\n{:<12}{:>8}{:>8}{:>8}{:>8}",
+            "function", "calls", "frame", "total", "static"
+        )
+        .unwrap();
+        let mut functions: Vec<(String, Usage)> = self.functions().into_iter().collect();
+        functions.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(&b.0)));
+        for (name, usage) in functions {
+            let estimate = estimates.get(&name).map_or("-".to_owned(), u16::to_string);
+            writeln!(
+                out,
+                "{name:<12}{:>8}{:>8}{:>8}{estimate:>8}",
+                usage.calls, usage.frame, usage.total
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+// 関数ごとのスタックの使用量の静的な見積もり．関数の中の分岐を辿り，push，popとspへの
+// 即値の加減算でspの深さを追う．戻りアドレスを含み，呼び出し先は含まない
+pub fn estimate(text: &[u8], symbols: &SymbolTable) -> BTreeMap<String, u16> {
+    let starts: BTreeSet<u16> = symbols.functions().map(|s| s.value as u16).collect();
+    symbols
+        .functions()
+        .map(|s| {
+            let function = Function::build(text, s.value as u16, s.name.clone(), &starts);
+            (s.name.clone(), 2 + max_depth(&function))
+        })
+        .collect()
+}
+
+fn max_depth(function: &Function) -> u16 {
+    let blocks: BTreeMap<u16, _> = function.blocks.iter().map(|b| (b.start, b)).collect();
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![(function.start, 0i32, 0i32)];
+    let mut max = 0;
+
+    while let Some((start, mut depth, mut frame)) = pending.pop() {
+        let Some(block) = blocks.get(&start) else {
+            continue;
+        };
+        if !seen.insert(start) {
+            continue;
+        }
+
+        for asm in &block.instructions {
+            let instruction = &asm.instruction;
+            let sp = |operand: &Option<Operand>| {
+                matches!(operand, Some(Operand::Register(Register::Reg16(Reg16::SP))))
+            };
+            let bp = |operand: &Option<Operand>| {
+                matches!(operand, Some(Operand::Register(Register::Reg16(Reg16::BP))))
+            };
+            let imm = match &instruction.operand2 {
+                Some(Operand::Immediate(ImmediateValue::I8(v, _))) => *v as i32,
+                Some(Operand::Immediate(ImmediateValue::I16(v, _))) => *v as i32,
+                _ => 0,
+            };
+            match instruction.opcode {
+                Opcode::PushReg | Opcode::PushRegMem | Opcode::PushSegReg => depth += 2,
+                Opcode::PopReg | Opcode::PopRegMem | Opcode::PopSegReg => depth -= 2,
+                Opcode::SubImmediateRegisterMemory if sp(&instruction.operand1) => depth += imm,
+                Opcode::AddImmediateRegisterMemory if sp(&instruction.operand1) => depth -= imm,
+                // mov bp, spで作ったフレームをmov sp, bpで捨てる
+                Opcode::MovRmToFromReg
+                    if bp(&instruction.operand1) && sp(&instruction.operand2) =>
+                {
+                    frame = depth
+                }
+                Opcode::MovRmToFromReg
+                    if sp(&instruction.operand1) && bp(&instruction.operand2) =>
+                {
+                    depth = frame
+                }
+                _ => {}
+            }
+            max = max.max(depth);
+        }
+
+        for edge in &function.edges {
+            match (edge.from == start, edge.to, edge.kind) {
+                (true, Some(to), kind) if kind != EdgeKind::Call => {
+                    pending.push((to, depth, frame))
+                }
+                _ => {}
+            }
+        }
+    }
+    max.clamp(0, u16::MAX as i32) as u16
+}
@@ -0,0 +1,196 @@
+use super::asm::Assembly;
+use super::constant::syscall::{BRK, CLOSE, CREAT, EXIT, IOCTL, LSEEK, OPEN, READ, WRITE};
+use super::cycles::Cpu;
+use super::opcode::Opcode;
+use super::operand::{Operand, EA};
+use crate::json::Json;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+pub const FORMATS: [&str; 2] = ["table", "json"];
+
+// 実行した命令の統計．--statsを指定したときだけVMが集計する
+#[derive(Default)]
+pub struct Stats {
+    pub instructions: usize,
+    opcodes: HashMap<Opcode, usize>,
+    modes: HashMap<&'static str, usize>,
+    syscalls: HashMap<u16, usize>,
+    // --cyclesを指定したときのクロック数
+    cycles: Option<(Cpu, u64)>,
+}
+
+impl Stats {
+    pub fn record(&mut self, asm: &Assembly) {
+        let instruction = &asm.instruction;
+        self.instructions += 1;
+        *self.opcodes.entry(instruction.opcode).or_default() += 1;
+
+        let relative = instruction.target().is_some();
+        for operand in [&instruction.operand1, &instruction.operand2]
+            .into_iter()
+            .flatten()
+        {
+            *self.modes.entry(mode(operand, relative)).or_default() += 1;
+        }
+    }
+
+    pub fn count_cycles(&mut self, cpu: Cpu, clocks: u32) {
+        self.cycles.get_or_insert((cpu, 0)).1 += clocks as u64;
+    }
+
+    pub fn syscall(&mut self, id: u16) {
+        *self.syscalls.entry(id).or_default() += 1;
+    }
+
+    // Opcodeの列挙子ごとの回数．多い順
+    pub fn opcodes(&self) -> Vec<(&'static str, usize)> {
+        sorted(self.opcodes.iter().map(|(op, &n)| (op.name(), n)))
+    }
+
+    // 命令名ごとの回数．byte，word，shortの区別はしない
+    pub fn mnemonics(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (op, &n) in &self.opcodes {
+            *counts.entry(op.mnemonic().to_owned()).or_default() += n;
+        }
+        sorted(counts)
+    }
+
+    // オペランドのアドレッシングモードごとの回数
+    pub fn modes(&self) -> Vec<(&'static str, usize)> {
+        sorted(self.modes.iter().map(|(&m, &n)| (m, n)))
+    }
+
+    pub fn syscalls(&self) -> Vec<(String, usize)> {
+        sorted(self.syscalls.iter().map(|(&id, &n)| (syscall_name(id), n)))
+    }
+
+    // 終了時に表示する表．elapsedはVMの実行にかかったホストの時間
+    pub fn render(&self, elapsed: Duration) -> String {
+        let mut out = String::new();
+        writeln!(out, "instructions executed: {}", self.instructions).unwrap();
+        if let Some((cpu, cycles)) = self.cycles {
+            let per = cycles as f64 / self.instructions.max(1) as f64;
+            writeln!(
+                out,
+                "cycles: {cycles} on {} ({per:.2} per instruction)",
+                cpu.name()
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "host time: {:.3}s ({:.0} instructions/s)",
+            elapsed.as_secs_f64(),
+            self.per_second(elapsed)
+        )
+        .unwrap();
+
+        self.section(&mut out, "syscalls", &self.syscalls());
+        self.section(&mut out, "mnemonics", &self.mnemonics());
+        self.section(&mut out, "opcodes", &self.opcodes());
+        self.section(&mut out, "addressing modes", &self.modes());
+        out
+    }
+
+    pub fn to_json(&self, elapsed: Duration) -> Json {
+        fn counts<K: Into<String>>(list: Vec<(K, usize)>) -> Json {
+            Json::object(list.into_iter().map(|(k, n)| (k, Json::from(n))))
+        }
+
+        let (cpu, cycles) = match self.cycles {
+            Some((cpu, cycles)) => (Some(cpu.name()), Some(cycles)),
+            None => (None, None),
+        };
+
+        Json::object([
+            ("instructions", Json::from(self.instructions)),
+            ("cpu", Json::from(cpu)),
+            ("cycles", Json::from(cycles)),
+            ("seconds", Json::from(elapsed.as_secs_f64())),
+            (
+                "instructions_per_second",
+                Json::from(self.per_second(elapsed)),
+            ),
+            ("syscalls", counts(self.syscalls())),
+            ("mnemonics", counts(self.mnemonics())),
+            ("opcodes", counts(self.opcodes())),
+            ("modes", counts(self.modes())),
+        ])
+    }
+
+    fn per_second(&self, elapsed: Duration) -> f64 {
+        self.instructions as f64 / elapsed.as_secs_f64().max(1e-9)
+    }
+
+    fn section<K: AsRef<str>>(&self, out: &mut String, title: &str, list: &[(K, usize)]) {
+        if list.is_empty() {
+            return;
+        }
+
+        writeln!(out, "\n{title}:").unwrap();
+        let width = list
+            .iter()
+            .map(|(k, _)| k.as_ref().len())
+            .max()
+            .unwrap_or(0);
+        let total: usize = list.iter().map(|(_, n)| n).sum();
+        for (key, n) in list {
+            let percent = *n as f64 * 100.0 / total as f64;
+            writeln!(out, "  {:<width$}  {n:>10}  {percent:5.1}%", key.as_ref()).unwrap();
+        }
+    }
+}
+
+// 多い順，同じ回数なら名前順
+fn sorted<K: Ord>(counts: impl IntoIterator<Item = (K, usize)>) -> Vec<(K, usize)> {
+    let mut list: Vec<(K, usize)> = counts.into_iter().collect();
+    list.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+    list
+}
+
+// 分岐の飛び先は即値ではなくipからの相対位置として数える
+fn mode(operand: &Operand, relative: bool) -> &'static str {
+    match operand {
+        Operand::Register(_) => "register",
+        Operand::Immediate(_) if relative => "relative",
+        Operand::Immediate(_) => "immediate",
+        Operand::EffectiveAddress(ea) => match ea {
+            EA::DispOnly(_) => "[disp]",
+            EA::BxSi(d) => with_disp(d.0, "[bx+si]", "[bx+si+disp]"),
+            EA::BxDi(d) => with_disp(d.0, "[bx+di]", "[bx+di+disp]"),
+            EA::BpSi(d) => with_disp(d.0, "[bp+si]", "[bp+si+disp]"),
+            EA::BpDi(d) => with_disp(d.0, "[bp+di]", "[bp+di+disp]"),
+            EA::Si(d) => with_disp(d.0, "[si]", "[si+disp]"),
+            EA::Di(d) => with_disp(d.0, "[di]", "[di+disp]"),
+            EA::Bp(d) => with_disp(d.0, "[bp]", "[bp+disp]"),
+            EA::Bx(d) => with_disp(d.0, "[bx]", "[bx+disp]"),
+        },
+    }
+}
+
+fn with_disp(disp: isize, without: &'static str, with: &'static str) -> &'static str {
+    if disp == 0 {
+        without
+    } else {
+        with
+    }
+}
+
+pub fn syscall_name(id: u16) -> String {
+    match id {
+        EXIT => "exit",
+        READ => "read",
+        WRITE => "write",
+        OPEN => "open",
+        CLOSE => "close",
+        CREAT => "creat",
+        BRK => "brk",
+        LSEEK => "lseek",
+        IOCTL => "ioctl",
+        _ => return format!("syscall {id}"),
+    }
+    .to_owned()
+}
@@ -0,0 +1,201 @@
+use super::bin::bytes_to_16bit_little_endian;
+use std::fmt::Debug;
+
+// struct nlist { char n_name[8]; long n_value; char n_sclass; char n_numaux; short n_type; }
+pub const SYMBOL_SIZE: usize = 16;
+pub const NAME_SIZE: usize = 8;
+
+// n_sclassの下位3bitはセクションを表す
+pub mod section {
+    pub const MASK: u8 = 0o7;
+    pub const UNDEF: u8 = 0;
+    pub const ABS: u8 = 1;
+    pub const TEXT: u8 = 2;
+    pub const DATA: u8 = 3;
+    pub const BSS: u8 = 4;
+    pub const COMM: u8 = 5;
+}
+
+// n_sclassの上位bitは記憶クラスを表す
+pub const C_EXT: u8 = 0o20;
+
+// テキストセグメントの境界を示すだけで，関数ではないシンボル
+const TEXT_MARKERS: [&str; 3] = ["begtext", "endtext", "__etext"];
+// データセグメントの境界を示すだけのシンボル
+const DATA_MARKERS: [&str; 7] = [
+    "begdata", "enddata", "__edata", "endrom", "begbss", "endbss", "__end",
+];
+
+#[derive(Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u32,
+    pub sclass: u8,
+    pub numaux: u8,
+    pub n_type: u16,
+}
+
+impl Symbol {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SYMBOL_SIZE {
+            return None;
+        }
+
+        let name = bytes[..NAME_SIZE]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect();
+
+        let value = bytes_to_16bit_little_endian(&bytes[8..10]) as u32
+            | (bytes_to_16bit_little_endian(&bytes[10..12]) as u32) << 16;
+
+        Some(Symbol {
+            name,
+            value,
+            sclass: bytes[12],
+            numaux: bytes[13],
+            n_type: bytes_to_16bit_little_endian(&bytes[14..16]),
+        })
+    }
+
+    // 名前は8バイトに満たなければ0で埋め，超える分は切り捨てる
+    pub fn to_bytes(&self) -> [u8; SYMBOL_SIZE] {
+        let mut bytes = [0; SYMBOL_SIZE];
+        for (b, c) in bytes[..NAME_SIZE].iter_mut().zip(self.name.bytes()) {
+            *b = c;
+        }
+        bytes[8..12].copy_from_slice(&self.value.to_le_bytes());
+        bytes[12] = self.sclass;
+        bytes[13] = self.numaux;
+        bytes[14..16].copy_from_slice(&self.n_type.to_le_bytes());
+        bytes
+    }
+
+    pub fn section(&self) -> u8 {
+        self.sclass & section::MASK
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.sclass & C_EXT != 0
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.section() == section::TEXT
+    }
+
+    // 変数として扱えるデータ，bssのシンボル
+    pub fn is_variable(&self) -> bool {
+        matches!(self.section(), section::DATA | section::BSS)
+            && !self.name.is_empty()
+            && !DATA_MARKERS.contains(&self.name.as_str())
+    }
+
+    // 関数の先頭として扱えるシンボル
+    pub fn is_function(&self) -> bool {
+        self.is_text() && !self.name.is_empty() && !TEXT_MARKERS.contains(&self.name.as_str())
+    }
+
+    // nmと同じ種別の文字
+    pub fn kind(&self) -> char {
+        let c = match self.section() {
+            section::UNDEF => 'u',
+            section::ABS => 'a',
+            section::TEXT => 't',
+            section::DATA => 'd',
+            section::BSS => 'b',
+            section::COMM => 'c',
+            _ => '?',
+        };
+
+        if self.is_external() {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08x} {} {}", self.value, self.kind(), self.name)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    // 関数として扱うテキストシンボルの添字(アドレス順)
+    functions: Vec<usize>,
+}
+
+impl SymbolTable {
+    pub fn new(symbols: Vec<Symbol>) -> Self {
+        let mut functions: Vec<usize> = (0..symbols.len())
+            .filter(|&i| symbols[i].is_function())
+            .collect();
+
+        // 同じアドレスに複数ある場合は外部シンボルを優先する
+        functions.sort_by_key(|&i| (symbols[i].value, !symbols[i].is_external()));
+        functions.dedup_by_key(|&mut i| symbols[i].value);
+
+        SymbolTable { symbols, functions }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Self {
+        let symbols = bytes
+            .chunks(SYMBOL_SIZE)
+            .filter_map(Symbol::parse)
+            .collect();
+
+        Self::new(symbols)
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Symbol> {
+        self.functions.iter().map(|&i| &self.symbols[i])
+    }
+
+    // 指定したアドレスから始まる関数
+    pub fn function_starting_at(&self, addr: u16) -> Option<&Symbol> {
+        self.functions().find(|s| s.value == addr as u32)
+    }
+
+    // 指定したアドレスを含む関数(直前の関数シンボル)
+    pub fn function_at(&self, addr: u16) -> Option<&Symbol> {
+        let pos = self
+            .functions
+            .partition_point(|&i| self.symbols[i].value <= addr as u32);
+
+        pos.checked_sub(1).map(|p| &self.symbols[self.functions[p]])
+    }
+
+    // 指定したデータセグメントのアドレスを含む変数(直前の変数シンボル)
+    pub fn variable_at(&self, addr: u16) -> Option<&Symbol> {
+        self.symbols
+            .iter()
+            .filter(|s| s.is_variable() && s.value <= addr as u32)
+            .max_by_key(|s| (s.value, s.is_external()))
+    }
+
+    // "_main+0x12" の形式でアドレスを表す．シンボルがなければ16進数のみ
+    pub fn describe(&self, addr: u16) -> String {
+        match self.function_at(addr) {
+            Some(sym) if sym.value == addr as u32 => sym.name.clone(),
+            Some(sym) => format!("{}+0x{:x}", sym.name, addr as u32 - sym.value),
+            None => format!("{addr:04x}"),
+        }
+    }
+}
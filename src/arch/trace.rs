@@ -0,0 +1,495 @@
+use super::asm::{Assembly, Traced};
+use super::compress::{Compressor, Entry, Step};
+use super::decode::Decoder;
+use super::explore::Chunk;
+use super::format::{hex, JsonLines};
+use super::opcode::Opcode;
+use super::stats::syscall_name;
+use crate::json::{escape, Json};
+use std::fmt::Write as _;
+use std::io::{self, BufWriter, Write};
+
+pub const FORMATS: [&str; 3] = ["text", "jsonl", "bin"];
+// trace-dumpが書ける書式．binはtrace-dumpが読むもの
+pub const DUMP_FORMATS: [&str; 2] = ["text", "jsonl"];
+
+// バイナリ形式のトレースの先頭に置くマジックナンバーと版．その後ろにフラグが付く
+pub const MAGIC: &[u8; 4] = b"M86T";
+const VERSION: u8 = 1;
+// --trace-compressで記録した．バイナリ形式には全命令を書き，読むときにまとめる
+const COMPRESSED: u8 = 1;
+
+// reg配列の添字の順
+pub const REGISTERS: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // 既存VMと同じ固定幅のトレース
+    Text,
+    // 1行に1命令のJSON
+    Jsonl,
+    // 同じ内容を詰めたバイナリ．trace-dumpで読む
+    Bin,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "jsonl" => Some(Format::Jsonl),
+            "bin" => Some(Format::Bin),
+            _ => None,
+        }
+    }
+}
+
+// --trace-from等によるトレースする命令の選択．from，untilはVMが状態として持つ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    // ipがfromに達してからuntilの命令を実行するまでをトレースする
+    pub from: Option<u16>,
+    pub until: Option<u16>,
+    // ipがstart以上end未満の命令
+    pub range: Option<(u16, u16)>,
+    // 1から数えたステップ数がstart以上end未満の命令
+    pub steps: Option<(usize, usize)>,
+    pub only_calls: bool,
+    pub only_syscalls: bool,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        *self == Filter::default()
+    }
+
+    // from，until以外の条件
+    pub fn selects(&self, step: usize, asm: &Assembly) -> bool {
+        let ip = asm.address;
+        if self
+            .range
+            .is_some_and(|(start, end)| ip < start || ip >= end)
+        {
+            return false;
+        }
+        if self
+            .steps
+            .is_some_and(|(start, end)| step < start || step >= end)
+        {
+            return false;
+        }
+        if !self.only_calls && !self.only_syscalls {
+            return true;
+        }
+
+        let opcode = asm.instruction.opcode;
+        let call = matches!(
+            opcode,
+            Opcode::CallWithinDirect
+                | Opcode::RetWithinSegment
+                | Opcode::RetWithinSegAddingImmedToSp
+                | Opcode::RetIntersegment
+                | Opcode::RetIntersegmentAddingImmediateToSp
+        );
+        let syscall = opcode == Opcode::IntTypeSpecified;
+        (self.only_calls && call) || (self.only_syscalls && syscall)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Registers {
+    pub reg: [u16; 8],
+    pub flags: u16,
+    pub ip: u16,
+}
+
+// メモリへの1回の読み書き．widthはバイト数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub addr: u16,
+    pub width: u8,
+    pub value: u16,
+}
+
+// 1命令の間のメモリへのアクセス．Ramが記録する
+#[derive(Debug, Default)]
+pub struct Accesses {
+    pub reads: Vec<Access>,
+    pub writes: Vec<Access>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Syscall {
+    pub id: u16,
+    // メッセージのm1_i1，m1_i2，m1_i3，m1_p1
+    pub args: [u16; 4],
+    // ホストでの結果．exitなど結果のないものはNone
+    pub result: Option<i32>,
+    // readで読んだ，writeで書いたバイト列
+    pub data: Vec<u8>,
+}
+
+// 1命令分のトレース
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub step: u64,
+    pub bytes: Vec<u8>,
+    pub before: Registers,
+    pub after: Registers,
+    pub reads: Vec<Access>,
+    pub writes: Vec<Access>,
+    pub syscalls: Vec<Syscall>,
+}
+
+impl Record {
+    pub fn ip(&self) -> u16 {
+        self.before.ip
+    }
+
+    // 記録した機械語をデコードし直す
+    pub fn assembly(&self) -> Option<Assembly> {
+        Decoder::at(&self.bytes, self.ip(), self.ip()).decode()
+    }
+
+    pub fn to_json(&self) -> Json {
+        let instruction = match self.assembly() {
+            Some(asm) => JsonLines.value(&Chunk::Code(asm), None),
+            None => Json::Null,
+        };
+        let accesses = |list: &[Access]| {
+            Json::Array(
+                list.iter()
+                    .map(|a| {
+                        Json::object([
+                            ("addr", a.addr.into()),
+                            ("width", (a.width as u16).into()),
+                            ("value", a.value.into()),
+                        ])
+                    })
+                    .collect(),
+            )
+        };
+        let syscalls = self.syscalls.iter().map(|s| {
+            // 読み書きした内容はUTF-8とは限らないので，機械語と同じく16進数の文字列にする
+            let data = (!s.data.is_empty()).then(|| hex(&s.data));
+            Json::object([
+                ("id", s.id.into()),
+                ("name", syscall_name(s.id).into()),
+                ("args", s.args.to_vec().into()),
+                ("result", s.result.into()),
+                ("data", data.into()),
+            ])
+        });
+
+        Json::object([
+            ("step", self.step.into()),
+            ("ip", self.ip().into()),
+            ("bytes", Json::Str(hex(&self.bytes))),
+            ("instruction", instruction),
+            ("before", registers_json(&self.before)),
+            ("after", registers_json(&self.after)),
+            ("reads", accesses(&self.reads)),
+            ("writes", accesses(&self.writes)),
+            ("syscalls", Json::Array(syscalls.collect())),
+        ])
+    }
+
+    // trace-dumpの1命令分の表示．変化したレジスタ，メモリへのアクセス，システムコールを付ける
+    pub fn render(&self) -> String {
+        let asm = match self.assembly() {
+            Some(asm) => format!("{:?}", Traced(&asm)),
+            None => format!("{:04x}: {}", self.ip(), hex(&self.bytes)),
+        };
+        let mut out = format!("{:>8} {asm:<40}", self.step);
+
+        for (i, name) in REGISTERS.iter().enumerate() {
+            if self.before.reg[i] != self.after.reg[i] {
+                write!(out, " {name}={:04x}", self.after.reg[i]).unwrap();
+            }
+        }
+        if self.before.flags != self.after.flags {
+            write!(out, " flags={:04x}", self.after.flags).unwrap();
+        }
+        for (kind, list) in [("r", &self.reads), ("w", &self.writes)] {
+            for a in list {
+                write!(out, " {kind}[{:04x}]={}", a.addr, access_value(a)).unwrap();
+            }
+        }
+        let mut out = out.trim_end().to_owned();
+
+        for s in &self.syscalls {
+            let args: Vec<String> = s.args.iter().map(|a| format!("0x{a:04x}")).collect();
+            write!(
+                out,
+                "\n{:9}<{}({})",
+                "",
+                syscall_name(s.id),
+                args.join(", ")
+            )
+            .unwrap();
+            if let Some(result) = s.result {
+                write!(out, " => {result}").unwrap();
+            }
+            out.push('>');
+            if !s.data.is_empty() {
+                write!(out, " {}", escape(&String::from_utf8_lossy(&s.data))).unwrap();
+            }
+        }
+        out
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.step.to_le_bytes());
+        for regs in [&self.before, &self.after] {
+            for value in regs.reg.iter().chain([&regs.flags, &regs.ip]) {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        out.push(self.bytes.len() as u8);
+        out.extend_from_slice(&self.bytes);
+
+        for list in [&self.reads, &self.writes] {
+            out.extend_from_slice(&(list.len() as u16).to_le_bytes());
+            for a in list {
+                out.extend_from_slice(&a.addr.to_le_bytes());
+                out.push(a.width);
+                out.extend_from_slice(&a.value.to_le_bytes());
+            }
+        }
+
+        out.push(self.syscalls.len() as u8);
+        for s in &self.syscalls {
+            out.extend_from_slice(&s.id.to_le_bytes());
+            for arg in s.args {
+                out.extend_from_slice(&arg.to_le_bytes());
+            }
+            match s.result {
+                Some(result) => {
+                    out.push(1);
+                    out.extend_from_slice(&result.to_le_bytes());
+                }
+                None => out.push(0),
+            }
+            out.extend_from_slice(&(s.data.len() as u16).to_le_bytes());
+            out.extend_from_slice(&s.data);
+        }
+    }
+}
+
+pub fn registers_json(regs: &Registers) -> Json {
+    let mut pairs: Vec<(&str, Json)> = REGISTERS
+        .iter()
+        .zip(regs.reg)
+        .map(|(&name, value)| (name, value.into()))
+        .collect();
+    pairs.push(("flags", regs.flags.into()));
+    pairs.push(("ip", regs.ip.into()));
+    Json::object(pairs)
+}
+
+pub fn access_value(a: &Access) -> String {
+    match a.width {
+        1 => format!("{:02x}", a.value & 0xff),
+        _ => format!("{:04x}", a.value),
+    }
+}
+
+// --trace-formatがjsonl，binのときのトレースの出力先
+pub struct Writer {
+    out: BufWriter<Box<dyn Write>>,
+    format: Format,
+    buf: Vec<u8>,
+    // 繰り返しをまとめる．binでは使わない
+    compressor: Option<Compressor<Record>>,
+}
+
+impl Writer {
+    pub fn new(out: Box<dyn Write>, format: Format, compress: bool) -> Self {
+        let mut out = BufWriter::new(out);
+        if format == Format::Bin {
+            let flags = if compress { COMPRESSED } else { 0 };
+            let _ = out.write_all(MAGIC);
+            let _ = out.write_all(&[VERSION, flags]);
+        }
+        let compressor = (compress && format != Format::Bin).then(Compressor::default);
+        Writer {
+            out,
+            format,
+            buf: Vec::new(),
+            compressor,
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let Some(compressor) = self.compressor.as_mut() else {
+            return self.write_record(record);
+        };
+        let entries = compressor.push(Step {
+            step: record.step,
+            ip: record.ip(),
+            after: record.after,
+            item: record.clone(),
+        });
+        self.write_entries(entries)
+    }
+
+    fn write_entries(&mut self, entries: Vec<Entry<Record>>) -> io::Result<()> {
+        for entry in entries {
+            match entry {
+                Entry::Step(record) => self.write_record(&record)?,
+                Entry::Loop(repeat) => match self.format {
+                    Format::Jsonl => writeln!(self.out, "{}", repeat.to_json())?,
+                    _ => writeln!(self.out, "{:9}{}", "", repeat.render())?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Bin => {
+                self.buf.clear();
+                record.encode(&mut self.buf);
+                self.out.write_all(&self.buf)
+            }
+            Format::Jsonl => writeln!(self.out, "{}", record.to_json()),
+            Format::Text => writeln!(self.out, "{}", record.render()),
+        }
+    }
+
+    // まとめ途中の繰り返しも書き出す
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(compressor) = self.compressor.as_mut() {
+            let entries = compressor.finish();
+            self.write_entries(entries)?;
+        }
+        self.out.flush()
+    }
+}
+
+// バイナリ形式のトレースの内容
+pub struct Trace {
+    // --trace-compressで記録したか
+    pub compressed: bool,
+    pub records: Vec<Record>,
+}
+
+// バイナリ形式のトレースを読む
+pub fn read(bytes: &[u8]) -> Result<Trace, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a minix_vm binary trace".to_owned());
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(format!("unsupported trace version {version}"));
+    }
+    let flags = reader.u8()?;
+
+    let mut records = Vec::new();
+    while reader.pos < bytes.len() {
+        records.push(reader.record()?);
+    }
+    Ok(Trace {
+        compressed: flags & COMPRESSED != 0,
+        records,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + n;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(format!("unexpected end of trace at offset {}", self.pos))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn registers(&mut self) -> Result<Registers, String> {
+        let mut regs = Registers::default();
+        for value in regs.reg.iter_mut() {
+            *value = self.u16()?;
+        }
+        regs.flags = self.u16()?;
+        regs.ip = self.u16()?;
+        Ok(regs)
+    }
+
+    fn accesses(&mut self) -> Result<Vec<Access>, String> {
+        (0..self.u16()?)
+            .map(|_| {
+                Ok(Access {
+                    addr: self.u16()?,
+                    width: self.u8()?,
+                    value: self.u16()?,
+                })
+            })
+            .collect()
+    }
+
+    fn syscall(&mut self) -> Result<Syscall, String> {
+        let id = self.u16()?;
+        let mut args = [0; 4];
+        for arg in args.iter_mut() {
+            *arg = self.u16()?;
+        }
+        let result = match self.u8()? {
+            0 => None,
+            _ => Some(self.i32()?),
+        };
+        let len = self.u16()? as usize;
+        let data = self.take(len)?.to_vec();
+        Ok(Syscall {
+            id,
+            args,
+            result,
+            data,
+        })
+    }
+
+    fn record(&mut self) -> Result<Record, String> {
+        let step = self.u64()?;
+        let before = self.registers()?;
+        let after = self.registers()?;
+        let len = self.u8()? as usize;
+        let bytes = self.take(len)?.to_vec();
+        let reads = self.accesses()?;
+        let writes = self.accesses()?;
+        let syscalls = (0..self.u8()?)
+            .map(|_| self.syscall())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Record {
+            step,
+            bytes,
+            before,
+            after,
+            reads,
+            writes,
+            syscalls,
+        })
+    }
+}
@@ -1,45 +1,308 @@
-use libc::O_RDWR;
-
-use crate::VM_MODE;
-
-use super::asm::Assembly;
+use super::asm::{Assembly, Traced};
 use super::bin::{bytes_to_16bit_little_endian, BinaryManager};
-use super::constant::syscall::{BRK, CLOSE, EXIT, IOCTL, LSEEK, OPEN, READ, WRITE};
+use super::calls::{self, CallTrace};
+use super::compress::{Compressor, Entry, Step};
+use super::constant::syscall::{BRK, CLOSE, CREAT, EXIT, IOCTL, LSEEK, OPEN, READ, WRITE};
+use super::coverage::Coverage;
+use super::cycles::{self, Cpu};
+use super::decode::{self, Decoder};
+use super::memory::{self, Heatmap, Layout};
+use super::profile::Profile;
+use super::stack::StackUsage;
+use super::stats::Stats;
+use super::symbol::SymbolTable;
+use super::trace::{self, Access, Accesses, Record, Registers, Syscall};
 use super::{
     opcode::Opcode,
     operand::{Operand, EA},
     reg::{Reg16, Reg8, Register},
 };
-use std::ffi::{c_int, CString};
-use std::fmt::Debug;
+use std::cell::RefCell;
+use std::ffi::c_int;
+use std::fmt::{self, Debug};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::os::raw::c_void;
-use std::path::Path;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Component, Path, PathBuf};
 use std::process::exit;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 const DATA2_SIZE: usize = 20;
+const DEFAULT_ENV: &str = "PATH=/usr:/usr/bin";
+// 経過時間の確認は毎命令ではなく，この命令数ごとに行う
+const TIMEOUT_CHECK_INTERVAL: usize = 1024;
+pub const STATUS_HEADER: &str = " AX   BX   CX   DX   SP   BP   SI   DI  FLAGS IP";
 
-#[derive(Debug)]
 pub struct VM {
     pub(crate) reg: [u16; 8], // レジスタの値．添字でアクセスする
     flags: u16,
     pub(crate) ip: u16,
     pub(crate) ram: Ram,
+    #[allow(dead_code)]
     pub text_size: usize,
+    pub symbols: SymbolTable,
+    config: Config,
+    steps: usize,
+    halt: Option<Halt>,
+    last_ip: u16,
+    started: Option<Instant>,
+    output_bytes: usize,
+    stats: Option<Stats>,
+    profile: Option<Profile>,
+    cycles: u64,
+    coverage: Option<Coverage>,
+    // --trace-formatがjsonl，binのときのトレース．テキストのトレースは出力しない
+    recorder: Option<trace::Writer>,
+    syscalls: Vec<Syscall>,
+    // 実行中の命令をトレースするか
+    traced: bool,
+    // --trace-fromに達してから--trace-untilまでの間か
+    trace_window: bool,
+    // --trace-compressでテキストのトレースの繰り返しをまとめる．pendingは実行中の命令の行
+    compressor: Option<Compressor<String>>,
+    pending: Option<String>,
+    // データセグメントの配置．brkで更新する
+    layout: Layout,
+    heatmap: Option<Heatmap>,
+    calls: Option<CallTrace>,
+    stack_usage: Option<StackUsage>,
+    call_stack: CallStack,
+    // open，creatで開いたファイル．添字がゲストのfdで，0から2は標準入出力なので使わない．
+    // ホストのfdを渡さないので，gradeの並列実行で他のテストのファイルを閉じない
+    files: Vec<Option<File>>,
+}
+
+// 実行時の設定．以前のVM_MODE等のグローバル変数やcargo featureを置き換える
+pub struct Config {
+    // トレース(VMモード)の出力先．Noneならトレースしない
+    pub trace: Option<Box<dyn Write>>,
+    // トレースの書式．Text以外ではメモリへのアクセスとシステムコールも記録する
+    pub trace_format: trace::Format,
+    // トレースする命令の選択．選ばなかった命令はトレースの処理をせずに実行する
+    pub trace_filter: trace::Filter,
+    // 同じipの並びの繰り返しを1行にまとめる
+    pub trace_compress: bool,
+    // ゲストがfd 1, 2にwriteした内容の出力先．トレースには常に埋め込まれる
+    pub output: Option<Box<dyn Write>>,
+    pub error: Option<Box<dyn Write>>,
+    pub max_steps: Option<usize>,
+    // 以下の制限に達した場合は，それぞれ異なる理由で停止する
+    pub max_instructions: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub max_output_bytes: Option<usize>,
+    // openで参照するファイルのルートディレクトリ
+    pub root: Option<PathBuf>,
+    pub env: Vec<String>,
+    // fd 0からのreadをこのファイルで置き換える
+    pub stdin: Option<File>,
+    // 命令の統計を集計する
+    pub stats: bool,
+    // 関数ごとの実行命令数を呼び出し木として集計する
+    pub profile: bool,
+    // クロック数を数えるCPU．トレースに命令ごとのクロック数を付け，プロファイルはクロック数で数える
+    pub cycles: Option<Cpu>,
+    // 実行した命令と条件分岐の結果を記録する
+    pub coverage: bool,
+    // ゲストの命令によるメモリの読み書きを1行ずつ書く出力先
+    pub mem_trace: Option<Box<dyn Write>>,
+    // メモリの読み書きの回数をアドレスごとに数える
+    pub mem_heatmap: bool,
+    // 関数の呼び出しと戻り値の出力先
+    pub call_trace: Option<Box<dyn Write>>,
+    // 引数の型がわからない関数で表示するスタックのワード数
+    pub call_args: usize,
+    // 全体と関数ごとのスタックの最大の使用量を記録する
+    pub stack_usage: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            trace: None,
+            trace_format: trace::Format::Text,
+            trace_filter: trace::Filter::default(),
+            trace_compress: false,
+            output: Some(Box::new(io::stdout())),
+            error: Some(Box::new(io::stderr())),
+            max_steps: None,
+            max_instructions: None,
+            timeout: None,
+            max_output_bytes: None,
+            root: None,
+            env: vec![DEFAULT_ENV.to_owned()],
+            stdin: None,
+            stats: false,
+            profile: false,
+            cycles: None,
+            coverage: false,
+            mem_trace: None,
+            mem_heatmap: false,
+            call_trace: None,
+            call_args: calls::DEFAULT_ARGS,
+            stack_usage: false,
+        }
+    }
+}
+
+// Configの出力先に渡して，書き込まれた内容をメモリに溜めるバッファ．
+// クローンは同じバッファを共有するので，VMに渡した後も内容を取り出せる
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// VMが停止した理由
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Halt {
+    Exit(u16),
+    StepLimit,
+    InstructionLimit,
+    Timeout,
+    OutputLimit,
+    // テキストセグメントの外や解釈できない命令に達した
+    Fault,
+}
+
+impl Halt {
+    // 実行制限による停止か
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            Halt::InstructionLimit | Halt::Timeout | Halt::OutputLimit
+        )
+    }
+
+    // 停止した位置を報告すべき異常な停止か
+    pub fn is_abnormal(&self) -> bool {
+        self.is_limit() || *self == Halt::Fault
+    }
+
+    // minix_vm自体の終了ステータス
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            Halt::Exit(status) => *status as i32,
+            Halt::StepLimit => 0,
+            Halt::OutputLimit => 122,
+            Halt::InstructionLimit => 123,
+            Halt::Timeout => 124,
+            // SIGILLで終了したシェルと同じ
+            Halt::Fault => 132,
+        }
+    }
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Halt::Exit(status) => write!(f, "exit({status})"),
+            Halt::StepLimit => write!(f, "step limit reached"),
+            Halt::InstructionLimit => write!(f, "instruction limit reached"),
+            Halt::Timeout => write!(f, "timeout"),
+            Halt::OutputLimit => write!(f, "output limit reached"),
+            Halt::Fault => write!(f, "invalid instruction"),
+        }
+    }
+}
+
+// 実行中の関数の呼び出し
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub name: String,
+    pub return_address: u16,
+}
+
+// callとretから関数の呼び出しをたどる．--call-trace，--profile，--stack-usageが共有する
+#[derive(Debug, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn call(&mut self, name: String, return_address: u16) {
+        self.frames.push(CallFrame {
+            name,
+            return_address,
+        });
+    }
+
+    // retの直後．戻り先が呼び出し時の戻りアドレスと一致しないretは関数の中の分岐とみなす．
+    // 戻った呼び出しを外側から返す
+    pub fn ret(&mut self, address: u16) -> Vec<CallFrame> {
+        match self
+            .frames
+            .iter()
+            .rposition(|f| f.return_address == address)
+        {
+            Some(pos) => self.frames.split_off(pos),
+            None => Vec::new(),
+        }
+    }
 }
 
+// 8086のアドレス空間
+const RAM_SIZE: usize = 0xfffff;
+
 #[derive(Debug)]
 pub struct Ram {
-    cells: [u8; 0xfffff],
+    cells: Box<[u8]>,
     pub text_base: u16,
     pub data_base: u16,
+    // read_*，write_*によるアクセスの記録．Noneなら記録しない
+    accesses: Option<RefCell<Accesses>>,
 }
 
 impl Ram {
-    pub fn new(cells: [u8; 0xfffff], text_base: u16, data_base: u16) -> Self {
+    pub fn new(cells: Box<[u8]>, text_base: u16, data_base: u16) -> Self {
         Ram {
             cells,
             text_base,
             data_base,
+            accesses: None,
+        }
+    }
+
+    // 記録を始めるか止める．それまでの記録は捨てる
+    pub fn record_accesses(&mut self, on: bool) {
+        self.accesses = on.then(RefCell::default);
+    }
+
+    // 前回からのアクセスを取り出す
+    pub fn take_accesses(&self) -> Accesses {
+        self.accesses
+            .as_ref()
+            .map_or_else(Accesses::default, |a| a.take())
+    }
+
+    fn log_read(&self, addr: u16, width: u8, value: u16) {
+        if let Some(a) = &self.accesses {
+            a.borrow_mut().reads.push(Access { addr, width, value });
+        }
+    }
+
+    fn log_write(&self, addr: u16, width: u8, value: u16) {
+        if let Some(a) = &self.accesses {
+            a.borrow_mut().writes.push(Access { addr, width, value });
         }
     }
 
@@ -48,19 +311,33 @@ impl Ram {
     }
 
     pub fn read_i8(&self, addr: u16) -> i8 {
-        let base = (self.data_base as usize + addr as usize) as usize;
+        let base = self.data_base as usize + addr as usize;
+        self.log_read(addr, 1, self.cells[base] as u16);
         self.cells[base] as i8
     }
 
     pub fn read_i16(&self, addr: u16) -> i16 {
-        let base = (self.data_base as usize + addr as usize) as usize;
-        bytes_to_16bit_little_endian(&self.cells[base..=(base + 1)]) as i16
+        let base = self.data_base as usize + addr as usize;
+        let value = bytes_to_16bit_little_endian(&self.cells[base..=(base + 1)]);
+        self.log_read(addr, 2, value);
+        value as i16
     }
 
+    #[allow(dead_code)]
     pub fn read_text(&self, offset: u16) -> u8 {
         self.cells[self.text_base as usize + offset as usize]
     }
 
+    // データセグメント全体．アクセスは記録しない
+    pub fn data(&self) -> &[u8] {
+        &self.cells[self.data_base as usize..self.data_base as usize + 0x10000]
+    }
+
+    // テキストセグメント全体．データセグメントの直前まで
+    pub fn text(&self) -> &[u8] {
+        &self.cells[self.text_base as usize..self.data_base as usize]
+    }
+
     pub fn read_data_size(&self, offset: usize) -> &[u8] {
         let base = self.data_base as usize + offset;
         &self.cells[base..base + DATA2_SIZE]
@@ -72,23 +349,33 @@ impl Ram {
         &self.cells[data_base..data_base + offset as usize]
     }
 
+    pub fn read_data_slice_mut(&mut self, base: u16, offset: u16) -> &mut [u8] {
+        let data_base = self.data_base as usize + base as usize;
+
+        &mut self.cells[data_base..data_base + offset as usize]
+    }
+
     pub fn write_i8(&mut self, addr: u16, value: i8) {
-        let base = (self.data_base as usize + addr as usize) as usize;
-        self.cells[base as usize] = value as u8;
+        let base = self.data_base as usize + addr as usize;
+        self.log_write(addr, 1, value as u8 as u16);
+        self.cells[base] = value as u8;
     }
 
+    #[allow(dead_code)]
     pub fn direct_write_i8(&mut self, addr: u16, value: i8) {
         self.cells[addr as usize] = value as u8;
     }
 
     pub fn write_i16(&mut self, addr: u16, value: i16) {
-        let base = (self.data_base as usize + addr as usize) as usize;
+        let base = self.data_base as usize + addr as usize;
+        self.log_write(addr, 2, value as u16);
 
         /* エンディアンの相違を考慮しながら配置 */
         self.cells[base] = (value & 0xff) as u8;
         self.cells[base + 1] = (value >> 8 & 0xff) as u8;
     }
 
+    #[allow(dead_code)]
     pub fn direct_write_i16(&mut self, addr: u16, value: i16) {
         /* エンディアンの相違を考慮しながら配置 */
         self.cells[addr as usize] = (value & 0xff) as u8;
@@ -114,21 +401,25 @@ impl Ram {
 }
 
 trait ManageFlags {
+    #[allow(dead_code)]
     fn evaluate_overflow(&mut self, dst: u16, result: u16);
     fn evaluate_cmp(&mut self, val: u16, dst: u16, src: u16);
     fn get_flag(&self, flag: Flag) -> bool;
     fn set_flag(&mut self, flag: Flag, val: bool);
     fn evaluate_calculation_result(&mut self, result: u16);
-    fn print_flags(&self);
+    fn flags_string(&self) -> String;
 }
 
 enum Flag {
     CF = 0, // Carry Flag
     PF = 2,
+    #[allow(dead_code)]
     AF = 4,
     ZF = 6, // Zero Flag
     SF = 7,
+    #[allow(dead_code)]
     TF = 8,
+    #[allow(dead_code)]
     IF = 9,
     DF = 10,
     OF = 11,
@@ -157,55 +448,113 @@ impl ManageFlags for VM {
         self.set_flag(Flag::OF, (result >> 15 & 1) != (dst >> 15 & 1));
     }
 
-    fn evaluate_cmp(&mut self, result: u16, dst: u16, src: u16) {
+    fn evaluate_cmp(&mut self, result: u16, dst: u16, _src: u16) {
         self.set_flag(Flag::CF, (result >> 15 & 1) != (dst >> 15 & 1));
     }
 
-    fn print_flags(&self) {
+    fn flags_string(&self) -> String {
         let of_char = if self.get_flag(Flag::OF) { 'O' } else { '-' }; // zero flag
         let sf_char = if self.get_flag(Flag::SF) { 'S' } else { '-' }; // sign flag
         let zf_char = if self.get_flag(Flag::ZF) { 'Z' } else { '-' }; // zero flag
         let cf_char = if self.get_flag(Flag::CF) { 'C' } else { '-' }; // cmp flag
 
-        if unsafe { VM_MODE } {
-            print!("{}{}{}{}", of_char, sf_char, zf_char, cf_char);
-        }
+        format!("{}{}{}{}", of_char, sf_char, zf_char, cf_char)
     }
 }
 
 impl VM {
-    pub fn new(mut bytes: Vec<u8>, args: &[String]) -> Self {
-        let mut bm = BinaryManager::new(bytes.clone());
+    #[allow(dead_code)]
+    pub fn new(bytes: Vec<u8>, args: &[String]) -> Result<Self, String> {
+        Self::with_config(bytes, args, Config::default())
+    }
+
+    // 実行ファイルでないものや途中で切れたものはエラーにする
+    pub fn with_config(
+        bytes: Vec<u8>,
+        args: &[String],
+        mut config: Config,
+    ) -> Result<Self, String> {
+        let bm = BinaryManager::new(bytes.clone());
+        bm.validate()?;
+        let symbols = bm.get_symbols();
 
         let header_size = bm.get_header_size();
         let text_size = bm.get_text_size();
         let data_size = bm.get_data_size();
-        let bss_size = bm.get_bss_size();
+        let layout = Layout::new(data_size as u16, bm.get_bss_size() as u16);
+        let entry_point = bm.get_header().map_or(0, |h| h.entry_point as u16);
 
         let data_base_ptr = header_size + text_size;
-        let bss_base_ptr = data_base_ptr + data_size;
 
         let text = &bytes[header_size..header_size + text_size];
         let data = &bytes[data_base_ptr..data_base_ptr + data_size];
 
-        let mut cells: [u8; 0xfffff] = [0u8; 0xfffff];
+        let mut cells = vec![0u8; RAM_SIZE].into_boxed_slice();
 
         cells[..text_size].copy_from_slice(text);
         cells[text_size..text_size + data_size].copy_from_slice(data);
 
-        let ram = Ram::new(cells, 0, text_size as u16);
+        let mut ram = Ram::new(cells, 0, text_size as u16);
+        let recorder = match config.trace_format {
+            trace::Format::Text => None,
+            format => config
+                .trace
+                .take()
+                .map(|w| trace::Writer::new(w, format, config.trace_compress)),
+        };
+        let compressor =
+            (config.trace_compress && config.trace.is_some()).then(Compressor::default);
+        ram.record_accesses(recorder.is_some());
+        let trace_window = config.trace_filter.from.is_none();
+        let stats = config.stats.then(Stats::default);
+        let coverage = config.coverage.then(Coverage::default);
+        let heatmap = config.mem_heatmap.then(Heatmap::default);
+        let calls = config
+            .call_trace
+            .take()
+            .map(|w| CallTrace::new(w, config.call_args));
+        let profile = config
+            .profile
+            .then(|| Profile::new(symbols.describe(entry_point)));
 
         let mut vm = Self {
             reg: [0; 8],
-            ip: 0,
+            ip: entry_point,
             flags: 0,
             ram,
             text_size,
+            symbols,
+            config,
+            steps: 0,
+            halt: None,
+            last_ip: 0,
+            started: None,
+            output_bytes: 0,
+            stats,
+            profile,
+            cycles: 0,
+            coverage,
+            recorder,
+            syscalls: Vec::new(),
+            traced: true,
+            trace_window,
+            compressor,
+            pending: None,
+            layout,
+            heatmap,
+            calls,
+            stack_usage: None,
+            call_stack: CallStack::default(),
+            files: vec![None, None, None],
         };
 
         vm.init(args);
+        if vm.config.stack_usage {
+            let root = vm.symbols.describe(vm.ip);
+            vm.stack_usage = Some(StackUsage::new(root, vm.get_reg16(Reg16::SP)));
+        }
 
-        vm
+        Ok(vm)
     }
 
     pub fn init(&mut self, args: &[String]) {
@@ -213,11 +562,11 @@ impl VM {
     }
 
     pub fn make_stack_frame(&mut self, args: &[String]) {
-        let cstr_byte = "PATH=/usr:/usr/bin".as_bytes();
+        let env = self.config.env.clone();
         let mut args = args.to_owned();
 
-        if args.len() != 0 {
-            args[0] = Path::new(args.get(0).unwrap())
+        if !args.is_empty() {
+            args[0] = Path::new(&args[0])
                 .file_name()
                 .unwrap()
                 .to_str()
@@ -228,21 +577,29 @@ impl VM {
         self.reg[Reg16::SP as usize] = 0xfffe;
 
         /* env content in */
-        for i in (0..cstr_byte.len()).rev() {
-            self.ram
-                .write_i8(self.get_reg16(Reg16::SP), cstr_byte[i] as i8);
-            self.reg[Reg16::SP as usize] -= 1;
+        let mut envp = Vec::new();
+        for (i, s) in env.iter().enumerate().rev() {
+            // 最上位の文字列の終端は0xffffの初期値(0)を使う
+            if i != env.len() - 1 {
+                self.ram.write_i8(self.get_reg16(Reg16::SP), '\0' as i8);
+                self.reg[Reg16::SP as usize] -= 1;
+            }
+
+            for &b in s.as_bytes().iter().rev() {
+                self.ram.write_i8(self.get_reg16(Reg16::SP), b as i8);
+                self.reg[Reg16::SP as usize] -= 1;
+            }
+
+            envp.push(self.get_reg16(Reg16::SP) + 1);
         }
-        let env_ptr = self.get_reg16(Reg16::SP) + 1;
 
         let mut argsv = Vec::new();
-        for i in (0..args.len()).rev() {
+        for arg in args.iter().rev() {
             self.ram.write_i8(self.get_reg16(Reg16::SP), '\0' as i8);
             self.reg[Reg16::SP as usize] -= 1;
 
-            for j in (0..args[i].len()).rev() {
-                self.ram
-                    .write_i8(self.get_reg16(Reg16::SP), args[i].as_bytes()[j] as i8);
+            for &b in arg.as_bytes().iter().rev() {
+                self.ram.write_i8(self.get_reg16(Reg16::SP), b as i8);
                 self.reg[Reg16::SP as usize] -= 1;
             }
 
@@ -255,18 +612,18 @@ impl VM {
 
         self.reg[Reg16::SP as usize] -= 2;
 
-        self.ram
-            .write_i16(self.get_reg16(Reg16::SP), env_ptr as i16);
-        self.reg[Reg16::SP as usize] -= 2;
+        for &ptr in envp.iter() {
+            self.ram.write_i16(self.get_reg16(Reg16::SP), ptr as i16);
+            self.reg[Reg16::SP as usize] -= 2;
+        }
 
         self.ram.write_i8(self.reg[Reg16::SP as usize], '\0' as i8);
         self.reg[Reg16::SP as usize] -= 1;
         self.ram.write_i8(self.reg[Reg16::SP as usize], '\0' as i8);
         self.reg[Reg16::SP as usize] -= 1;
 
-        for i in 0..argsv.len() {
-            self.ram
-                .write_i16(self.get_reg16(Reg16::SP), argsv[i] as i16);
+        for &ptr in argsv.iter() {
+            self.ram.write_i16(self.get_reg16(Reg16::SP), ptr as i16);
             self.reg[Reg16::SP as usize] -= 2;
         }
 
@@ -275,35 +632,443 @@ impl VM {
     }
 
     // 一連の処理の実行
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Halt {
         self.print_status_header();
 
-        loop {
-            /* fetch & decode */
-            let inst = self.decode().unwrap();
-            self.print_current_status(&inst);
+        let halt = loop {
+            if let Some(halt) = self.step() {
+                break halt;
+            }
+        };
 
-            /* decode */
-            let (opcode, dst_val, src_val) = self.fetch_operand_value(&inst);
+        self.flush();
+        halt
+    }
 
-            /* execute */
-            let result = self.execute(opcode, dst_val, src_val);
+    // 1命令だけ実行する．停止した場合はその理由を返す
+    pub fn step(&mut self) -> Option<Halt> {
+        if let Some(halt) = self.halt {
+            return Some(halt);
+        }
 
-            /* evaluate */
-            self.evaluate(&opcode, result, dst_val, src_val);
+        if let Some(halt) = self.check_limits() {
+            self.halt = Some(halt);
+            return self.halt;
+        }
+
+        // 命令の実行前のレジスタ．前の命令の後のアクセスは捨てる
+        let before = self.recorder.is_some().then(|| self.registers());
 
-            /* store */
-            if opcode.is_assign_effect() {
-                self.store(&inst, result);
+        /* fetch & decode */
+        self.last_ip = self.ip;
+        let Some(inst) = self.decode() else {
+            self.halt = Some(Halt::Fault);
+            return self.halt;
+        };
+
+        // 前の命令の後のアクセスは捨て，トレースしない命令では記録しない
+        self.traced = self.selects(&inst);
+        let before = before.filter(|_| self.traced);
+        if self.recorder.is_some() || self.records_memory() {
+            self.ram
+                .record_accesses(self.traced || self.records_memory());
+        }
+        // トレースが途切れたら繰り返しのまとめも終える
+        if self.compressor.is_some() {
+            if self.traced {
+                self.pending = Some(String::new());
+            } else {
+                self.finish_compression();
             }
+        }
+
+        self.print_current_status(&inst);
+        self.steps += 1;
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record(&inst);
+        }
+
+        /* decode */
+        let (opcode, dst_val, src_val) = self.fetch_operand_value(&inst);
+
+        /* execute */
+        let cx = self.get_reg16(Reg16::CX);
+        let result = self.execute(opcode, dst_val, src_val);
+
+        let clocks = self.count_cycles(&inst, cx);
+        let taken = self.branched(&inst);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(&inst, taken);
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.count(clocks.map_or(1, u64::from));
+        }
+
+        // exitした場合や出力の上限に達した場合はその場で止める
+        if self.halt.is_some() {
+            self.finish_step(&inst, before);
+            return self.halt;
+        }
+
+        /* evaluate */
+        self.evaluate(&opcode, result, dst_val, src_val);
+
+        /* store */
+        if opcode.is_assign_effect() {
+            self.store(&inst, result);
+        }
+
+        if self.profile.is_some() || self.calls.is_some() || self.stack_usage.is_some() {
+            self.track_call(&inst);
+        }
+        let sp = self.get_reg16(Reg16::SP);
+        if let Some(stack) = self.stack_usage.as_mut() {
+            stack.observe(sp, self.steps);
+        }
+
+        if let Some(clocks) = clocks {
+            let total = self.cycles;
+            self.trace(format_args!("  ; {clocks} clk, total {total}"));
+        }
+
+        /* 改行 */
+        self.trace(format_args!("\n"));
 
-            if unsafe { VM_MODE } {
-                /* 改行 */
-                println!();
+        self.finish_step(&inst, before);
+
+        None
+    }
+
+    // 実行した命令のメモリへのアクセスとトレースを書く
+    fn finish_step(&mut self, asm: &Assembly, before: Option<Registers>) {
+        let accesses = self.take_accesses(asm);
+        self.record_memory(asm, &accesses);
+        self.write_record(asm, before, accesses);
+        self.compress_step(asm);
+    }
+
+    // Config::trace_filterでこの命令をトレースするか
+    fn selects(&mut self, asm: &Assembly) -> bool {
+        let filter = &self.config.trace_filter;
+        if filter.is_empty() {
+            return true;
+        }
+
+        if filter.from == Some(asm.address) {
+            self.trace_window = true;
+        }
+        let open = self.trace_window;
+        if filter.until == Some(asm.address) {
+            self.trace_window = false;
+        }
+        open && filter.selects(self.steps + 1, asm)
+    }
+
+    fn registers(&self) -> Registers {
+        Registers {
+            reg: self.reg,
+            flags: self.flags,
+            ip: self.ip,
+        }
+    }
+
+    fn records_memory(&self) -> bool {
+        self.config.mem_trace.is_some() || self.heatmap.is_some()
+    }
+
+    // 実行した命令のメモリへのアクセス
+    fn take_accesses(&mut self, asm: &Assembly) -> Accesses {
+        let mut accesses = self.ram.take_accesses();
+        // VMはオペランドを両方読むので，leaとメモリへのmovの読み出しは実際のアクセスではない
+        let instruction = &asm.instruction;
+        let to_memory = matches!(instruction.operand1, Some(Operand::EffectiveAddress(_)));
+        let no_reads = match instruction.opcode {
+            Opcode::Lea => true,
+            Opcode::MovRmToFromReg
+            | Opcode::MovImmediateRegisterMemory
+            | Opcode::MovImmediateRegisterMemoryWord
+            | Opcode::MovImmediateRegisterMemoryByte => to_memory,
+            _ => false,
+        };
+        if no_reads {
+            accesses.reads.clear();
+        }
+        accesses
+    }
+
+    // --mem-trace，--mem-heatmapにアクセスを渡す
+    fn record_memory(&mut self, asm: &Assembly, accesses: &Accesses) {
+        let reads = accesses.reads.iter().map(|a| (a, false));
+        let writes = accesses.writes.iter().map(|a| (a, true));
+        for (access, write) in reads.chain(writes) {
+            if let Some(w) = self.config.mem_trace.as_mut() {
+                let region = self.layout.region(access.addr);
+                let line = memory::render_access(self.steps, asm.address, write, access, region);
+                let _ = writeln!(w, "{line}");
+            }
+            if let Some(heatmap) = self.heatmap.as_mut() {
+                heatmap.record(access, write);
             }
         }
     }
 
+    // jsonl，binのトレースに1命令分を書く
+    fn write_record(&mut self, asm: &Assembly, before: Option<Registers>, accesses: Accesses) {
+        let Some(before) = before else {
+            return;
+        };
+
+        let record = Record {
+            step: self.steps as u64,
+            bytes: asm.bytes(),
+            before,
+            after: self.registers(),
+            reads: accesses.reads,
+            writes: accesses.writes,
+            syscalls: std::mem::take(&mut self.syscalls),
+        };
+        if let Some(recorder) = self.recorder.as_mut() {
+            let _ = recorder.write(&record);
+        }
+    }
+
+    // --trace-compressで実行した命令の行を渡し，まとめ終えた分を書く
+    fn compress_step(&mut self, asm: &Assembly) {
+        let Some(item) = self.pending.take() else {
+            return;
+        };
+        let step = Step {
+            step: self.steps as u64,
+            ip: asm.address,
+            after: self.registers(),
+            item,
+        };
+        let entries = self.compressor.as_mut().unwrap().push(step);
+        self.write_entries(entries);
+    }
+
+    fn finish_compression(&mut self) {
+        if let Some(compressor) = self.compressor.as_mut() {
+            let entries = compressor.finish();
+            self.write_entries(entries);
+        }
+    }
+
+    fn write_entries(&mut self, entries: Vec<Entry<String>>) {
+        let Some(w) = self.config.trace.as_mut() else {
+            return;
+        };
+        for entry in entries {
+            let _ = match entry {
+                Entry::Step(line) => w.write_all(line.as_bytes()),
+                Entry::Loop(repeat) => writeln!(w, "{}", repeat.render()),
+            };
+        }
+    }
+
+    // 実行した命令が次の命令以外に進んだか
+    fn branched(&self, asm: &Assembly) -> bool {
+        self.ip != asm.address.wrapping_add(asm.size as u16)
+    }
+
+    // 実行した命令のクロック数．cxは実行前の値
+    fn count_cycles(&mut self, asm: &Assembly, cx: u16) -> Option<u32> {
+        let cpu = self.config.cycles?;
+        let taken = self.branched(asm);
+        // repは減ったcxの分だけ繰り返した
+        let count = if cycles::shifts_by_cl(asm) {
+            cx & 0xff
+        } else {
+            cx.wrapping_sub(self.get_reg16(Reg16::CX))
+        };
+
+        let clocks = cycles::cycles(cpu, asm, taken, count);
+        self.cycles += clocks as u64;
+        if let Some(stats) = self.stats.as_mut() {
+            stats.count_cycles(cpu, clocks);
+        }
+        Some(clocks)
+    }
+
+    // call，retの後のipから呼び出しをたどる．--call-traceには引数と戻り値も書く
+    fn track_call(&mut self, asm: &Assembly) {
+        let ip = self.ip;
+        match asm.instruction.opcode {
+            Opcode::CallWithinDirect => {
+                let name = self.symbols.describe(ip);
+                let return_address = asm.address + asm.size as u16;
+                let sp = self.get_reg16(Reg16::SP);
+                let depth = self.call_stack.depth();
+                if let Some(calls) = self.calls.as_mut() {
+                    calls.call(&name, depth, sp, self.ram.data());
+                }
+                if let Some(stack) = self.stack_usage.as_mut() {
+                    stack.call(name.clone(), sp);
+                }
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.call(name.clone());
+                }
+                self.call_stack.call(name, return_address);
+            }
+            Opcode::RetWithinSegment | Opcode::RetWithinSegAddingImmedToSp => {
+                let returned = self.call_stack.ret(ip);
+                let Some(frame) = returned.first() else {
+                    return;
+                };
+                let (ax, dx) = (self.get_reg16(Reg16::AX), self.get_reg16(Reg16::DX));
+                let depth = self.call_stack.depth();
+                if let Some(calls) = self.calls.as_mut() {
+                    calls.ret(&frame.name, depth, ax, dx, self.ram.data());
+                }
+                if let Some(stack) = self.stack_usage.as_mut() {
+                    stack.ret(returned.len());
+                }
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.ret(returned.len());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_limits(&mut self) -> Option<Halt> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+
+        if self.config.max_steps.is_some_and(|max| self.steps >= max) {
+            return Some(Halt::StepLimit);
+        }
+
+        if self
+            .config
+            .max_instructions
+            .is_some_and(|max| self.steps >= max)
+        {
+            return Some(Halt::InstructionLimit);
+        }
+
+        if let Some(timeout) = self.config.timeout {
+            if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && started.elapsed() >= timeout {
+                return Some(Halt::Timeout);
+            }
+        }
+
+        None
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // 最初の命令を実行してからの経過時間
+    pub fn elapsed(&self) -> Duration {
+        self.started.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    pub fn stack_usage(&self) -> Option<&StackUsage> {
+        self.stack_usage.as_ref()
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    #[cfg(test)]
+    // 実行したクロック数．Config::cyclesを指定したときのみ
+    pub fn cycles(&self) -> Option<u64> {
+        self.config.cycles.map(|_| self.cycles)
+    }
+
+    // 停止時に表示する要約
+    pub fn summary(&self, halt: Halt) -> String {
+        format!(
+            "{halt}\n  instructions executed: {}\n  last ip: {:04x}\n  function: {}",
+            self.steps,
+            self.last_ip,
+            self.symbols.describe(self.last_ip)
+        )
+    }
+
+    pub fn flush(&mut self) {
+        self.finish_compression();
+        if let Some(w) = self.config.trace.as_mut() {
+            let _ = w.flush();
+        }
+        if let Some(w) = self.recorder.as_mut() {
+            let _ = w.flush();
+        }
+        if let Some(w) = self.config.mem_trace.as_mut() {
+            let _ = w.flush();
+        }
+        if let Some(calls) = self.calls.as_mut() {
+            calls.flush();
+        }
+        if let Some(w) = self.config.output.as_mut() {
+            let _ = w.flush();
+        }
+        if let Some(w) = self.config.error.as_mut() {
+            let _ = w.flush();
+        }
+    }
+
+    // 現在のipの命令を，ipを進めずにデコードする
+    pub fn current_instruction(&mut self) -> Option<Assembly> {
+        let ip = self.ip;
+        let asm = self.decode();
+        self.ip = ip;
+        asm
+    }
+
+    // VMモードと同じ書式のレジスタ・フラグ・命令の表示
+    pub fn status_line(&self, asm: &Assembly) -> String {
+        let mut line = String::new();
+        for reg in [
+            Reg16::AX,
+            Reg16::BX,
+            Reg16::CX,
+            Reg16::DX,
+            Reg16::SP,
+            Reg16::BP,
+            Reg16::SI,
+            Reg16::DI,
+        ] {
+            line += &format!("{:04x} ", self.reg[reg as usize]);
+        }
+        line += &self.flags_string();
+        line += &format!(" {:?}", Traced(asm));
+        line
+    }
+
+    fn is_tracing(&self) -> bool {
+        self.traced && self.config.trace.is_some()
+    }
+
+    fn trace(&mut self, args: fmt::Arguments) {
+        if !self.traced {
+            return;
+        }
+        if let Some(line) = self.pending.as_mut() {
+            let _ = fmt::Write::write_fmt(line, args);
+        } else if let Some(w) = self.config.trace.as_mut() {
+            let _ = w.write_fmt(args);
+        }
+    }
+
     fn evaluate(&mut self, opcode: &Opcode, result: u16, dst_val: u16, src_val: u16) {
         /* evaluate */
         if opcode.is_calculated() {
@@ -319,20 +1084,18 @@ impl VM {
         }
     }
 
-    pub fn disassemble(&mut self) -> Vec<Assembly> {
-        let mut v = Vec::new();
-        loop {
-            if let Some(asm) = self.decode() {
-                v.push(asm.clone());
+    // ipの命令をデコードして，ipを次の命令へ進める
+    pub fn decode(&mut self) -> Option<Assembly> {
+        let mut decoder = Decoder::at(self.ram.text(), self.ram.text_base, self.ip);
+        let asm = decoder.decode();
+        self.ip = decoder.ip;
 
-                if asm.instruction.opcode == Opcode::Undefined {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
-        v
+        asm
+    }
+
+    #[allow(dead_code)]
+    pub fn disassemble(&self) -> Vec<Assembly> {
+        decode::disassemble(self.ram.text(), self.ram.text_base)
     }
 
     fn store(&mut self, asm: &Assembly, value: u16) {
@@ -393,7 +1156,7 @@ impl VM {
         self.ip = addr;
     }
 
-    fn fetch_operand_value(&self, asm: &Assembly) -> (Opcode, u16, u16) {
+    fn fetch_operand_value(&mut self, asm: &Assembly) -> (Opcode, u16, u16) {
         let dst_val = self.get_val_from_operand(
             asm.instruction.operand1.as_ref(),
             asm.instruction.operand2.as_ref(),
@@ -405,7 +1168,7 @@ impl VM {
             &asm.instruction.opcode,
         );
 
-        (asm.instruction.opcode.clone(), dst_val, src_val)
+        (asm.instruction.opcode, dst_val, src_val)
     }
 
     fn push(&mut self, value: u16) {
@@ -424,7 +1187,7 @@ impl VM {
     }
 
     fn get_val_from_operand(
-        &self,
+        &mut self,
         target_operand: Option<&Operand>,
         pair_operand: Option<&Operand>,
         opcode: &Opcode,
@@ -452,26 +1215,17 @@ impl VM {
 
                 let val = self.ram.read_i16(addr) as u16;
 
-                if unsafe { VM_MODE } {
-                    print!(" ;[{addr:04x}]");
-                }
+                self.trace(format_args!(" ;[{addr:04x}]"));
 
-                if display_byte_flag(pair_operand, &opcode) {
-                    if unsafe { VM_MODE } {
-                        print!("{:02x}", val & 0xff);
-                    }
+                if display_byte_flag(pair_operand, opcode) {
+                    self.trace(format_args!("{:02x}", val & 0xff));
                 } else {
-                    if unsafe { VM_MODE } {
-                        print!("{val:04x}");
-                    }
+                    self.trace(format_args!("{val:04x}"));
                 }
 
                 /* exception case */
-                match opcode {
-                    Opcode::Lea => {
-                        return addr;
-                    }
-                    _ => (),
+                if opcode == &Opcode::Lea {
+                    return addr;
                 }
 
                 val
@@ -497,17 +1251,17 @@ impl VM {
                 }
                 Register::None => todo!(),
             },
-            Some(Operand::Immediate(val)) => {}
+            Some(Operand::Immediate(_)) => {}
             Some(Operand::EffectiveAddress(ea)) => {
                 let addr = self.get_val_from_ea(ea);
 
-                if let Some(Operand::Register(Register::Reg8(r8))) = src_operand {
+                if let Some(Operand::Register(Register::Reg8(_))) = src_operand {
                     self.ram.write_i8(addr, value as i8);
                     return;
                 }
 
                 if *opcode == Opcode::MovImmediateRegisterMemoryByte {
-                    value = value & 0xff;
+                    value &= 0xff;
                     self.ram.write_i8(addr, value as i8);
                     return;
                 }
@@ -598,7 +1352,7 @@ impl VM {
             Opcode::SubRegEither
             | Opcode::SubImmediateRegisterMemory
             | Opcode::SubImmediateFromAccumulator => {
-                let result = (dst as i16 - src as i16) as i16;
+                let result = dst as i16 - src as i16;
 
                 self.set_flag(Flag::CF, dst < src);
                 self.set_flag(Flag::ZF, result == 0);
@@ -609,7 +1363,7 @@ impl VM {
             Opcode::AdcRegEither
             | Opcode::AdcImmediateRegisterMemory
             | Opcode::AdcImmediateFromAccumulator => {
-                let result = (dst as i16 + src as i16 + self.get_flag(Flag::CF) as i16) as i16;
+                let result = dst as i16 + src as i16 + self.get_flag(Flag::CF) as i16;
 
                 self.set_flag(Flag::CF, dst < src && (result as u16 >> 15) != (dst >> 15));
                 self.set_flag(Flag::ZF, result == 0);
@@ -643,7 +1397,7 @@ impl VM {
             Opcode::TestImmediateByte => (dst & 0xff) & (src & 0xff),
 
             Opcode::CallWithinDirect => {
-                self.push(self.ip as u16);
+                self.push(self.ip);
                 self.jmp(dst);
                 0
             }
@@ -670,7 +1424,7 @@ impl VM {
                         break;
                     }
                 }
-                return 0;
+                0
             }
 
             Opcode::RepMovsb => loop {
@@ -740,8 +1494,8 @@ impl VM {
                 let mut src = src;
 
                 if opcode == Opcode::CmpImmediateByte {
-                    dst = dst & 0xff;
-                    src = src & 0xff;
+                    dst &= 0xff;
+                    src &= 0xff;
                 }
                 let result = (dst as i16 - src as i16) as u16;
 
@@ -786,7 +1540,7 @@ impl VM {
                     result |= 0xffff << (16 - src - 1);
                 }
 
-                let last_into_cf = (dst >> src - 1) & 1;
+                let last_into_cf = (dst >> (src - 1)) & 1;
                 self.set_flag(Flag::CF, last_into_cf == 1);
 
                 result
@@ -907,7 +1661,7 @@ impl VM {
             Opcode::IncRegisterMemory | Opcode::IncRegister => dst + 1,
             Opcode::DecRegisterMemory | Opcode::DecRegister => (dst as i16 - 1) as u16,
             Opcode::Mul => {
-                let result = if src == 0 {
+                if src == 0 {
                     let ax = self.get_reg16(Reg16::AX);
                     let res = ax * dst;
 
@@ -924,9 +1678,7 @@ impl VM {
                     self.set_flag(Flag::SF, result >> 15 & 1 == 1);
 
                     result
-                };
-
-                result
+                }
             }
             Opcode::Imul => todo!(),
 
@@ -963,20 +1715,40 @@ impl VM {
 
         let data = bytes_to_16bit_little_endian(&data_segment[18..=19]);
 
+        if let Some(stats) = self.stats.as_mut() {
+            stats.syscall(syscall_id);
+        }
+
+        // jsonl，binのトレースに記録する結果と読み書きしたバイト列
+        let mut result: Option<i64> = None;
+        let mut transferred = Vec::new();
+
         match syscall_id {
             EXIT => {
-                if unsafe { VM_MODE } {
-                    println!("\n<exit({fd})>");
-                }
-
-                exit(1);
+                self.trace(format_args!("\n<exit({fd})>\n"));
+                self.halt = Some(Halt::Exit(fd));
             }
             READ => {
-                let real_addr = unsafe { self.ram.get_real_address(buff) };
-                let x = unsafe { libc::read(fd as i32, real_addr as *mut c_void, size as usize) };
+                let file = match fd {
+                    0 => self.config.stdin.as_mut(),
+                    _ => self.files.get_mut(fd as usize).and_then(Option::as_mut),
+                };
+                let x = match file {
+                    Some(file) => {
+                        let buf = self.ram.read_data_slice_mut(buff, size);
+                        file.read(buf).map_or(-1, |n| n as isize)
+                    }
+                    None if fd == 0 => {
+                        let real_addr = unsafe { self.ram.get_real_address(buff) };
+                        unsafe { libc::read(0, real_addr, size as usize) }
+                    }
+                    None => -1,
+                };
 
-                if unsafe { VM_MODE } {
-                    print!("\n<read({fd}, 0x{buff:04x}, {size}) => {x}>");
+                self.trace(format_args!("\n<read({fd}, 0x{buff:04x}, {size}) => {x}>"));
+                result = Some(x as i64);
+                if x > 0 {
+                    transferred = self.ram.read_data_slice(buff, x as u16).to_vec();
                 }
 
                 // SIが指すアドレスに対して，data2をコピー
@@ -987,26 +1759,59 @@ impl VM {
             }
             WRITE => {
                 // ソースインデックスから取得
-                let bytes = self.ram.read_data_slice(buff, size);
-
-                let target = match std::str::from_utf8(bytes) {
-                    Ok(s) => s,
-                    Err(_) => "",
-                };
-
-                if unsafe { VM_MODE } {
-                    print!("\n<write({fd}, 0x{buff:04x}, {size})");
+                let mut bytes = self.ram.read_data_slice(buff, size).to_vec();
+
+                // 出力の上限を超える分は書き出さずに停止する
+                let mut len = bytes.len();
+                if let Some(max) = self.config.max_output_bytes {
+                    if self.output_bytes + len > max {
+                        len = max.saturating_sub(self.output_bytes);
+                        self.halt = Some(Halt::OutputLimit);
+                    }
+                }
+                self.output_bytes += len;
+                bytes.truncate(len);
+
+                let written = String::from_utf8_lossy(&bytes).into_owned();
+                self.trace(format_args!(
+                    "\n<write({fd}, 0x{buff:04x}, {size}){written}"
+                ));
+
+                // fd 1, 2以外はopen/creatで開いたファイル
+                match fd {
+                    1 => {
+                        if let Some(w) = self.config.output.as_mut() {
+                            let _ = w.write_all(&bytes);
+                        }
+                    }
+                    2 => {
+                        if let Some(w) = self.config.error.as_mut() {
+                            let _ = w.write_all(&bytes);
+                        }
+                    }
+                    _ => {
+                        if let Some(file) = self.files.get_mut(fd as usize).and_then(Option::as_mut)
+                        {
+                            let _ = file.write_all(&bytes);
+                        }
+                    }
                 }
 
-                print!("{target}");
-
-                if unsafe { VM_MODE } {
-                    print!(" => {size}>");
+                // トレースしない命令での出力は，トレースに埋め込む代わりにそのまま書く
+                if !self.traced && matches!(fd, 1 | 2) {
+                    if let Some(w) = self.config.trace.as_mut() {
+                        let _ = w.write_all(&bytes);
+                    }
                 }
 
+                // 上限で切ったときは書いた分だけを返す
+                self.trace(format_args!(" => {len}>"));
+                result = Some(len as i64);
+                transferred = bytes;
+
                 // SIが指すアドレスに対して，data2をコピー
                 let si_val = self.get_reg16(Reg16::SI);
-                self.ram.write_i16(si_val + 2, size as i16);
+                self.ram.write_i16(si_val + 2, len as i16);
 
                 self.set_reg16(Reg16::AX, 0);
             }
@@ -1015,17 +1820,20 @@ impl VM {
                 let bytes = self.ram.read_data_slice(buff, size);
                 let filename = self.ram.get_string(anonymus);
 
-                let target = match std::str::from_utf8(bytes) {
-                    Ok(s) => s,
-                    Err(_) => "",
+                let target = std::str::from_utf8(bytes).unwrap_or("").to_owned();
+
+                let res = match self.resolve_path(&filename) {
+                    Some(path) => {
+                        let file = OpenOptions::new().read(true).write(true).open(path);
+                        file.map_or(-1, |file| self.add_file(file))
+                    }
+                    None => -1,
                 };
 
-                let x = CString::new(filename.clone()).unwrap();
-                let res = unsafe { libc::open(x.as_ptr(), O_RDWR) };
-
-                if unsafe { VM_MODE } {
-                    print!("\n<open(\"{filename}\", {size}){target} => {res}>");
-                }
+                self.trace(format_args!(
+                    "\n<open(\"{filename}\", {size}){target} => {res}>"
+                ));
+                result = Some(res as i64);
 
                 // SIが指すアドレスに対して，data2をコピー
                 let si_val = self.get_reg16(Reg16::SI);
@@ -1033,12 +1841,43 @@ impl VM {
 
                 self.set_reg16(Reg16::AX, 0);
             }
+            CREAT => {
+                let filename = self.ram.get_string(anonymus);
+
+                let res = match self.resolve_path(&filename) {
+                    Some(path) => {
+                        let file = OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .mode(size as u32)
+                            .open(path);
+                        file.map_or(-1, |file| self.add_file(file))
+                    }
+                    None => -1,
+                };
+
+                self.trace(format_args!(
+                    "\n<creat(\"{filename}\", 0{size:03o}) => {res}>"
+                ));
+                result = Some(res as i64);
+
+                let si_val = self.get_reg16(Reg16::SI);
+                self.ram.write_i16(si_val + 2, res as i16);
+
+                self.set_reg16(Reg16::AX, 0);
+            }
             CLOSE => {
-                let res = unsafe { libc::close(fd as c_int) };
+                // 標準入出力はホストのものなので閉じない
+                let res = match self.files.get_mut(fd as usize).and_then(Option::take) {
+                    Some(_) => 0,
+                    None if fd < 3 => 0,
+                    None => -1,
+                };
 
-                if unsafe { VM_MODE } {
-                    print!("\n<close({fd}) => {res}>");
-                }
+                self.trace(format_args!("\n<close({fd}) => {res}>"));
+                result = Some(res as i64);
 
                 // SIが指すアドレスに対して，data2をコピー
                 let si_val = self.get_reg16(Reg16::SI);
@@ -1047,23 +1886,36 @@ impl VM {
                 self.set_reg16(Reg16::AX, 0);
             }
             BRK => {
-                if unsafe { VM_MODE } {
-                    print!("\n<brk(0x{buff:04x}) => 0>");
-                }
+                self.trace(format_args!("\n<brk(0x{buff:04x}) => 0>"));
+                result = Some(0);
 
                 let si_val = self.get_reg16(Reg16::SI);
 
                 self.ram.write_i16(si_val + 2, 0);
                 self.ram.write_i16(addr as u16 + 18, buff as i16);
+                self.layout.brk = buff;
 
                 self.set_reg16(Reg16::AX, 0);
             }
             LSEEK => {
-                let res = unsafe { libc::lseek(fd as i32, buff as i64, size as c_int) };
+                let file = match fd {
+                    0 => self.config.stdin.as_mut(),
+                    _ => self.files.get_mut(fd as usize).and_then(Option::as_mut),
+                };
+                let pos = match size {
+                    0 => Some(SeekFrom::Start(buff as u64)),
+                    1 => Some(SeekFrom::Current(buff as i64)),
+                    2 => Some(SeekFrom::End(buff as i64)),
+                    _ => None,
+                };
+                let res = match (file, pos) {
+                    (Some(file), Some(pos)) => file.seek(pos).map_or(-1, |n| n as i64),
+                    (None, _) if fd == 0 => unsafe { libc::lseek(0, buff as i64, size as c_int) },
+                    _ => -1,
+                };
 
-                if unsafe { VM_MODE } {
-                    print!("\n<lseek({fd}, {buff}, {size}) => {res}>");
-                }
+                self.trace(format_args!("\n<lseek({fd}, {buff}, {size}) => {res}>"));
+                result = Some(res);
 
                 let si_val = self.get_reg16(Reg16::SI);
 
@@ -1076,9 +1928,9 @@ impl VM {
                 self.set_reg16(Reg16::AX, 0);
             }
             IOCTL => {
-                if unsafe { VM_MODE } {
-                    print!("\n<ioctl({fd}, 0x{anonymus:04x}, 0x{data:04x})>");
-                }
+                self.trace(format_args!(
+                    "\n<ioctl({fd}, 0x{anonymus:04x}, 0x{data:04x})>"
+                ));
 
                 let si_val = self.get_reg16(Reg16::SI);
 
@@ -1088,35 +1940,68 @@ impl VM {
 
             _ => {}
         }
+
+        if self.recorder.is_some() && self.traced {
+            self.syscalls.push(Syscall {
+                id: syscall_id,
+                args: [fd, size, anonymus, buff],
+                result: result.map(|r| r as i32),
+                data: transferred,
+            });
+        }
     }
 
-    fn print_status_header(&self) {
-        if unsafe { VM_MODE } {
-            println!(" AX   BX   CX   DX   SP   BP   SI   DI  FLAGS IP");
+    fn print_status_header(&mut self) {
+        self.trace(format_args!("{STATUS_HEADER}\n"));
+    }
+
+    fn print_current_status(&mut self, asm: &Assembly) {
+        if self.is_tracing() {
+            let line = self.status_line(asm);
+            self.trace(format_args!("{line}"));
         }
     }
 
-    fn print_current_status(&self, asm: &Assembly) {
-        self.print_reg_status(Reg16::AX);
-        self.print_reg_status(Reg16::BX);
-        self.print_reg_status(Reg16::CX);
-        self.print_reg_status(Reg16::DX);
-        self.print_reg_status(Reg16::SP);
-        self.print_reg_status(Reg16::BP);
-        self.print_reg_status(Reg16::SI);
-        self.print_reg_status(Reg16::DI);
-        self.print_flags();
+    // openのパスを--rootで指定されたディレクトリからの相対パスに読み替える．
+    // rootの外に出るパスはNone
+    // 空いている最小のfdにファイルを割り当てる
+    fn add_file(&mut self, file: File) -> i32 {
+        let fd = match self.files.iter().skip(3).position(Option::is_none) {
+            Some(i) => i + 3,
+            None => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+        };
+        self.files[fd] = Some(file);
+        fd as i32
+    }
 
-        if unsafe { VM_MODE } {
-            print!(" {:?}", asm);
+    fn resolve_path(&self, filename: &str) -> Option<String> {
+        match self.config.root.as_ref() {
+            Some(root) => Some(root_path(root, filename)?.to_string_lossy().into_owned()),
+            None => Some(filename.to_owned()),
         }
     }
+}
 
-    fn print_reg_status(&self, reg: Reg16) {
-        if unsafe { VM_MODE } {
-            print!("{:04x} ", self.reg[reg as usize]);
+// rootからの相対パスとして..を解決する．rootより上に出たらNone
+pub fn root_path(root: &Path, filename: &str) -> Option<PathBuf> {
+    let mut parts = Vec::new();
+    for component in Path::new(filename).components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
         }
     }
+    Some(
+        parts
+            .iter()
+            .fold(root.to_path_buf(), |path, part| path.join(part)),
+    )
 }
 
 trait Reg8Trait {
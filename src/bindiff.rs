@@ -0,0 +1,191 @@
+use crate::arch::asm::Assembly;
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode::disassemble;
+use crate::arch::explore::{linear, Chunk};
+use crate::arch::symbol::SymbolTable;
+use crate::cli::Cli;
+use crate::diff::{self, Edit};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
+
+// 差分の前後に表示する命令の数
+const CONTEXT: usize = 3;
+
+// 逆アセンブルした実行ファイル
+pub struct Binary {
+    bm: BinaryManager<std::vec::IntoIter<u8>>,
+    symbols: SymbolTable,
+    chunks: Vec<Chunk>,
+}
+
+impl Binary {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let bm = BinaryManager::new(bytes);
+        let symbols = bm.get_symbols();
+        // 分岐を辿ると，書き換えで届かなくなった命令までdbになって差分が大きくなるので線形に読む
+        let chunks = linear(disassemble(bm.get_text(), 0));
+
+        Binary {
+            bm,
+            symbols,
+            chunks,
+        }
+    }
+
+    // 関数の命令の並び．名前がなければテキスト全体
+    fn lines(&self, function: Option<&str>) -> Vec<String> {
+        let range = match function {
+            Some(name) => {
+                let start = self.symbols.get(name).map_or(0, |s| s.value as u16);
+                let end = self
+                    .symbols
+                    .functions()
+                    .map(|s| s.value as u16)
+                    .filter(|&a| a > start)
+                    .min();
+                start..end.unwrap_or(u16::MAX)
+            }
+            None => 0..u16::MAX,
+        };
+
+        self.chunks
+            .iter()
+            .filter(|c| range.contains(&c.address()))
+            .map(|c| match c {
+                Chunk::Code(asm) => self.normalize(asm),
+                Chunk::Data { bytes, .. } => {
+                    let values: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                    format!("db {}", values.join(", "))
+                }
+            })
+            .collect()
+    }
+
+    // アドレスによらない命令の表示．飛び先は関数名からの位置，関数がなければ命令からの相対位置にする
+    fn normalize(&self, asm: &Assembly) -> String {
+        let Some(target) = asm.instruction.target() else {
+            return asm.instruction.render(None);
+        };
+
+        let offset = target.wrapping_sub(asm.address) as i16;
+        let label = if self.symbols.function_at(target).is_some() {
+            self.symbols.describe(target)
+        } else if offset < 0 {
+            format!(".-0x{:x}", -(offset as i32))
+        } else {
+            format!(".+0x{offset:x}")
+        };
+        asm.instruction.render(Some(&label))
+    }
+
+    // 関数名．同じ名前が複数あれば最初のものだけ
+    fn functions(&self) -> Vec<String> {
+        let mut seen = BTreeSet::new();
+        self.symbols
+            .functions()
+            .filter(|s| seen.insert(s.name.as_str()))
+            .map(|s| s.name.clone())
+            .collect()
+    }
+}
+
+// 2つの実行ファイルの差分．同じなら空文字列
+pub fn bindiff(a: &Binary, b: &Binary) -> String {
+    let mut out = String::new();
+    header_diff(&mut out, a, b);
+
+    let (fa, fb) = (a.functions(), b.functions());
+    if fa.is_empty() || fb.is_empty() {
+        // シンボルがなければ全体を1つの列として揃える
+        function_diff(&mut out, "text", &a.lines(None), &b.lines(None));
+        return out;
+    }
+
+    // aの順に，bにしかない関数はその後に並べる
+    let only_b = fb.iter().filter(|f| !fa.contains(f));
+    for name in fa.iter().chain(only_b) {
+        let lines = |binary: &Binary, names: &[String]| {
+            if names.contains(name) {
+                binary.lines(Some(name))
+            } else {
+                Vec::new()
+            }
+        };
+        function_diff(&mut out, name, &lines(a, &fa), &lines(b, &fb));
+    }
+
+    out
+}
+
+fn function_diff(out: &mut String, name: &str, a: &[String], b: &[String]) {
+    if a == b {
+        return;
+    }
+
+    let edits = diff::diff(a, b);
+    let count = |f: fn(&Edit) -> bool| edits.iter().filter(|e| f(e)).count();
+    let removed = count(|e| matches!(e, Edit::Delete(_)));
+    let added = count(|e| matches!(e, Edit::Insert(_)));
+    let status = match (a.is_empty(), b.is_empty()) {
+        (true, _) => "added",
+        (_, true) => "removed",
+        _ => "changed",
+    };
+
+    writeln!(out, "function {name} {status} (-{removed} +{added})").unwrap();
+    out.push_str(&diff::unified(a, b, CONTEXT));
+}
+
+fn header_diff(out: &mut String, a: &Binary, b: &Binary) {
+    let (Some(ha), Some(hb)) = (a.bm.get_header(), b.bm.get_header()) else {
+        return;
+    };
+
+    let fields = [
+        ("flags", ha.flags as u32, hb.flags as u32),
+        ("cpu", ha.cpu_id as u32, hb.cpu_id as u32),
+        ("text", ha.text_size, hb.text_size),
+        ("data", ha.data_size, hb.data_size),
+        ("bss", ha.bss_size, hb.bss_size),
+        ("entry", ha.entry_point, hb.entry_point),
+        ("total", ha.total, hb.total),
+        ("syms", ha.syms, hb.syms),
+    ];
+    for (name, x, y) in fields {
+        if x != y {
+            let delta = y as i64 - x as i64;
+            writeln!(out, "header {name}: 0x{x:04x} -> 0x{y:04x} ({delta:+})").unwrap();
+        }
+    }
+
+    if a.bm.get_data() != b.bm.get_data() {
+        writeln!(out, "data segment contents differ").unwrap();
+    }
+}
+
+// `minix_vm bindiff` の本体．diffと同じく，差分があれば1を返す
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let [other] = cli.args.as_slice() else {
+        eprintln!("minix_vm: bindiff needs two files");
+        return Ok(2);
+    };
+
+    let a = Binary::new(fs::read(&cli.file)?);
+    let b = Binary::new(fs::read(other)?);
+    // 比べられないものは差分なしとせず，diffと同じく2を返す
+    for (path, binary) in [(&cli.file, &a), (other, &b)] {
+        if let Err(err) = binary.bm.validate() {
+            eprintln!("minix_vm: {path}: {err}");
+            return Ok(2);
+        }
+    }
+    let out = bindiff(&a, &b);
+    if out.is_empty() {
+        return Ok(0);
+    }
+
+    write!(io::stdout(), "--- {}\n+++ {other}\n{out}", cli.file)?;
+    Ok(1)
+}
@@ -0,0 +1,214 @@
+use crate::arch::asm::Flow;
+use crate::arch::bin::BinaryManager;
+use crate::arch::explore::{explore, Chunk};
+use crate::arch::function::{EdgeKind, Function};
+use crate::arch::listing::Listing;
+use crate::arch::symbol::SymbolTable;
+use crate::cli::Cli;
+use crate::json::Json;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
+
+pub const FORMATS: [&str; 2] = ["dot", "json"];
+
+// `minix_vm cfg` の本体
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let bm = BinaryManager::new(fs::read(&cli.file)?);
+    let symbols = bm.get_symbols();
+    let entry = bm.get_header().map_or(0, |h| h.entry_point as u16);
+
+    let cfg = match Cfg::build(bm.get_text(), &symbols, entry, cli.function.as_deref()) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            eprintln!("minix_vm: {}: {err}", cli.file);
+            return Ok(1);
+        }
+    };
+
+    match cli.format.as_deref() {
+        Some("json") => writeln!(io::stdout(), "{:#}", cfg.to_json())?,
+        _ => io::stdout().write_all(cfg.to_dot().as_bytes())?,
+    }
+
+    Ok(0)
+}
+
+// 関数ごとの制御フローグラフ
+pub struct Cfg<'a> {
+    pub functions: Vec<Function>,
+    listing: Listing<'a>,
+}
+
+impl<'a> Cfg<'a> {
+    // 関数はシンボル，エントリポイント，callの飛び先．onlyを指定するとその名前の関数だけ
+    pub fn build(
+        text: &[u8],
+        symbols: &'a SymbolTable,
+        entry: u16,
+        only: Option<&str>,
+    ) -> Result<Self, String> {
+        let entries = std::iter::once(entry).chain(symbols.functions().map(|s| s.value as u16));
+        let chunks = explore(text, 0, entries);
+        let listing = Listing::new(&chunks, symbols, entry);
+
+        let mut starts: BTreeSet<u16> = symbols.functions().map(|s| s.value as u16).collect();
+        starts.insert(entry);
+        starts.extend(chunks.iter().filter_map(Chunk::as_code).filter_map(|a| {
+            match a.instruction.flow() {
+                Flow::Call(target) => target,
+                _ => None,
+            }
+        }));
+
+        let mut functions = Vec::new();
+        for &start in &starts {
+            let name = listing
+                .label(start)
+                .unwrap_or_else(|| format!("L_{start:04x}"));
+            if only.is_some_and(|only| only != name) {
+                continue;
+            }
+            functions.push(Function::build(text, start, name, &starts));
+        }
+
+        if let (Some(only), true) = (only, functions.is_empty()) {
+            return Err(format!("no function named `{only}`"));
+        }
+
+        Ok(Cfg { functions, listing })
+    }
+
+    fn node_name(&self, addr: u16) -> String {
+        self.listing
+            .label(addr)
+            .unwrap_or_else(|| format!("{addr:04x}"))
+    }
+
+    fn is_block(&self, addr: u16) -> bool {
+        self.functions.iter().any(|f| f.contains_block(addr))
+    }
+
+    // Graphvizのdot形式．関数ごとにクラスタにまとめる
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let mut external = BTreeSet::new();
+
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for function in &self.functions {
+            writeln!(
+                out,
+                "    subgraph \"cluster_{0}\" {{\n        label=\"{0}\";",
+                dot_escape(&function.name)
+            )
+            .unwrap();
+
+            for block in &function.blocks {
+                let mut label = String::new();
+                if let Some(name) = self.listing.label(block.start) {
+                    label.push_str(&format!("{name}:\\l"));
+                }
+                for asm in &block.instructions {
+                    label.push_str(&dot_escape(&self.listing.line(asm)));
+                    label.push_str("\\l");
+                }
+                writeln!(out, "        \"{:04x}\" [label=\"{label}\"];", block.start).unwrap();
+            }
+            if function.edges.iter().any(|e| e.kind == EdgeKind::Return) {
+                writeln!(
+                    out,
+                    "        \"{:04x}_return\" [label=\"return\", shape=ellipse];",
+                    function.start
+                )
+                .unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+
+            for edge in &function.edges {
+                let to = match (edge.kind, edge.to) {
+                    (EdgeKind::Return, _) => format!("{:04x}_return", function.start),
+                    (_, Some(to)) if self.is_block(to) => format!("{to:04x}"),
+                    (_, Some(to)) => {
+                        external.insert(Some(to));
+                        self.node_name(to)
+                    }
+                    (_, None) => {
+                        external.insert(None);
+                        "(indirect)".to_owned()
+                    }
+                };
+                let style = match edge.kind {
+                    EdgeKind::Call => ", style=dashed",
+                    EdgeKind::Conditional => ", color=blue",
+                    _ => "",
+                };
+                writeln!(
+                    out,
+                    "    \"{:04x}\" -> \"{}\" [label=\"{}\"{style}];",
+                    edge.from,
+                    dot_escape(&to),
+                    edge.kind.name()
+                )
+                .unwrap();
+            }
+        }
+
+        for to in external {
+            let name = match to {
+                Some(to) => self.node_name(to),
+                None => "(indirect)".to_owned(),
+            };
+            writeln!(out, "    \"{}\" [shape=ellipse];", dot_escape(&name)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+
+        out
+    }
+
+    pub fn to_json(&self) -> Json {
+        let functions = self.functions.iter().map(|function| {
+            let blocks = function.blocks.iter().map(|block| {
+                let instructions = block.instructions.iter().map(|asm| {
+                    Json::object([
+                        ("address", asm.address.into()),
+                        ("size", asm.size.into()),
+                        ("text", Json::Str(format!("{:?}", asm.instruction))),
+                    ])
+                });
+                Json::object([
+                    ("start", block.start.into()),
+                    ("end", block.end().into()),
+                    (
+                        "label",
+                        self.listing
+                            .label(block.start)
+                            .map_or(Json::Null, Json::Str),
+                    ),
+                    ("instructions", Json::Array(instructions.collect())),
+                ])
+            });
+            let edges = function.edges.iter().map(|edge| {
+                Json::object([
+                    ("from", edge.from.into()),
+                    ("to", edge.to.map_or(Json::Null, Json::from)),
+                    ("kind", Json::Str(edge.kind.name().to_owned())),
+                ])
+            });
+            Json::object([
+                ("name", Json::Str(function.name.clone())),
+                ("start", function.start.into()),
+                ("blocks", Json::Array(blocks.collect())),
+                ("edges", Json::Array(edges.collect())),
+            ])
+        });
+
+        Json::object([("functions", Json::Array(functions.collect()))])
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,684 @@
+use crate::arch::cycles::{self, Cpu};
+use crate::arch::format::SYNTAXES;
+use crate::arch::symbol::SymbolTable;
+use crate::arch::trace::{self, Filter, Format};
+use crate::arch::vm::Config;
+use crate::arch::{profile, stats};
+use crate::cfg;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: minix_vm [command] [options] <file> [args...]
+       minix_vm grade [options] <spec.toml>
+       minix_vm golden [--bless] [dir]
+       minix_vm disasm [options] (<file> | --hex <bytes>)
+       minix_vm link [options] <file.s>
+       minix_vm cfg [--function <name>] [--format dot|json] <file>
+       minix_vm sections <file>
+       minix_vm bindiff <file> <file>
+       minix_vm patch [-o <path>] [--size <n>] <file> (<addr> <asm> | --patches <file.json>)
+       minix_vm trace-dump [--trace-format text|jsonl] [--expand] <trace.bin>
+
+Commands:
+    run       Execute the binary (default)
+    trace     Execute the binary and print registers and memory for each instruction
+    disasm    Disassemble the text segment
+    debug     Execute the binary step by step in an interactive debugger
+              (commands are read from stdin, which the program also reads)
+    grade     Run the test cases described in <spec.toml> and report pass/fail
+    golden    Compare every binary in <dir>/bin with its expected output in <dir>/origin
+    link      Assemble <file.s> and write a MINIX a.out executable
+    cfg       Print the control-flow graph of each function as Graphviz DOT or JSON
+    sections  Print the section sizes and an annotated dump of the data segment
+    patch     Replace instructions in the text segment and write a new a.out
+    bindiff   Compare the headers and the instructions of each function of two binaries
+    trace-dump
+              Print a binary trace written with --trace-format=bin
+
+Options:
+    --trace-file <path>   Write the trace to <path> instead of stdout (implies tracing)
+    --trace-format <name> Trace format: text (default), jsonl or bin; jsonl and bin also
+                          record memory accesses and syscalls (implies tracing)
+    --trace-from <addr|symbol>
+                          Start tracing when execution reaches <addr> or <symbol>
+    --trace-until <addr|symbol>
+                          Stop tracing after the instruction at <addr> or <symbol>
+    --trace-range <start>-<end>
+                          Only trace instructions at addresses from <start> up to <end>
+    --trace-steps <a>..<b>
+                          Only trace steps <a> up to <b> (counted from 1; either may
+                          be omitted)
+    --trace-only-calls    Only trace call and ret instructions
+    --trace-only-syscalls Only trace system calls
+    --trace-compress      Collapse repeated instruction sequences into one line with the
+                          iteration count and register changes (bin records every step)
+    --expand              Print every step of a trace recorded with --trace-compress
+                          (trace-dump)
+    --max-steps <n>       Stop quietly after executing <n> instructions (exit status 0;
+                          for cutting traces short)
+    --max-instructions <n>
+                          Abort after executing <n> instructions (exit status 123)
+    --timeout <time>      Abort after <time> of wall-clock time, e.g. 10, 2.5s, 500ms
+                          (exit status 124; not checked while the binary waits in read)
+    --max-output-bytes <n>
+                          Abort when the binary writes more than <n> bytes
+                          (exit status 122)
+    --root <dir>          Resolve files opened by the binary relative to <dir>
+    --env <KEY=VALUE>     Set an environment variable for the binary (repeatable,
+                          replaces the default PATH=/usr:/usr/bin)
+    --stdin <path>        Read the binary's standard input from <path>
+    --quiet               Do not echo the binary's output to stdout
+    --stats[=table|json]  Print instruction, addressing-mode and syscall counts to stderr
+                          when the binary stops
+    --profile[=flat|folded|tree]
+                          Print the instructions executed in each function as a flat
+                          profile, folded stacks for flame graphs or a call tree
+    --cycles[=8086|8088]  Count clock cycles with the 8086 (default) or 8088 timings; adds
+                          them to the trace, --stats and --profile
+    --coverage <path>     Add the executed instructions and branch outcomes to the lcov
+                          file <path>; with disasm, show the hit counts from <path>
+    --mem-trace <path>    Write every memory read and write made by the binary's
+                          instructions to <path>, with the region (data, bss, heap, stack)
+    --mem-heatmap         Print read and write counts per 16-byte line and per variable
+                          when the binary stops
+    --call-trace <path>   Write each call with its arguments and each return with its value
+                          to <path>; strings are shown for known libc functions
+    --call-args <n>       Show <n> stack words as the arguments of other functions
+                          (default: 3)
+    --stack-usage         Print the lowest sp, the stack used by each function and the call
+                          chain at the lowest sp when the binary stops, with a static
+                          estimate of each function's frame
+    --report-file <path>  Write the --stats, --profile, --mem-heatmap and --stack-usage
+                          reports to <path> instead of stderr
+    --jobs <n>            Run <n> test cases in parallel (grade)
+    --junit <path>        Write a JUnit XML report to <path> (grade)
+    --json <path>         Write a JSON report to <path> (grade)
+    --bless               Regenerate <dir>/origin from the current output (golden)
+    --labels              Label jump targets and separate basic blocks and functions (disasm)
+    --recursive           Follow jumps and calls from the entry point and every symbol,
+                          and show unreached bytes as data (disasm)
+    --syntax <name>       Output syntax: legacy, nasm, att or json (disasm, default: legacy)
+    --raw                 Treat <file> as a flat binary instead of a MINIX a.out (disasm)
+    --hex <bytes>         Disassemble the given bytes, e.g. 31ed89e3 (disasm)
+    --base <addr>         Address of the first byte of the text (disasm, default: 0)
+    --start <addr>        Start disassembling at <addr> (disasm)
+    --end <addr>          Stop disassembling before <addr> (disasm)
+    --data                Also dump the data segment and show the strings that
+                          immediates point to (disasm)
+    --function <name>     Only print the function <name> (cfg)
+    --format <name>       Output format: dot or json (cfg)
+    -o <path>             Write the executable to <path> (link, default: <file>.out;
+                          patch, default: <file>.patched)
+    --entry <label|addr>  Start execution at <label> or <addr> (link)
+    --stack <n>           Reserve <n> bytes of stack instead of a 64KB total (link)
+    --bss <n>             Add <n> bytes of bss after the .bss section (link)
+    --no-symbols          Do not write a symbol table (link)
+    --size <n>            Replace <n> bytes instead of the instruction at <addr>; the rest
+                          is filled with nop (patch)
+    --patches <path>      Apply the patches listed in a JSON file, e.g.
+                          [{\"address\": \"0x17\", \"asm\": \"jmp 0028\"}] (patch)
+    -h, --help            Print this help
+
+Legacy options:
+    -m <file> [args...]   Same as `trace`
+    -d <file>             Same as `disasm`
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Run,
+    Trace,
+    Disasm,
+    Debug,
+    Grade,
+    Golden,
+    Link,
+    Cfg,
+    Sections,
+    Patch,
+    Bindiff,
+    TraceDump,
+    Help,
+}
+
+impl Command {
+    // ゲストの引数を取らないコマンドは，位置引数の後もオプションとして解釈する
+    fn takes_guest_args(self) -> bool {
+        !matches!(
+            self,
+            Command::Disasm
+                | Command::Grade
+                | Command::Golden
+                | Command::Link
+                | Command::Cfg
+                | Command::Sections
+                | Command::Patch
+                | Command::Bindiff
+                | Command::TraceDump
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub file: String,
+    // ゲストに渡す引数．argv[0]は実行ファイルのパス
+    pub args: Vec<String>,
+    pub trace_file: Option<String>,
+    pub trace_format: Option<String>,
+    pub trace_from: Option<String>,
+    pub trace_until: Option<String>,
+    pub trace_range: Option<(u16, u16)>,
+    pub trace_steps: Option<(usize, usize)>,
+    pub trace_only_calls: bool,
+    pub trace_only_syscalls: bool,
+    pub trace_compress: bool,
+    pub expand: bool,
+    pub max_steps: Option<usize>,
+    pub max_instructions: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub max_output_bytes: Option<usize>,
+    pub root: Option<String>,
+    pub env: Vec<String>,
+    pub stdin: Option<String>,
+    pub quiet: bool,
+    pub jobs: Option<usize>,
+    pub junit: Option<String>,
+    pub json: Option<String>,
+    pub bless: bool,
+    pub labels: bool,
+    pub recursive: bool,
+    pub syntax: Option<String>,
+    pub raw: bool,
+    pub data: bool,
+    pub hex: Option<String>,
+    pub base: Option<u16>,
+    pub start: Option<u16>,
+    pub end: Option<u16>,
+    pub function: Option<String>,
+    pub format: Option<String>,
+    pub output: Option<String>,
+    pub entry: Option<String>,
+    pub stack: Option<u32>,
+    pub bss: Option<u32>,
+    pub no_symbols: bool,
+    pub size: Option<u32>,
+    pub patches: Option<String>,
+    pub stats: Option<String>,
+    pub profile: Option<String>,
+    pub report_file: Option<String>,
+    pub cycles: Option<Cpu>,
+    pub coverage: Option<String>,
+    pub mem_trace: Option<String>,
+    pub mem_heatmap: bool,
+    pub call_trace: Option<String>,
+    pub call_args: Option<usize>,
+    pub stack_usage: bool,
+}
+
+impl Cli {
+    fn new(command: Command) -> Self {
+        Cli {
+            command,
+            file: String::new(),
+            args: Vec::new(),
+            trace_file: None,
+            trace_format: None,
+            trace_from: None,
+            trace_until: None,
+            trace_range: None,
+            trace_steps: None,
+            trace_only_calls: false,
+            trace_only_syscalls: false,
+            trace_compress: false,
+            expand: false,
+            max_steps: None,
+            max_instructions: None,
+            timeout: None,
+            max_output_bytes: None,
+            root: None,
+            env: Vec::new(),
+            stdin: None,
+            quiet: false,
+            jobs: None,
+            junit: None,
+            json: None,
+            bless: false,
+            labels: false,
+            recursive: false,
+            syntax: None,
+            raw: false,
+            data: false,
+            hex: None,
+            base: None,
+            start: None,
+            end: None,
+            function: None,
+            format: None,
+            output: None,
+            entry: None,
+            stack: None,
+            bss: None,
+            no_symbols: false,
+            size: None,
+            patches: None,
+            stats: None,
+            profile: None,
+            report_file: None,
+            cycles: None,
+            coverage: None,
+            mem_trace: None,
+            mem_heatmap: false,
+            call_trace: None,
+            call_args: None,
+            stack_usage: false,
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.command == Command::Trace
+            || self.trace_file.is_some()
+            || self.trace_format.is_some()
+            || self.has_trace_filter()
+            || self.trace_compress
+    }
+
+    fn has_trace_filter(&self) -> bool {
+        self.trace_from.is_some()
+            || self.trace_until.is_some()
+            || self.trace_range.is_some()
+            || self.trace_steps.is_some()
+            || self.trace_only_calls
+            || self.trace_only_syscalls
+    }
+
+    // --trace-from等をVMの設定にする．シンボルは実行ファイルのシンボルテーブルから引く
+    pub fn trace_filter(&self, symbols: &SymbolTable) -> Result<Filter, String> {
+        let address = |name: &str, value: &Option<String>| -> Result<Option<u16>, String> {
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            match symbols.get(value) {
+                Some(symbol) => Ok(Some(symbol.value as u16)),
+                None => parse_address(name, value)
+                    .map(Some)
+                    .map_err(|_| format!("invalid value for '{name}': undefined symbol `{value}`")),
+            }
+        };
+
+        Ok(Filter {
+            from: address("--trace-from", &self.trace_from)?,
+            until: address("--trace-until", &self.trace_until)?,
+            range: self.trace_range,
+            steps: self.trace_steps,
+            only_calls: self.trace_only_calls,
+            only_syscalls: self.trace_only_syscalls,
+        })
+    }
+
+    // コマンドライン引数からVMの実行時設定を作る
+    pub fn vm_config(&self) -> io::Result<Config> {
+        let mut config = Config::default();
+
+        if self.is_tracing() {
+            config.trace = Some(match &self.trace_file {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout()),
+            });
+        }
+
+        // 標準出力へのトレースにはゲストの出力が埋め込まれるので，二重に出力しない
+        let trace_to_stdout = self.is_tracing() && self.trace_file.is_none();
+        if self.quiet || trace_to_stdout {
+            config.output = None;
+        }
+
+        if let Some(format) = self.trace_format.as_deref().and_then(Format::from_name) {
+            config.trace_format = format;
+        }
+        config.trace_compress = self.trace_compress;
+        config.max_steps = self.max_steps;
+        config.max_instructions = self.max_instructions;
+        config.timeout = self.timeout;
+        config.max_output_bytes = self.max_output_bytes;
+        config.root = self.root.as_ref().map(PathBuf::from);
+        config.stats = self.stats.is_some();
+        config.profile = self.profile.is_some();
+        config.cycles = self.cycles;
+        config.coverage = self.coverage.is_some();
+        config.mem_heatmap = self.mem_heatmap;
+        config.stack_usage = self.stack_usage;
+        if let Some(path) = &self.mem_trace {
+            config.mem_trace = Some(Box::new(BufWriter::new(File::create(path)?)));
+        }
+        if let Some(path) = &self.call_trace {
+            config.call_trace = Some(Box::new(BufWriter::new(File::create(path)?)));
+        }
+        if let Some(n) = self.call_args {
+            config.call_args = n;
+        }
+
+        if !self.env.is_empty() {
+            config.env = self.env.clone();
+        }
+
+        if let Some(path) = &self.stdin {
+            config.stdin = Some(File::open(path)?);
+        }
+
+        Ok(config)
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Cli, String> {
+    let mut iter = args.iter().skip(1);
+    let mut cli = Cli::new(Command::Run);
+    let mut command_given = false;
+
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+
+        let mut value = |name: &str| -> Result<String, String> {
+            match inline_value.clone() {
+                Some(value) => Ok(value),
+                None => iter
+                    .next()
+                    .cloned()
+                    .ok_or(format!("option '{name}' requires a value")),
+            }
+        };
+
+        match name {
+            "-h" | "--help" => {
+                cli.command = Command::Help;
+                return Ok(cli);
+            }
+            "--trace-file" => cli.trace_file = Some(value(name)?),
+            "--trace-format" => {
+                let format = value(name)?;
+                if !trace::FORMATS.contains(&format.as_str()) {
+                    return Err(format!(
+                        "invalid value for '{name}': {format} (expected one of {})",
+                        trace::FORMATS.join(", ")
+                    ));
+                }
+                cli.trace_format = Some(format);
+            }
+            "--trace-from" => cli.trace_from = Some(value(name)?),
+            "--trace-until" => cli.trace_until = Some(value(name)?),
+            "--trace-range" => {
+                let range = value(name)?;
+                let invalid =
+                    || format!("invalid value for '{name}': {range} (expected <start>-<end>)");
+                let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+                let start = parse_address(name, start).map_err(|_| invalid())?;
+                let end = parse_address(name, end).map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                cli.trace_range = Some((start, end));
+            }
+            "--trace-steps" => {
+                let steps = value(name)?;
+                let invalid = || format!("invalid value for '{name}': {steps} (expected <a>..<b>)");
+                let (start, end) = steps.split_once("..").ok_or_else(invalid)?;
+                let bound = |s: &str, default: usize| match s {
+                    "" => Some(default),
+                    s => s.parse().ok(),
+                };
+                let start = bound(start, 1).ok_or_else(invalid)?;
+                let end = bound(end, usize::MAX).ok_or_else(invalid)?;
+                if start > end {
+                    return Err(invalid());
+                }
+                cli.trace_steps = Some((start, end));
+            }
+            "--trace-only-calls" => cli.trace_only_calls = true,
+            "--trace-only-syscalls" => cli.trace_only_syscalls = true,
+            "--trace-compress" => cli.trace_compress = true,
+            "--expand" => cli.expand = true,
+            "--max-steps" => cli.max_steps = Some(parse_value(name, &value(name)?)?),
+            "--max-instructions" => cli.max_instructions = Some(parse_value(name, &value(name)?)?),
+            "--timeout" => {
+                let timeout = value(name)?;
+                cli.timeout = Some(
+                    parse_duration(&timeout)
+                        .ok_or(format!("invalid value for '{name}': {timeout}"))?,
+                );
+            }
+            "--max-output-bytes" => cli.max_output_bytes = Some(parse_value(name, &value(name)?)?),
+            "--root" => cli.root = Some(value(name)?),
+            "--env" => {
+                let env = value(name)?;
+                if !env.contains('=') {
+                    return Err(format!(
+                        "invalid value for '{name}': {env} (expected KEY=VALUE)"
+                    ));
+                }
+                cli.env.push(env);
+            }
+            "--stdin" => cli.stdin = Some(value(name)?),
+            "--quiet" => cli.quiet = true,
+            // 値は省略できるので，--stats=jsonの形だけ受け付ける
+            "--stats" => cli.stats = Some(optional_value(name, &inline_value, &stats::FORMATS)?),
+            "--profile" => {
+                cli.profile = Some(optional_value(name, &inline_value, &profile::FORMATS)?)
+            }
+            "--cycles" => {
+                let cpu = optional_value(name, &inline_value, &cycles::CPUS)?;
+                cli.cycles = Cpu::from_name(&cpu);
+            }
+            "--coverage" => cli.coverage = Some(value(name)?),
+            "--mem-trace" => cli.mem_trace = Some(value(name)?),
+            "--mem-heatmap" => cli.mem_heatmap = true,
+            "--call-trace" => cli.call_trace = Some(value(name)?),
+            "--stack-usage" => cli.stack_usage = true,
+            "--call-args" => cli.call_args = Some(parse_value(name, &value(name)?)?),
+            "--report-file" => cli.report_file = Some(value(name)?),
+            "--jobs" => cli.jobs = Some(parse_value(name, &value(name)?)?),
+            "--junit" => cli.junit = Some(value(name)?),
+            "--json" => cli.json = Some(value(name)?),
+            "--bless" => cli.bless = true,
+            "--labels" => cli.labels = true,
+            "--recursive" => cli.recursive = true,
+            "--syntax" => {
+                let syntax = value(name)?;
+                if !SYNTAXES.contains(&syntax.as_str()) {
+                    return Err(format!(
+                        "invalid value for '{name}': {syntax} (expected one of {})",
+                        SYNTAXES.join(", ")
+                    ));
+                }
+                cli.syntax = Some(syntax);
+            }
+            "--raw" => cli.raw = true,
+            "--data" => cli.data = true,
+            "--hex" => cli.hex = Some(value(name)?),
+            "--base" => cli.base = Some(parse_address(name, &value(name)?)?),
+            "--start" => cli.start = Some(parse_address(name, &value(name)?)?),
+            "--end" => cli.end = Some(parse_address(name, &value(name)?)?),
+            "--function" => cli.function = Some(value(name)?),
+            "--format" => {
+                let format = value(name)?;
+                if !cfg::FORMATS.contains(&format.as_str()) {
+                    return Err(format!(
+                        "invalid value for '{name}': {format} (expected one of {})",
+                        cfg::FORMATS.join(", ")
+                    ));
+                }
+                cli.format = Some(format);
+            }
+            "-o" => cli.output = Some(value(name)?),
+            "--entry" => cli.entry = Some(value(name)?),
+            "--stack" => cli.stack = Some(parse_size(name, &value(name)?)?),
+            "--bss" => cli.bss = Some(parse_size(name, &value(name)?)?),
+            "--no-symbols" => cli.no_symbols = true,
+            "--size" => cli.size = Some(parse_size(name, &value(name)?)?),
+            "--patches" => cli.patches = Some(value(name)?),
+            "-m" | "-d" if !command_given => {
+                cli.command = if name == "-m" {
+                    Command::Trace
+                } else {
+                    Command::Disasm
+                };
+                command_given = true;
+            }
+            "--" => {
+                let file = iter.next().ok_or("missing <file>")?;
+                return Ok(finish(cli, file, iter));
+            }
+            _ if name.starts_with('-') && name.len() > 1 => {
+                return Err(format!("unknown option '{arg}'"));
+            }
+            _ => {
+                if !command_given {
+                    command_given = true;
+
+                    let command = match name {
+                        "run" => Some(Command::Run),
+                        "trace" => Some(Command::Trace),
+                        "disasm" => Some(Command::Disasm),
+                        "debug" => Some(Command::Debug),
+                        "grade" => Some(Command::Grade),
+                        "golden" => Some(Command::Golden),
+                        "link" => Some(Command::Link),
+                        "cfg" => Some(Command::Cfg),
+                        "sections" => Some(Command::Sections),
+                        "patch" => Some(Command::Patch),
+                        "bindiff" => Some(Command::Bindiff),
+                        "trace-dump" => Some(Command::TraceDump),
+                        _ => None,
+                    };
+
+                    if let Some(command) = command {
+                        cli.command = command;
+                        continue;
+                    }
+                }
+
+                if !cli.command.takes_guest_args() {
+                    // patchは<file>の後に<addr> <asm>，bindiffは比べるもう1つのファイルを取る
+                    let extra = matches!(cli.command, Command::Patch | Command::Bindiff);
+                    if extra && !cli.file.is_empty() {
+                        cli.args.push(arg.clone());
+                        continue;
+                    }
+                    if !cli.file.is_empty() {
+                        return Err(format!("unexpected argument '{arg}'"));
+                    }
+                    cli.file = arg.clone();
+                    continue;
+                }
+
+                return Ok(finish(cli, arg, iter));
+            }
+        }
+    }
+
+    // trace-dumpはbinのトレースを読み，textかjsonlで書く
+    if let (Command::TraceDump, Some(format)) = (cli.command, cli.trace_format.as_deref()) {
+        if !trace::DUMP_FORMATS.contains(&format) {
+            return Err(format!(
+                "invalid value for '--trace-format': {format} (expected one of {})",
+                trace::DUMP_FORMATS.join(", ")
+            ));
+        }
+    }
+
+    match cli.command {
+        Command::Grade
+        | Command::Link
+        | Command::Cfg
+        | Command::Sections
+        | Command::Patch
+        | Command::Bindiff
+        | Command::TraceDump
+            if !cli.file.is_empty() =>
+        {
+            return Ok(cli)
+        }
+        Command::Disasm if cli.hex.is_some() => {
+            if !cli.file.is_empty() {
+                return Err("'--hex' cannot be used with <file>".to_owned());
+            }
+            return Ok(cli);
+        }
+        Command::Disasm if !cli.file.is_empty() => return Ok(cli),
+        Command::Golden => {
+            if cli.file.is_empty() {
+                cli.file = ".".to_owned();
+            }
+            return Ok(cli);
+        }
+        _ => {}
+    }
+
+    Err("missing <file>".to_owned())
+}
+
+// 省略できる値．省略時はformatsの先頭
+fn optional_value(name: &str, value: &Option<String>, formats: &[&str]) -> Result<String, String> {
+    let format = value.as_deref().unwrap_or(formats[0]);
+    if !formats.contains(&format) {
+        return Err(format!(
+            "invalid value for '{name}': {format} (expected one of {})",
+            formats.join(", ")
+        ));
+    }
+    Ok(format.to_owned())
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for '{name}': {value}"))
+}
+
+// 0x接頭辞があれば16進数，なければ10進数
+pub fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_size(name: &str, value: &str) -> Result<u32, String> {
+    parse_number(value).ok_or(format!("invalid value for '{name}': {value}"))
+}
+
+fn parse_address(name: &str, value: &str) -> Result<u16, String> {
+    parse_number(value)
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or(format!("invalid value for '{name}': {value}"))
+}
+
+// "10"，"2.5s"，"500ms" のような時間の指定を解釈する．単位がなければ秒
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        (value, 1.0)
+    };
+
+    let secs = number.parse::<f64>().ok()? * scale;
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+// 最初の位置引数をファイルとし，残りはすべてゲストの引数とする
+fn finish<'a>(mut cli: Cli, file: &str, rest: impl Iterator<Item = &'a String>) -> Cli {
+    cli.file = file.to_owned();
+    cli.args = std::iter::once(file.to_owned())
+        .chain(rest.cloned())
+        .collect();
+    cli
+}
@@ -0,0 +1,138 @@
+use crate::arch::vm::{Halt, STATUS_HEADER, VM};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+s [n]          step n instructions (default 1)
+c              continue until a breakpoint or exit
+b <addr>       set a breakpoint at <addr> (hex)
+d <addr>       delete the breakpoint at <addr>
+l              list breakpoints
+r              show registers and the next instruction
+u [n]          disassemble n instructions from ip (default 5)
+x <addr> [n]   dump n bytes of data memory from <addr> (default 32)
+q              quit
+h              show this help";
+
+// 対話的なデバッガ．標準入力からコマンドを読んでVMを1命令ずつ進める．
+// 標準入力は実行するプログラムと共有するので，readはコマンドと同じ入力を読む
+pub fn run(vm: &mut VM) -> Option<Halt> {
+    let stdin = io::stdin();
+    let mut breakpoints = BTreeSet::new();
+
+    println!("{STATUS_HEADER}");
+    print_status(vm);
+
+    loop {
+        print!("(mvm) ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return None;
+        }
+
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let arg1 = words.next();
+        let arg2 = words.next();
+
+        match command {
+            "s" | "step" => {
+                let count = arg1.and_then(|n| n.parse().ok()).unwrap_or(1);
+
+                for _ in 0..count {
+                    if let Some(halt) = vm.step() {
+                        return report(vm, halt);
+                    }
+                }
+                print_status(vm);
+            }
+            "c" | "continue" => {
+                loop {
+                    if let Some(halt) = vm.step() {
+                        return report(vm, halt);
+                    }
+                    if breakpoints.contains(&vm.ip) {
+                        println!("breakpoint at {:04x}", vm.ip);
+                        break;
+                    }
+                }
+                print_status(vm);
+            }
+            "b" | "break" => match arg1.and_then(parse_addr) {
+                Some(addr) => {
+                    breakpoints.insert(addr);
+                }
+                None => println!("usage: b <addr>"),
+            },
+            "d" | "delete" => match arg1.and_then(parse_addr) {
+                Some(addr) => {
+                    breakpoints.remove(&addr);
+                }
+                None => println!("usage: d <addr>"),
+            },
+            "l" | "list" => {
+                for addr in breakpoints.iter() {
+                    println!("{addr:04x}");
+                }
+            }
+            "r" | "regs" => print_status(vm),
+            "u" | "disasm" => {
+                let count = arg1.and_then(|n| n.parse().ok()).unwrap_or(5);
+                let ip = vm.ip;
+
+                for _ in 0..count {
+                    match vm.decode() {
+                        Some(asm) => println!("{:?}", asm),
+                        None => break,
+                    }
+                }
+                vm.ip = ip;
+            }
+            "x" | "examine" => match arg1.and_then(parse_addr) {
+                Some(addr) => {
+                    let len = arg2.and_then(|n| n.parse().ok()).unwrap_or(32);
+                    dump(vm, addr, len);
+                }
+                None => println!("usage: x <addr> [n]"),
+            },
+            "q" | "quit" => return None,
+            "h" | "help" => println!("{HELP}"),
+            _ => println!("unknown command '{command}' (h for help)"),
+        }
+    }
+}
+
+fn print_status(vm: &mut VM) {
+    match vm.current_instruction() {
+        Some(asm) => println!("{}", vm.status_line(&asm)),
+        None => println!("ip {:04x} is outside the text segment", vm.ip),
+    }
+}
+
+fn report(vm: &mut VM, halt: Halt) -> Option<Halt> {
+    vm.flush();
+    // 異常停止の要約は呼び出し側が標準エラー出力に書く
+    if !halt.is_abnormal() {
+        println!("\nstopped after {} instructions: {:?}", vm.steps(), halt);
+    }
+    Some(halt)
+}
+
+fn dump(vm: &VM, addr: u16, len: u16) {
+    for line_addr in (addr..addr.saturating_add(len)).step_by(16) {
+        let end = line_addr.saturating_add(16).min(addr.saturating_add(len));
+        let bytes: Vec<String> = (line_addr..end)
+            .map(|a| format!("{:02x}", vm.ram.read_i8(a) as u8))
+            .collect();
+
+        println!("{line_addr:04x}: {}", bytes.join(" "));
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
@@ -0,0 +1,198 @@
+use std::fmt::{Display, Write};
+
+// これ以上の編集距離になる場合は，差分を求めずに全体を置き換えとして扱う
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    // aの添字，bの添字
+    Equal(usize, usize),
+    // aの添字
+    Delete(usize),
+    // bの添字
+    Insert(usize),
+}
+
+// aをbに変換する最短の編集列を求める(Myersの差分アルゴリズム)
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+
+    let middle = myers(a_mid, b_mid).unwrap_or_else(|| {
+        (0..a_mid.len())
+            .map(Edit::Delete)
+            .chain((0..b_mid.len()).map(Edit::Insert))
+            .collect()
+    });
+    edits.extend(middle.into_iter().map(|e| match e {
+        Edit::Equal(i, j) => Edit::Equal(i + prefix, j + prefix),
+        Edit::Delete(i) => Edit::Delete(i + prefix),
+        Edit::Insert(j) => Edit::Insert(j + prefix),
+    }));
+
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Equal(a_end + i, b_end + i)));
+
+    edits
+}
+
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+
+    let mut v = vec![0isize; 2 * max + 3];
+    // 各ステップ開始時のvのうち，k = -d-1..=d+1 の部分
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max.min(MAX_EDIT_DISTANCE) as isize {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let down =
+                k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]);
+            let mut x = if down {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert(prev_y as usize));
+            } else {
+                edits.push(Edit::Delete(prev_x as usize));
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+// unified diff形式(diff -u)で差分を表示する．差分がなければ空文字列
+pub fn unified<T: PartialEq + Display>(a: &[T], b: &[T], context: usize) -> String {
+    let edits = diff(a, b);
+    let mut out = String::new();
+
+    // 各編集の直前までに消費したa, bの行数
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut a_pos, mut b_pos) = (0, 0);
+    for e in &edits {
+        positions.push((a_pos, b_pos));
+        match e {
+            Edit::Equal(..) => {
+                a_pos += 1;
+                b_pos += 1;
+            }
+            Edit::Delete(_) => a_pos += 1,
+            Edit::Insert(_) => b_pos += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Equal(..)))
+        .collect();
+
+    let mut i = 0;
+    while i < changes.len() {
+        // 前後のcontext行が重なる変更は1つのhunkにまとめる
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        let a_count = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Insert(_)))
+            .count();
+        let b_count = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Delete(_)))
+            .count();
+        let (a_start, b_start) = positions[start];
+
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(a_start, a_count),
+            hunk_range(b_start, b_count)
+        );
+
+        for e in hunk {
+            let _ = match *e {
+                Edit::Equal(x, _) => writeln!(out, " {}", a[x]),
+                Edit::Delete(x) => writeln!(out, "-{}", a[x]),
+                Edit::Insert(y) => writeln!(out, "+{}", b[y]),
+            };
+        }
+
+        i = j + 1;
+    }
+
+    out
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{count}", start + 1),
+    }
+}
@@ -0,0 +1,239 @@
+use crate::arch::asm::Assembly;
+use crate::arch::bin::BinaryManager;
+use crate::arch::coverage::{parse_lcov, same_file, Coverage};
+use crate::arch::data::{quote, DataView};
+use crate::arch::decode;
+use crate::arch::explore::{self, Chunk};
+use crate::arch::format;
+use crate::arch::listing::Listing;
+use crate::arch::opcode::Opcode;
+use crate::arch::operand::{ImmediateValue::*, Operand};
+use crate::arch::reg::Register;
+use crate::arch::symbol::SymbolTable;
+use crate::cli::Cli;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+
+// 逆アセンブルする機械語．a.outならテキストセグメント，--raw/--hexなら与えられたバイト列そのもの
+pub struct Image {
+    pub bytes: Vec<u8>,
+    // bytes[0]のアドレス
+    pub base: u16,
+    pub entry: u16,
+    pub symbols: SymbolTable,
+    // データセグメントの初期値とbssの大きさ．a.outのみ
+    pub data: Vec<u8>,
+    pub bss_size: u16,
+}
+
+impl Image {
+    pub fn load(cli: &Cli) -> io::Result<Result<Image, String>> {
+        let base = cli.base.unwrap_or(0);
+
+        if let Some(hex) = &cli.hex {
+            return Ok(parse_hex(hex).map(|bytes| Image::raw(bytes, base)));
+        }
+
+        let content = fs::read(&cli.file)?;
+        if cli.raw {
+            return Ok(Ok(Image::raw(content, base)));
+        }
+
+        let bm = BinaryManager::new(content);
+        if let Err(err) = bm.validate() {
+            return Ok(Err(err));
+        }
+        Ok(Ok(Image {
+            bytes: bm.get_text().to_vec(),
+            base,
+            entry: bm.get_header().map_or(0, |h| h.entry_point as u16),
+            symbols: bm.get_symbols(),
+            data: bm.get_data().to_vec(),
+            bss_size: bm.get_bss().map_or(0, |b| b.user_size as u16),
+        }))
+    }
+
+    fn raw(bytes: Vec<u8>, base: u16) -> Image {
+        Image {
+            bytes,
+            base,
+            entry: base,
+            symbols: SymbolTable::default(),
+            data: Vec::new(),
+            bss_size: 0,
+        }
+    }
+
+    // [start, end)のアドレスの部分だけにする
+    pub fn slice(self, start: Option<u16>, end: Option<u16>) -> Result<Image, String> {
+        let limit = self.base as usize + self.bytes.len();
+        let start = start.map_or(self.base as usize, usize::from);
+        let end = end.map_or(limit, usize::from);
+
+        if start < self.base as usize || end > limit || start > end {
+            return Err(format!(
+                "address range {start:04x}..{end:04x} is outside {:04x}..{limit:04x}",
+                self.base
+            ));
+        }
+
+        let offset = start - self.base as usize;
+        let bytes = self.bytes[offset..offset + (end - start)].to_vec();
+        let entry = if (start..end).contains(&(self.entry as usize)) {
+            self.entry
+        } else {
+            start as u16
+        };
+        Ok(Image {
+            bytes,
+            base: start as u16,
+            entry,
+            symbols: self.symbols,
+            data: self.data,
+            bss_size: self.bss_size,
+        })
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        addr.checked_sub(self.base)
+            .is_some_and(|offset| (offset as usize) < self.bytes.len())
+    }
+}
+
+// "31ed 89e3" のような16進数の並び．空白は無視する
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits: {hex}"));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("invalid hex byte '{pair}'"))
+        })
+        .collect()
+}
+
+// `minix_vm disasm` の本体
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    disasm(cli, &mut io::stdout().lock())
+}
+
+fn disasm(cli: &Cli, out: &mut impl Write) -> io::Result<i32> {
+    let name = cli.hex.as_deref().map_or(cli.file.as_str(), |_| "--hex");
+    let image = match Image::load(cli)?.and_then(|image| image.slice(cli.start, cli.end)) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("minix_vm: {name}: {err}");
+            return Ok(1);
+        }
+    };
+
+    let chunks: Vec<Chunk> = if cli.recursive {
+        let entries = std::iter::once(image.entry)
+            .chain(image.symbols.functions().map(|s| s.value as u16))
+            .filter(|&addr| image.contains(addr));
+        explore::explore(&image.bytes, image.base, entries)
+    } else {
+        explore::linear(decode::disassemble(&image.bytes, image.base))
+    };
+
+    let fmt = format::formatter(cli.syntax.as_deref().unwrap_or("legacy")).unwrap();
+    if let Some(prologue) = fmt.prologue(image.base) {
+        writeln!(out, "{prologue}")?;
+    }
+
+    let view = DataView::new(&image.data, image.bss_size, &image.symbols);
+    let mut comments = if cli.data {
+        strings_used(&chunks, &view)
+    } else {
+        BTreeMap::new()
+    };
+
+    if let Some(path) = &cli.coverage {
+        let coverage = match load_coverage(path, &cli.file)? {
+            Ok(coverage) => coverage,
+            Err(err) => {
+                eprintln!("minix_vm: {path}: {err}");
+                return Ok(1);
+            }
+        };
+        let code: Vec<&Assembly> = chunks.iter().filter_map(Chunk::as_code).collect();
+        for (addr, hits) in coverage.comments(&code) {
+            let comment = comments.entry(addr).or_default();
+            if !comment.is_empty() {
+                comment.push_str(", ");
+            }
+            comment.push_str(&hits);
+        }
+    }
+
+    if cli.labels {
+        let listing = Listing::new(&chunks, &image.symbols, image.entry).with_comments(comments);
+        write!(out, "{}", listing.render(&chunks, fmt.as_ref()))?;
+    } else {
+        for chunk in &chunks {
+            let line = fmt.chunk(chunk, None);
+            match comments.get(&chunk.address()) {
+                Some(comment) => writeln!(out, "{line:<40}{}", fmt.comment(comment))?,
+                None => writeln!(out, "{line}")?,
+            }
+        }
+    }
+
+    if cli.data && !fmt.is_structured() {
+        write!(out, "\n{}", view.render())?;
+    }
+
+    out.flush()?;
+    Ok(0)
+}
+
+// --coverageのトレースファイルからfileのレコードを探す．レコードが1つだけならそれを使う
+fn load_coverage(path: &str, file: &str) -> io::Result<Result<Coverage, String>> {
+    let records = match parse_lcov(&fs::read_to_string(path)?) {
+        Ok(records) => records,
+        Err(err) => return Ok(Err(err)),
+    };
+
+    let found = match records.iter().position(|(sf, _)| same_file(sf, file)) {
+        Some(i) => Some(i),
+        None if records.len() == 1 => Some(0),
+        None => None,
+    };
+    Ok(found
+        .map(|i| records[i].1.clone())
+        .ok_or(format!("no coverage data for {file}")))
+}
+
+// 文字列の先頭のアドレスをワードの即値として代入する命令と，その文字列．
+// 演算やフラグの検査の即値は文字列を指していても偶然なので含めない
+pub fn strings_used(chunks: &[Chunk], view: &DataView) -> BTreeMap<u16, String> {
+    let mut comments = BTreeMap::new();
+
+    for asm in chunks.iter().filter_map(Chunk::as_code) {
+        let instruction = &asm.instruction;
+        let is_mov = matches!(
+            instruction.opcode,
+            Opcode::MovImmediate
+                | Opcode::MovImmediateRegisterMemory
+                | Opcode::MovImmediateRegisterMemoryWord
+        );
+        let word = !matches!(
+            instruction.operand1,
+            Some(Operand::Register(Register::Reg8(_)))
+        );
+        if let (true, true, Some(Operand::Immediate(imm @ (I16(..) | I32(..))))) =
+            (is_mov, word, &instruction.operand2)
+        {
+            if let Some(s) = view.string_at(u16::from(*imm)) {
+                comments.insert(asm.address, quote(s));
+            }
+        }
+    }
+
+    comments
+}
@@ -0,0 +1,326 @@
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode;
+use crate::arch::vm::{Capture, Config, VM};
+use crate::grade::parallel;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write as _};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// 不一致の前に表示する一致した行数
+const CONTEXT_LINES: usize = 3;
+// 退行で無限ループしてもテストが止まるようにする
+const TIMEOUT: Duration = Duration::from_secs(10);
+const REGISTERS: [&str; 8] = ["AX", "BX", "CX", "DX", "SP", "BP", "SI", "DI"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Default,
+    Asm,
+    Vm,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Default, Mode::Asm, Mode::Vm];
+
+    // origin/，out/以下のディレクトリ名
+    pub fn dir(self) -> &'static str {
+        match self {
+            Mode::Default => "default",
+            Mode::Asm => "asm",
+            Mode::Vm => "vm",
+        }
+    }
+}
+
+// bin/の1つの実行ファイルと，1つのモードの期待する出力の組
+pub struct Case {
+    pub name: String,
+    pub mode: Mode,
+    pub binary: PathBuf,
+    pub args: Vec<String>,
+    // origin/<mode>/<name>.txt
+    pub expected: PathBuf,
+    // out/<mode>/<name>.txt
+    pub actual: PathBuf,
+}
+
+impl Case {
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.mode.dir(), self.name)
+    }
+}
+
+pub enum Verdict {
+    Pass,
+    // origin/に期待する出力がない
+    Missing,
+    Blessed,
+    Fail(String),
+}
+
+// root/bin/以下のすべてのファイルについて，各モードのテストを列挙する．
+// origin/<mode>/<name>.args があれば，空白区切りでゲストの引数とする
+pub fn discover(root: &Path) -> io::Result<Vec<Case>> {
+    let mut names: Vec<String> = fs::read_dir(root.join("bin"))?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let mut cases = Vec::new();
+    for mode in Mode::ALL {
+        let origin = root.join("origin").join(mode.dir());
+
+        for name in &names {
+            let args = fs::read_to_string(origin.join(format!("{name}.args")))
+                .map(|s| s.split_whitespace().map(str::to_owned).collect())
+                .unwrap_or_default();
+
+            cases.push(Case {
+                name: name.clone(),
+                mode,
+                binary: root.join("bin").join(name),
+                args,
+                expected: origin.join(format!("{name}.txt")),
+                actual: root
+                    .join("out")
+                    .join(mode.dir())
+                    .join(format!("{name}.txt")),
+            });
+        }
+    }
+
+    Ok(cases)
+}
+
+// VMをプロセス内で実行して，そのモードでの出力を得る
+pub fn render(case: &Case) -> Result<String, String> {
+    let content =
+        fs::read(&case.binary).map_err(|err| format!("{}: {err}", case.binary.display()))?;
+    let args: Vec<String> = std::iter::once(case.binary.to_string_lossy().into_owned())
+        .chain(case.args.iter().cloned())
+        .collect();
+
+    if case.mode == Mode::Asm {
+        let mut out = String::new();
+        let bm = BinaryManager::new(content);
+        for asm in decode::disassemble(bm.get_text(), 0) {
+            let _ = writeln!(out, "{:?}", asm);
+        }
+        return Ok(out);
+    }
+
+    let capture = Capture::default();
+    let config = match case.mode {
+        Mode::Vm => Config {
+            trace: Some(Box::new(capture.clone())),
+            output: None,
+            ..Config::default()
+        },
+        _ => Config {
+            output: Some(Box::new(capture.clone())),
+            ..Config::default()
+        },
+    };
+    let config = Config {
+        timeout: Some(TIMEOUT),
+        ..config
+    };
+
+    let mut vm = VM::with_config(content, &args, config)
+        .map_err(|err| format!("{}: {err}", case.binary.display()))?;
+    let halt = vm.run();
+    if halt.is_abnormal() {
+        return Err(vm.summary(halt));
+    }
+
+    Ok(String::from_utf8_lossy(&capture.contents()).into_owned())
+}
+
+pub fn check(case: &Case, bless: bool) -> Verdict {
+    let actual = match panic::catch_unwind(AssertUnwindSafe(|| render(case))) {
+        Ok(Ok(actual)) => actual,
+        Ok(Err(err)) => return Verdict::Fail(format!("{}: {err}", case.id())),
+        Err(_) => return Verdict::Fail(format!("{}: vm panicked", case.id())),
+    };
+
+    if bless {
+        return match write(&case.expected, &actual) {
+            Ok(()) => Verdict::Blessed,
+            Err(err) => Verdict::Fail(format!("{}: {err}", case.expected.display())),
+        };
+    }
+
+    let Ok(expected) = fs::read_to_string(&case.expected) else {
+        return Verdict::Missing;
+    };
+
+    // 比較できるように実際の出力も残しておく
+    let _ = write(&case.actual, &actual);
+
+    match first_divergence(case, &expected, &actual) {
+        Some(report) => Verdict::Fail(report),
+        None => Verdict::Pass,
+    }
+}
+
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)
+}
+
+// 行単位で比較し，最初に異なる行を前後の文脈とともに報告する．末尾の改行の有無は区別しない
+pub fn first_divergence(case: &Case, expected: &str, actual: &str) -> Option<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    let i = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i))?;
+
+    let mut report = match case.mode {
+        Mode::Vm => {
+            let instruction = expected[..i.min(expected.len())]
+                .iter()
+                .filter(|line| parse_status(line).is_some())
+                .count();
+            format!(
+                "{}: first divergence at instruction {} (line {})\n",
+                case.id(),
+                instruction + 1,
+                i + 1
+            )
+        }
+        _ => format!("{}: first divergence at line {}\n", case.id(), i + 1),
+    };
+
+    for line in &expected[i.saturating_sub(CONTEXT_LINES)..i] {
+        let _ = writeln!(report, "    {line}");
+    }
+    let _ = writeln!(
+        report,
+        "  - {}",
+        expected.get(i).unwrap_or(&"(end of output)")
+    );
+    let _ = writeln!(
+        report,
+        "  + {}",
+        actual.get(i).unwrap_or(&"(end of output)")
+    );
+
+    if case.mode == Mode::Vm {
+        report.push_str(&register_context(&expected, &actual, i));
+    }
+
+    Some(report)
+}
+
+// トレースの行が異なる場合に，どのレジスタが異なるかと，直前に実行した命令を示す
+fn register_context(expected: &[&str], actual: &[&str], i: usize) -> String {
+    let mut out = String::new();
+
+    let (Some(e), Some(a)) = (
+        expected.get(i).and_then(|l| parse_status(l)),
+        actual.get(i).and_then(|l| parse_status(l)),
+    ) else {
+        return out;
+    };
+
+    let mut diffs: Vec<String> = (0..REGISTERS.len())
+        .filter(|&r| e.regs[r] != a.regs[r])
+        .map(|r| format!("{} {:04x} != {:04x}", REGISTERS[r], e.regs[r], a.regs[r]))
+        .collect();
+    if e.flags != a.flags {
+        diffs.push(format!("FLAGS {} != {}", e.flags, a.flags));
+    }
+    if e.ip != a.ip {
+        diffs.push(format!("IP {} != {}", e.ip, a.ip));
+    }
+
+    if !diffs.is_empty() {
+        let _ = writeln!(
+            out,
+            "  registers (expected != actual): {}",
+            diffs.join(", ")
+        );
+    }
+
+    // レジスタは命令の実行前の値なので，原因は直前に実行した命令にある
+    if let Some(prev) = expected[..i]
+        .iter()
+        .rev()
+        .find(|l| parse_status(l).is_some())
+    {
+        let _ = writeln!(out, "  after executing: {prev}");
+    }
+
+    out
+}
+
+struct Status<'a> {
+    regs: [u16; 8],
+    flags: &'a str,
+    ip: &'a str,
+}
+
+// " AX   BX ... FLAGS IP" の形式のトレース行
+fn parse_status(line: &str) -> Option<Status<'_>> {
+    let mut fields = line.split_whitespace();
+    let mut regs = [0u16; 8];
+
+    for reg in regs.iter_mut() {
+        let field = fields.next()?;
+        if field.len() != 4 {
+            return None;
+        }
+        *reg = u16::from_str_radix(field, 16).ok()?;
+    }
+
+    let flags = fields.next()?;
+    let ip = fields.next()?.split(':').next()?;
+
+    Some(Status { regs, flags, ip })
+}
+
+// 全テストを並列に実行する
+pub fn check_all(root: &Path, bless: bool) -> io::Result<Vec<(Case, Verdict)>> {
+    let cases = discover(root)?;
+    let jobs = thread::available_parallelism().map_or(1, |n| n.get());
+    let verdicts = parallel(&cases, jobs, |case| check(case, bless));
+
+    Ok(cases.into_iter().zip(verdicts).collect())
+}
+
+// `minix_vm golden [--bless] [dir]` の本体
+pub fn main(root: &Path, bless: bool) -> io::Result<i32> {
+    let results = check_all(root, bless)?;
+    let mut failed = 0;
+    let mut out = io::stdout().lock();
+
+    for (case, verdict) in &results {
+        match verdict {
+            Verdict::Pass => writeln!(out, "ok      {}", case.id())?,
+            Verdict::Blessed => writeln!(out, "blessed {}", case.expected.display())?,
+            Verdict::Missing => writeln!(
+                out,
+                "skip    {} (no {}, run with --bless to create it)",
+                case.id(),
+                case.expected.display()
+            )?,
+            Verdict::Fail(report) => {
+                failed += 1;
+                writeln!(out, "FAIL    {}", case.id())?;
+                write!(out, "{report}")?;
+            }
+        }
+    }
+
+    writeln!(out, "\n{} cases, {failed} failed", results.len())?;
+
+    Ok(if failed == 0 { 0 } else { 1 })
+}
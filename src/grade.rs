@@ -0,0 +1,664 @@
+use crate::arch::vm::{root_path, Capture, Config, Halt, VM};
+use crate::cli::{parse_duration, Cli};
+use crate::diff;
+use crate::json::Json;
+use crate::toml::{self, Table, Value};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 仕様にもコマンドラインにも制限がなくても，無限ループで止まらないようにする
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DIFF_CONTEXT: usize = 3;
+
+const CASE_KEYS: [&str; 15] = [
+    "name",
+    "binary",
+    "args",
+    "stdin",
+    "env",
+    "root",
+    "exit_code",
+    "stdout",
+    "stdout_file",
+    "stderr",
+    "stderr_file",
+    "files",
+    "timeout",
+    "max_instructions",
+    "max_output_bytes",
+];
+const SPEC_KEYS: [&str; 3] = ["suite", "jobs", "test"];
+
+// 1つの仕様ファイル([[test]]の集まり)
+pub struct Spec {
+    pub suite: String,
+    pub jobs: Option<usize>,
+    pub cases: Vec<Case>,
+}
+
+pub struct Case {
+    pub name: String,
+    pub binary: PathBuf,
+    // argv[1]以降
+    pub args: Vec<String>,
+    pub stdin: Option<PathBuf>,
+    pub env: Option<Vec<String>>,
+    // ゲストがopenするファイルのルートディレクトリ
+    pub root: PathBuf,
+    pub exit_code: Option<i32>,
+    pub stdout: Option<Vec<u8>>,
+    pub stderr: Option<Vec<u8>>,
+    // (rootからの相対パス, 期待する内容)
+    pub files: Vec<(String, Vec<u8>)>,
+    pub timeout: Option<Duration>,
+    pub max_instructions: Option<usize>,
+    pub max_output_bytes: Option<usize>,
+}
+
+pub struct Failure {
+    pub kind: &'static str,
+    pub message: String,
+    pub diff: String,
+}
+
+pub struct Outcome {
+    pub name: String,
+    pub time: Duration,
+    pub exit_code: Option<i32>,
+    pub instructions: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    // 期待と違ったのではなく，VMがpanicしたり不正な命令に達したりしたか
+    pub fn errored(&self) -> bool {
+        self.failures.iter().any(Failure::is_error)
+    }
+}
+
+impl Failure {
+    fn is_error(&self) -> bool {
+        matches!(self.kind, "error" | "fault")
+    }
+}
+
+impl Spec {
+    // 仕様中のパスはdirからの相対パスとして扱う．limitsはコマンドラインで指定された既定の制限
+    pub fn parse(src: &str, dir: &Path, limits: &Cli) -> Result<Self, String> {
+        let top = toml::parse(src)?;
+
+        for key in top.keys() {
+            if !CASE_KEYS.contains(&key.as_str()) && !SPEC_KEYS.contains(&key.as_str()) {
+                return Err(format!("unknown key '{key}'"));
+            }
+        }
+
+        let suite = match top.get("suite") {
+            Some(v) => expect_str(v, "suite")?.to_owned(),
+            None => "minix_vm".to_owned(),
+        };
+        let jobs = match top.get("jobs") {
+            Some(v) => Some(expect_count(v, "jobs")?),
+            None => None,
+        };
+
+        let tests = match top.get("test") {
+            Some(Value::Array(tests)) => tests.as_slice(),
+            Some(v) => {
+                return Err(format!(
+                    "'test' must be an array of tables, not {}",
+                    v.type_name()
+                ))
+            }
+            None => return Err("no [[test]] entries".to_owned()),
+        };
+
+        let mut cases = Vec::new();
+        for (i, test) in tests.iter().enumerate() {
+            let table = test
+                .as_table()
+                .ok_or(format!("test #{}: not a table", i + 1))?;
+            let lookup = Lookup { table, top: &top };
+
+            let case = lookup
+                .case(dir, limits)
+                .map_err(|err| format!("test #{}: {err}", i + 1))?;
+            cases.push(case);
+        }
+
+        Ok(Spec { suite, jobs, cases })
+    }
+
+    pub fn load(path: &Path, limits: &Cli) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        Spec::parse(&src, dir, limits).map_err(|err| format!("{}: {err}", path.display()))
+    }
+}
+
+// [[test]]のキーを引き，なければトップレベルの既定値を引く
+struct Lookup<'a> {
+    table: &'a Table,
+    top: &'a Table,
+}
+
+impl Lookup<'_> {
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.table.get(key).or_else(|| self.top.get(key))
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, String> {
+        self.get(key)
+            .map(|v| expect_str(v, key).map(str::to_owned))
+            .transpose()
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>, String> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        let items = value
+            .as_array()
+            .ok_or(format!("'{key}' must be an array of strings"))?;
+
+        items
+            .iter()
+            .map(|v| expect_str(v, key).map(str::to_owned))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    fn count(&self, key: &str) -> Result<Option<usize>, String> {
+        self.get(key).map(|v| expect_count(v, key)).transpose()
+    }
+
+    // "5s"のような文字列か，秒数
+    fn duration(&self, key: &str) -> Result<Option<Duration>, String> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        let duration = match value {
+            Value::String(s) => parse_duration(s),
+            v => v
+                .as_float()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+        };
+
+        duration
+            .map(Some)
+            .ok_or(format!("invalid value for '{key}'"))
+    }
+
+    // 文字列で直接書くか，_fileで期待する内容のファイルを指定する
+    fn expected(&self, key: &str, dir: &Path) -> Result<Option<Vec<u8>>, String> {
+        let file_key = format!("{key}_file");
+
+        match (self.string(key)?, self.string(&file_key)?) {
+            (Some(_), Some(_)) => Err(format!("both '{key}' and '{file_key}' are given")),
+            (Some(text), None) => Ok(Some(text.into_bytes())),
+            (None, Some(path)) => read(&dir.join(path)).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn case(&self, dir: &Path, limits: &Cli) -> Result<Case, String> {
+        for key in self.table.keys() {
+            if !CASE_KEYS.contains(&key.as_str()) {
+                return Err(format!("unknown key '{key}'"));
+            }
+        }
+
+        let binary = self.string("binary")?.ok_or("missing 'binary'")?;
+        let name = self
+            .table
+            .get("name")
+            .map_or(Ok(binary.as_str()), |v| expect_str(v, "name"))?;
+
+        let exit_code = match self.get("exit_code") {
+            Some(v) => Some(
+                v.as_integer()
+                    .and_then(|n| i32::try_from(n).ok())
+                    .ok_or("'exit_code' must be an integer")?,
+            ),
+            None => None,
+        };
+
+        let mut files = Vec::new();
+        if let Some(value) = self.get("files") {
+            let table = value
+                .as_table()
+                .ok_or("'files' must be a table of path = expected file")?;
+
+            for (path, expected) in table {
+                let expected = expect_str(expected, "files")?;
+                files.push((path.clone(), read(&dir.join(expected))?));
+            }
+        }
+
+        Ok(Case {
+            name: name.to_owned(),
+            binary: dir.join(&binary),
+            args: self.strings("args")?.unwrap_or_default(),
+            stdin: self.string("stdin")?.map(|path| dir.join(path)),
+            env: self.strings("env")?,
+            root: dir.join(self.string("root")?.unwrap_or_default()),
+            exit_code,
+            stdout: self.expected("stdout", dir)?,
+            stderr: self.expected("stderr", dir)?,
+            files,
+            timeout: self.duration("timeout")?.or(limits.timeout),
+            max_instructions: self.count("max_instructions")?.or(limits.max_instructions),
+            max_output_bytes: self.count("max_output_bytes")?.or(limits.max_output_bytes),
+        })
+    }
+}
+
+fn expect_str<'a>(value: &'a Value, key: &str) -> Result<&'a str, String> {
+    value.as_str().ok_or(format!(
+        "'{key}' must be a string, not {}",
+        value.type_name()
+    ))
+}
+
+fn expect_count(value: &Value, key: &str) -> Result<usize, String> {
+    value
+        .as_integer()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or(format!("'{key}' must be a non-negative integer"))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+// 1つのテストを実行して，期待する結果と比較する
+pub fn run_case(case: &Case) -> Outcome {
+    let started = Instant::now();
+    let mut outcome = Outcome {
+        name: case.name.clone(),
+        time: Duration::ZERO,
+        exit_code: None,
+        instructions: 0,
+        failures: Vec::new(),
+    };
+
+    // 前回の実行で残ったファイルを期待する出力と取り違えないように消しておく
+    for (path, _) in &case.files {
+        if let Err(message) = remove_expected(&case.root, path) {
+            outcome.failures.push(Failure {
+                kind: "error",
+                message,
+                diff: String::new(),
+            });
+            outcome.time = started.elapsed();
+            return outcome;
+        }
+    }
+
+    let stdout = Capture::default();
+    let stderr = Capture::default();
+
+    match execute(case, stdout.clone(), stderr.clone()) {
+        Ok((halt, instructions, summary)) => {
+            outcome.instructions = instructions;
+
+            match halt {
+                Halt::Exit(status) => outcome.exit_code = Some(status as i32),
+                halt if halt.is_abnormal() => outcome.failures.push(Failure {
+                    kind: if halt.is_limit() { "limit" } else { "fault" },
+                    message: summary,
+                    diff: String::new(),
+                }),
+                _ => {}
+            }
+        }
+        Err(message) => outcome.failures.push(Failure {
+            kind: "error",
+            message,
+            diff: String::new(),
+        }),
+    }
+
+    if let Some(expected) = case.exit_code {
+        if outcome.exit_code != Some(expected) {
+            let actual = outcome
+                .exit_code
+                .map_or("none".to_owned(), |code| code.to_string());
+
+            outcome.failures.push(Failure {
+                kind: "exit_code",
+                message: format!("expected exit code {expected}, got {actual}"),
+                diff: String::new(),
+            });
+        }
+    }
+
+    if let Some(expected) = &case.stdout {
+        outcome
+            .failures
+            .extend(compare("stdout", expected, &stdout.contents()));
+    }
+
+    if let Some(expected) = &case.stderr {
+        outcome
+            .failures
+            .extend(compare("stderr", expected, &stderr.contents()));
+    }
+
+    for (path, expected) in &case.files {
+        match fs::read(root_path(&case.root, path).unwrap()) {
+            Ok(actual) => outcome
+                .failures
+                .extend(compare("file", expected, &actual).map(|f| Failure {
+                    message: format!("file '{path}' differs"),
+                    ..f
+                })),
+            Err(err) => outcome.failures.push(Failure {
+                kind: "file",
+                message: format!("file '{path}': {err}"),
+                diff: String::new(),
+            }),
+        }
+    }
+
+    outcome.time = started.elapsed();
+    outcome
+}
+
+fn remove_expected(root: &Path, path: &str) -> Result<(), String> {
+    let full = root_path(root, path).ok_or(format!("file '{path}' is outside the root"))?;
+    match fs::remove_file(&full) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(format!("{}: {err}", full.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn execute(case: &Case, stdout: Capture, stderr: Capture) -> Result<(Halt, usize, String), String> {
+    let content = read(&case.binary)?;
+
+    let mut config = Config {
+        output: Some(Box::new(stdout)),
+        error: Some(Box::new(stderr)),
+        max_instructions: case.max_instructions,
+        timeout: Some(case.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+        max_output_bytes: case.max_output_bytes,
+        root: Some(case.root.clone()),
+        ..Config::default()
+    };
+
+    if let Some(env) = &case.env {
+        config.env = env.clone();
+    }
+
+    // 指定がなければ空の入力にして，readで端末を待たないようにする
+    let path = case.stdin.as_deref().unwrap_or(Path::new("/dev/null"));
+    config.stdin = Some(File::open(path).map_err(|err| format!("{}: {err}", path.display()))?);
+
+    let args: Vec<String> = std::iter::once(case.binary.to_string_lossy().into_owned())
+        .chain(case.args.iter().cloned())
+        .collect();
+
+    // 未実装の命令などでVMがpanicしても，他のテストは続ける
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut vm = VM::with_config(content, &args, config)
+            .map_err(|err| format!("{}: {err}", case.binary.display()))?;
+        let halt = vm.run();
+        Ok((halt, vm.steps(), vm.summary(halt)))
+    }))
+    .unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+
+        Err(format!("vm panicked: {message}"))
+    })
+}
+
+fn compare(kind: &'static str, expected: &[u8], actual: &[u8]) -> Option<Failure> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let a: Vec<&str> = expected.split('\n').collect();
+    let b: Vec<&str> = actual.split('\n').collect();
+
+    let diff = format!(
+        "--- expected\n+++ actual\n{}",
+        diff::unified(&a, &b, DIFF_CONTEXT)
+    );
+
+    Some(Failure {
+        kind,
+        message: format!("{kind} differs"),
+        diff,
+    })
+}
+
+// jobs個のスレッドでテストを並列に実行する．結果は仕様の順に返す
+pub fn run_all(cases: &[Case], jobs: usize, on_done: impl Fn(&Outcome) + Sync) -> Vec<Outcome> {
+    parallel(cases, jobs, |case| {
+        let outcome = run_case(case);
+        on_done(&outcome);
+        outcome
+    })
+}
+
+// itemsの各要素にfをjobs個のスレッドで並列に適用する．結果はitemsの順に返す
+pub fn parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect()
+}
+
+// `minix_vm grade` の本体．すべて成功なら0を返す
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let spec = match Spec::load(Path::new(&cli.file), cli) {
+        Ok(spec) => spec,
+        Err(err) => {
+            eprintln!("minix_vm: {err}");
+            return Ok(2);
+        }
+    };
+
+    let jobs = cli
+        .jobs
+        .or(spec.jobs)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    // panicの既定の表示はテストの結果表示に混ざるので抑止する．メッセージは結果に残る
+    panic::set_hook(Box::new(|_| {}));
+
+    let started = Instant::now();
+    let outcomes = run_all(&spec.cases, jobs, |outcome| {
+        let status = if outcome.passed() { "PASS" } else { "FAIL" };
+        // 書けなかったことは後の出力で分かる
+        let _ = writeln!(
+            io::stdout(),
+            "{status} {} ({:.3}s)",
+            outcome.name,
+            outcome.time.as_secs_f64()
+        );
+    });
+    let elapsed = started.elapsed();
+
+    let mut out = io::stdout().lock();
+    for outcome in outcomes.iter().filter(|o| !o.passed()) {
+        writeln!(out, "\n--- {} ---", outcome.name)?;
+        for failure in &outcome.failures {
+            writeln!(out, "{}", failure.message)?;
+            write!(out, "{}", failure.diff)?;
+        }
+    }
+
+    let passed = outcomes.iter().filter(|o| o.passed()).count();
+    writeln!(
+        out,
+        "\n{passed} passed, {} failed, {} total ({:.3}s)",
+        outcomes.len() - passed,
+        outcomes.len(),
+        elapsed.as_secs_f64()
+    )?;
+
+    if let Some(path) = &cli.junit {
+        fs::write(path, junit(&spec.suite, &outcomes, elapsed))?;
+    }
+
+    if let Some(path) = &cli.json {
+        fs::write(
+            path,
+            format!("{:#}\n", summary(&spec.suite, &outcomes, elapsed)),
+        )?;
+    }
+
+    Ok(if passed == outcomes.len() { 0 } else { 1 })
+}
+
+pub fn junit(suite: &str, outcomes: &[Outcome], elapsed: Duration) -> String {
+    let errors = outcomes.iter().filter(|o| o.errored()).count();
+    let failed = outcomes.iter().filter(|o| !o.passed()).count() - errors;
+    let mut out = String::new();
+
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<testsuite name="{}" tests="{}" failures="{failed}" errors="{errors}" time="{:.3}">"#,
+        xml_escape(suite),
+        outcomes.len(),
+        elapsed.as_secs_f64()
+    );
+
+    for outcome in outcomes {
+        let _ = write!(
+            out,
+            r#"  <testcase name="{}" classname="{}" time="{:.3}""#,
+            xml_escape(&outcome.name),
+            xml_escape(suite),
+            outcome.time.as_secs_f64()
+        );
+
+        if outcome.passed() {
+            let _ = writeln!(out, "/>");
+            continue;
+        }
+
+        let _ = writeln!(out, ">");
+        for failure in &outcome.failures {
+            let tag = if failure.is_error() {
+                "error"
+            } else {
+                "failure"
+            };
+            let _ = writeln!(
+                out,
+                r#"    <{tag} type="{}" message="{}">{}</{tag}>"#,
+                failure.kind,
+                xml_escape(&failure.message),
+                xml_escape(&failure.diff)
+            );
+        }
+        let _ = writeln!(out, "  </testcase>");
+    }
+
+    let _ = writeln!(out, "</testsuite>");
+    out
+}
+
+pub fn summary(suite: &str, outcomes: &[Outcome], elapsed: Duration) -> Json {
+    let passed = outcomes.iter().filter(|o| o.passed()).count();
+
+    let tests = outcomes
+        .iter()
+        .map(|o| {
+            let failures = o
+                .failures
+                .iter()
+                .map(|f| {
+                    Json::object([
+                        ("kind", f.kind.into()),
+                        ("message", f.message.as_str().into()),
+                        ("diff", f.diff.as_str().into()),
+                    ])
+                })
+                .collect::<Vec<_>>();
+
+            Json::object([
+                ("name", o.name.as_str().into()),
+                ("passed", o.passed().into()),
+                ("time", o.time.as_secs_f64().into()),
+                ("exit_code", o.exit_code.into()),
+                ("instructions", o.instructions.into()),
+                ("failures", failures.into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    Json::object([
+        ("suite", suite.into()),
+        ("total", outcomes.len().into()),
+        ("passed", passed.into()),
+        ("failed", (outcomes.len() - passed).into()),
+        ("time", elapsed.as_secs_f64().into()),
+        ("tests", tests.into()),
+    ])
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // XML 1.0で使えない制御文字
+            c if (c as u32) < 0x20 && !matches!(c, '\n' | '\r' | '\t') => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
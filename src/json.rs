@@ -0,0 +1,371 @@
+use std::fmt::{self, Display, Write};
+
+// レポート出力と設定の読み込み用の最小限のJSON値．オブジェクトはキーの挿入順を保つ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<K: Into<String>>(pairs: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Int(_) | Json::Float(_) => "number",
+            Json::Str(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Int(n) => write!(f, "{n}"),
+            Json::Float(x) if x.is_finite() => write!(f, "{x}"),
+            Json::Float(_) => f.write_str("null"),
+            Json::Str(s) => f.write_str(&escape(s)),
+            Json::Array(items) => {
+                if items.is_empty() {
+                    return f.write_str("[]");
+                }
+
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    newline(f, indent.map(|n| n + 1))?;
+                    item.write(f, indent.map(|n| n + 1))?;
+                }
+                newline(f, indent)?;
+                f.write_char(']')
+            }
+            Json::Object(pairs) => {
+                if pairs.is_empty() {
+                    return f.write_str("{}");
+                }
+
+                f.write_char('{')?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    newline(f, indent.map(|n| n + 1))?;
+                    f.write_str(&escape(key))?;
+                    f.write_str(if indent.is_some() { ": " } else { ":" })?;
+                    value.write(f, indent.map(|n| n + 1))?;
+                }
+                newline(f, indent)?;
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn newline(f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+    match indent {
+        Some(n) => write!(f, "\n{:1$}", "", n * 2),
+        None => Ok(()),
+    }
+}
+
+// {}は1行，{:#}はインデントして出力する
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = if f.alternate() { Some(0) } else { None };
+        self.write(f, indent)
+    }
+}
+
+pub fn parse(src: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        chars: src.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+
+    let value = parser.value().and_then(|value| {
+        parser.skip_blank();
+        match parser.peek() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected '{c}' after the value")),
+        }
+    });
+    value.map_err(|err| format!("line {}: {err}", parser.line))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next() {
+            Some(x) if x == c => Ok(()),
+            Some(x) => Err(format!("expected '{c}', found '{x}'")),
+            None => Err(format!("expected '{c}', found end of file")),
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for c in word.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_blank();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::Str),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("unexpected '{c}'")),
+            None => Err("unexpected end of file".to_owned()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut pairs = Vec::new();
+        self.skip_blank();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Json::Object(pairs));
+        }
+
+        loop {
+            self.skip_blank();
+            let key = self.string()?;
+            self.skip_blank();
+            self.expect(':')?;
+            pairs.push((key, self.value()?));
+            self.skip_blank();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(pairs)),
+                _ => return Err("expected ',' or '}' in object".to_owned()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_blank();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err("expected ',' or ']' in array".to_owned()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => self.unicode()?,
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err("invalid escape sequence".to_owned()),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    // \uXXXX．サロゲートペアは続く\uXXXXと合わせて1文字にする
+    fn unicode(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or("invalid \\u escape".to_owned());
+        }
+
+        self.expect('\\')?;
+        self.expect('u')?;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err("invalid surrogate pair".to_owned());
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or("invalid surrogate pair".to_owned())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+        // from_str_radixは先頭の+も受け付けるので，16進数字だけか確かめる
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid \\u escape '{digits}'"));
+        }
+        Ok(u32::from_str_radix(&digits, 16).unwrap())
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.next();
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+
+        if let Ok(n) = text.parse() {
+            return Ok(Json::Int(n));
+        }
+        text.parse()
+            .map(Json::Float)
+            .map_err(|_| format!("invalid number '{text}'"))
+    }
+}
+
+// 引用符を含むJSON文字列リテラルにする
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Int(n)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Self {
+        Json::Int(n as i64)
+    }
+}
+
+impl From<u16> for Json {
+    fn from(n: u16) -> Self {
+        Json::Int(n as i64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Int(n as i64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Self {
+        Json::Int(n as i64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(x: f64) -> Self {
+        Json::Float(x)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::Str(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::Str(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Self {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::arch::assemble::{assemble, Label, Program, Section};
+use crate::arch::header::AOutBuilder;
+use crate::arch::symbol::{section, Symbol, C_EXT};
+use crate::cli::{parse_number, Cli};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// アセンブリのソースを組み立ててMINIXのa.outを書き出す
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let src = fs::read_to_string(&cli.file)?;
+
+    let output = match &cli.output {
+        Some(path) => path.clone(),
+        None => Path::new(&cli.file)
+            .with_extension("out")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    match link(&src, cli).and_then(|bytes| fs::write(&output, bytes).map_err(|e| e.to_string())) {
+        Ok(()) => Ok(0),
+        Err(err) => {
+            eprintln!("minix_vm: {}: {err}", cli.file);
+            Ok(1)
+        }
+    }
+}
+
+pub fn link(src: &str, cli: &Cli) -> Result<Vec<u8>, String> {
+    let program = assemble(src, 0)?;
+
+    let entry_point = match &cli.entry {
+        Some(entry) => entry_point(&program, entry)?,
+        None => 0,
+    };
+
+    let mut builder = AOutBuilder::new()
+        .bss_size(program.bss_size as u32 + cli.bss.unwrap_or(0))
+        .entry_point(entry_point as u32);
+    if let Some(stack) = cli.stack {
+        builder = builder.stack_size(stack);
+    }
+    if !cli.no_symbols {
+        builder = builder.symbols(symbols(&program.labels));
+    }
+
+    builder.text(program.text).data(program.data).build()
+}
+
+// `.`で始まるラベルはソースの中だけで使うものとして，シンボルテーブルには入れない
+pub fn symbols(labels: &[Label]) -> Vec<Symbol> {
+    labels
+        .iter()
+        .filter(|label| !label.name.starts_with('.'))
+        .map(|label| {
+            let section = match label.section {
+                Section::Text => section::TEXT,
+                Section::Data => section::DATA,
+                Section::Bss => section::BSS,
+            };
+            Symbol {
+                name: label.name.clone(),
+                value: label.address as u32,
+                sclass: section | C_EXT,
+                numaux: 0,
+                n_type: 0,
+            }
+        })
+        .collect()
+}
+
+fn entry_point(program: &Program, entry: &str) -> Result<u16, String> {
+    if let Some(label) = program.labels.iter().find(|label| label.name == entry) {
+        if label.section != Section::Text {
+            return Err(format!("entry point `{entry}` is not in .text"));
+        }
+        return Ok(label.address);
+    }
+
+    parse_number(entry)
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or(format!("undefined entry point `{entry}`"))
+}
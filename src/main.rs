@@ -1,63 +1,230 @@
+use arch::asm::Assembly;
+use arch::bin::BinaryManager;
+use arch::coverage::{parse_lcov, same_file, split_lcov};
+use arch::explore::{explore, Chunk};
+use arch::stack;
 use arch::vm::VM;
+use cli::Command;
 use std::env;
-use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::process::exit;
 
 mod arch;
-pub mod test;
-
-pub static mut VM_MODE: bool = false;
-pub static mut DASM_MODE: bool = false;
-pub static mut INPUT_FILE: &str = "";
-pub static mut FLAG: usize = 0;
+mod bindiff;
+mod cfg;
+mod cli;
+mod debugger;
+mod diff;
+mod disasm;
+mod golden;
+mod grade;
+mod json;
+mod link;
+mod patch;
+mod sections;
+#[cfg(test)]
+mod test;
+mod toml;
+mod trace_dump;
 
 fn main() -> io::Result<()> {
+    // headなどに渡して出力が途中で閉じられても，エラーにせず正常に終える
+    match run() {
+        Ok(status) => exit(status),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => exit(0),
+        Err(err) => Err(err),
+    }
+}
+
+// サブコマンドを実行して終了ステータスを返す
+fn run() -> io::Result<i32> {
     let args: Vec<String> = env::args().collect();
 
-    let mode = &args[1];
-    let mut filename = "";
+    let cli = match cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("minix_vm: {err}");
+            eprintln!("Try 'minix_vm --help' for more information.");
+            return Ok(2);
+        }
+    };
+
+    if cli.command == Command::Help {
+        write!(io::stdout(), "{}", cli::USAGE)?;
+        return Ok(0);
+    }
+
+    if cli.command == Command::Grade {
+        return grade::main(&cli);
+    }
+
+    if cli.command == Command::Golden {
+        return golden::main(Path::new(&cli.file), cli.bless);
+    }
+
+    if cli.command == Command::Cfg {
+        return cfg::main(&cli);
+    }
+
+    if cli.command == Command::Bindiff {
+        return bindiff::main(&cli);
+    }
+
+    if cli.command == Command::Patch {
+        return patch::main(&cli);
+    }
+
+    if cli.command == Command::Sections {
+        return sections::main(&cli);
+    }
+
+    if cli.command == Command::TraceDump {
+        return trace_dump::main(&cli);
+    }
+
+    if cli.command == Command::Link {
+        return link::main(&cli);
+    }
+
+    if cli.command == Command::Disasm {
+        return disasm::main(&cli);
+    }
+
+    let content = read_file_content(&cli.file)?;
+
+    let mut config = cli.vm_config()?;
+    let symbols = BinaryManager::new(content.clone()).get_symbols();
+    config.trace_filter = match cli.trace_filter(&symbols) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("minix_vm: {err}");
+            return Ok(2);
+        }
+    };
+
+    let mut vm = match VM::with_config(content, &cli.args, config) {
+        Ok(vm) => vm,
+        Err(err) => {
+            eprintln!("minix_vm: {}: {err}", cli.file);
+            return Ok(1);
+        }
+    };
+
+    let halt = if cli.command == Command::Debug {
+        debugger::run(&mut vm)
+    } else {
+        Some(vm.run())
+    };
 
-    match mode.as_str() {
-        "-m" => {
-            unsafe {
-                VM_MODE = true;
-            };
-            filename = &args[2];
+    io::stdout().flush()?;
+
+    if let Some(halt) = halt {
+        if halt.is_abnormal() {
+            eprintln!("minix_vm: {}", vm.summary(halt));
         }
-        "-d" => {
-            unsafe {
-                DASM_MODE = true;
-            };
-            filename = &args[2];
+
+        report(&cli, &vm)?;
+        // 書けなくても実行ファイルの終了ステータスは変えない
+        if let Err(err) = write_coverage(&cli, &vm) {
+            eprintln!(
+                "minix_vm: {}: {err}",
+                cli.coverage.as_deref().unwrap_or_default()
+            );
         }
-        _ => {
-            filename = &args[1];
+
+        return Ok(halt.exit_status());
+    }
+
+    Ok(0)
+}
+
+// --stats，--profile，--mem-heatmap，--stack-usageの集計結果．--report-fileがなければ標準エラー出力に書く
+fn report(cli: &cli::Cli, vm: &VM) -> io::Result<()> {
+    let mut out = String::new();
+
+    if let Some(stats) = vm.stats() {
+        match cli.stats.as_deref() {
+            Some("json") => out += &format!("{:#}\n", stats.to_json(vm.elapsed())),
+            _ => out += &stats.render(vm.elapsed()),
         }
     }
 
-    let content = read_file_content(&filename)?;
+    if let Some(profile) = vm.profile() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out += &profile.render(cli.profile.as_deref().unwrap_or_default());
+    }
 
-    if mode == "-d" {
-        let mut vm = VM::new(content, &args);
-        let asm = vm.disassemble();
+    if let Some(heatmap) = vm.heatmap() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out += &heatmap.render(vm.layout(), &vm.symbols);
+    }
 
-        for a in asm {
-            println!("{:?}", a);
+    if let Some(stack) = vm.stack_usage() {
+        if !out.is_empty() {
+            out.push('\n');
         }
+        let estimates = stack::estimate(vm.ram.text(), &vm.symbols);
+        out += &stack.render(vm.layout(), &estimates);
+    }
 
-        return Ok(());
+    match &cli.report_file {
+        Some(path) if !out.is_empty() => fs::write(path, out),
+        _ => io::stderr().write_all(out.as_bytes()),
     }
+}
 
-    let bin_args = if unsafe { VM_MODE } {
-        &args[2..]
-    } else {
-        &args[1..]
+// --coverageのトレースファイル．既にあれば同じ実行ファイルのレコードに今回の実行を加える
+fn write_coverage(cli: &cli::Cli, vm: &VM) -> io::Result<()> {
+    let (Some(path), Some(coverage)) = (&cli.coverage, vm.coverage()) else {
+        return Ok(());
     };
 
-    let mut vm = VM::new(content, bin_args);
-    vm.run();
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let records =
+        parse_lcov(&src).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    // 同じ実行ファイルのレコードは足し合わせる．SFの書き方が違っても同じファイルなら同じレコード
+    let mut merged = coverage.clone();
+    for (_, record) in records.iter().filter(|(sf, _)| same_file(sf, &cli.file)) {
+        merged.merge(record);
+    }
+
+    let bm = BinaryManager::new(read_file_content(&cli.file)?);
+    let symbols = bm.get_symbols();
+    let entry = bm.get_header().map_or(0, |h| h.entry_point as u16);
+    let entries = std::iter::once(entry).chain(symbols.functions().map(|s| s.value as u16));
+    let code: Vec<Assembly> = explore(bm.get_text(), 0, entries)
+        .iter()
+        .filter_map(Chunk::as_code)
+        .cloned()
+        .collect();
+    let ours = merged.to_lcov(&cli.file, &code, &symbols);
 
-    Ok(())
+    // 他の実行ファイルのレコードは書かれたまま残す
+    let mut out = String::new();
+    let mut written = false;
+    for (sf, record) in split_lcov(&src) {
+        if !same_file(sf, &cli.file) {
+            out += record;
+        } else if !written {
+            out += &ours;
+            written = true;
+        }
+    }
+    if !written {
+        out += &ours;
+    }
+    fs::write(path, out)
 }
 
 fn read_file_content(path: &str) -> io::Result<Vec<u8>> {
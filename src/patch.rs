@@ -0,0 +1,223 @@
+use crate::arch::asm::Assembly;
+use crate::arch::assemble::assemble;
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode::{disassemble, Decoder};
+use crate::arch::explore::{explore, Chunk};
+use crate::arch::header::HEADER_SIZE;
+use crate::cli::{parse_number, Cli};
+use crate::disasm::parse_hex;
+use crate::json::{self, Json};
+use std::fs;
+use std::io::{self, Write};
+
+const NOP: u8 = 0x90;
+
+// テキストセグメントの1か所の書き換え
+#[derive(Debug, PartialEq)]
+pub struct Patch {
+    pub address: u16,
+    pub code: Code,
+    // 置き換えるバイト数．省略時はaddressにある命令の大きさ
+    pub size: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Code {
+    Asm(String),
+    Bytes(Vec<u8>),
+}
+
+// 書き換えの前後の命令
+#[derive(Debug)]
+pub struct Applied {
+    pub before: Vec<Assembly>,
+    pub after: Vec<Assembly>,
+}
+
+impl Patch {
+    // [{"address": "0x0017", "asm": "jmp 0028"}, {"address": 25, "bytes": "9090", "size": 3}]
+    pub fn list_from_json(value: &Json) -> Result<Vec<Patch>, String> {
+        let Json::Array(items) = value else {
+            return Err(format!(
+                "expected an array of patches, found {}",
+                value.type_name()
+            ));
+        };
+
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| Patch::from_json(item).map_err(|err| format!("patch {i}: {err}")))
+            .collect()
+    }
+
+    fn from_json(value: &Json) -> Result<Patch, String> {
+        let address = match value.get("address") {
+            Some(Json::Int(n)) => u16::try_from(*n).ok(),
+            Some(Json::Str(s)) => parse_number(s).and_then(|n| u16::try_from(n).ok()),
+            Some(other) => return Err(format!("invalid address: {other}")),
+            None => return Err("missing \"address\"".to_owned()),
+        }
+        .ok_or("address is out of range".to_owned())?;
+
+        let code = match (value.get("asm"), value.get("bytes")) {
+            (Some(Json::Str(src)), None) => Code::Asm(src.clone()),
+            (None, Some(Json::Str(hex))) => Code::Bytes(parse_hex(hex)?),
+            (Some(_), Some(_)) => {
+                return Err("give either \"asm\" or \"bytes\", not both".to_owned())
+            }
+            _ => return Err("missing \"asm\" or \"bytes\" string".to_owned()),
+        };
+
+        let size = match value.get("size") {
+            Some(Json::Int(n)) if *n > 0 => Some(*n as usize),
+            Some(other) => return Err(format!("invalid size: {other}")),
+            None => None,
+        };
+
+        Ok(Patch {
+            address,
+            code,
+            size,
+        })
+    }
+
+    // a.outのバイト列のテキストを書き換える．余ったバイトはnopで埋める
+    pub fn apply(&self, binary: &mut [u8]) -> Result<Applied, String> {
+        let bm = BinaryManager::new(binary.to_vec());
+        let Some(header) = bm.get_header() else {
+            return Err("not a MINIX a.out file".to_owned());
+        };
+        let text = bm.get_text();
+        let address = self.address as usize;
+        if address >= text.len() {
+            return Err(format!(
+                "0x{address:04x} is outside the text segment (0x{:04x} bytes)",
+                text.len()
+            ));
+        }
+
+        // 命令の途中からは書き換えない．disasm --recursiveと同じく，エントリポイントと関数から辿る
+        let symbols = bm.get_symbols();
+        let entries = std::iter::once(header.entry_point as u16)
+            .chain(symbols.functions().map(|s| s.value as u16));
+        let chunks = explore(text, 0, entries);
+        match chunks.iter().rev().find(|c| c.address() <= self.address) {
+            Some(Chunk::Code(asm)) if asm.address == self.address => {}
+            Some(Chunk::Code(asm)) => {
+                return Err(format!(
+                    "0x{address:04x} is inside the instruction at 0x{:04x}",
+                    asm.address
+                ))
+            }
+            // 辿れなかったところは，disasmの既定と同じく先頭から線形に読んだ命令の区切りに合わせる
+            _ if disassemble(text, 0)
+                .iter()
+                .any(|a| a.address == self.address) => {}
+            _ => {
+                return Err(format!(
+                    "0x{address:04x} is not the start of an instruction"
+                ))
+            }
+        }
+
+        let code = match &self.code {
+            Code::Asm(src) => {
+                let program =
+                    assemble(src, self.address).map_err(|err| format!("`{src}`: {err}"))?;
+                if !program.data.is_empty() || program.bss_size != 0 {
+                    return Err(format!("`{src}`: only text can be patched"));
+                }
+                program.text
+            }
+            Code::Bytes(bytes) => bytes.clone(),
+        };
+
+        let size = match self.size {
+            Some(size) => size,
+            None => Decoder::at(text, 0, self.address)
+                .decode()
+                .map_or(1, |a| a.size),
+        };
+        if code.len() > size {
+            return Err(format!(
+                "the new code is {} bytes but only {size} bytes are replaced at 0x{address:04x} \
+                 (use a larger size to overwrite the following instructions)",
+                code.len()
+            ));
+        }
+        if address + size > text.len() {
+            return Err(format!(
+                "0x{address:04x}+{size} runs past the end of the text segment"
+            ));
+        }
+
+        let before = disassemble(&text[address..address + size], self.address);
+        let mut replacement = code;
+        replacement.resize(size, NOP);
+        let offset = HEADER_SIZE + address;
+        binary[offset..offset + size].copy_from_slice(&replacement);
+        let after = disassemble(&replacement, self.address);
+
+        Ok(Applied { before, after })
+    }
+}
+
+// `minix_vm patch` の本体
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let patches = match patches(cli) {
+        Ok(patches) => patches,
+        Err(err) => {
+            eprintln!("minix_vm: {err}");
+            return Ok(2);
+        }
+    };
+
+    let mut binary = fs::read(&cli.file)?;
+    let mut out = io::stdout().lock();
+    for patch in &patches {
+        match patch.apply(&mut binary) {
+            Ok(applied) => {
+                for asm in &applied.before {
+                    writeln!(out, "- {asm:?}")?;
+                }
+                for asm in &applied.after {
+                    writeln!(out, "+ {asm:?}")?;
+                }
+            }
+            Err(err) => {
+                eprintln!("minix_vm: {}: {err}", cli.file);
+                return Ok(1);
+            }
+        }
+    }
+
+    let output = cli
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("{}.patched", cli.file));
+    fs::write(&output, binary)?;
+    Ok(0)
+}
+
+// --patchesのファイル，または位置引数の<addr> <asm>
+fn patches(cli: &Cli) -> Result<Vec<Patch>, String> {
+    if let Some(path) = &cli.patches {
+        let src = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        let value = json::parse(&src).map_err(|err| format!("{path}: {err}"))?;
+        return Patch::list_from_json(&value).map_err(|err| format!("{path}: {err}"));
+    }
+
+    let [address, src] = cli.args.as_slice() else {
+        return Err("patch needs <addr> and <asm>, or --patches <file.json>".to_owned());
+    };
+    let address = parse_number(address)
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or(format!("invalid address '{address}'"))?;
+
+    Ok(vec![Patch {
+        address,
+        code: Code::Asm(src.clone()),
+        size: cli.size.map(|n| n as usize),
+    }])
+}
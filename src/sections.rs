@@ -0,0 +1,64 @@
+use crate::arch::bin::BinaryManager;
+use crate::arch::data::DataView;
+use crate::arch::header::HEADER_SIZE;
+use crate::cli::Cli;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
+
+// `minix_vm sections` の本体
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let bm = BinaryManager::new(fs::read(&cli.file)?);
+    if bm.get_header().is_none() {
+        eprintln!("minix_vm: {}: not a MINIX a.out file", cli.file);
+        return Ok(1);
+    }
+
+    io::stdout().write_all(render(&bm).as_bytes())?;
+    Ok(0)
+}
+
+// セクションの一覧とデータセグメントのダンプ
+pub fn render(bm: &BinaryManager<std::vec::IntoIter<u8>>) -> String {
+    let mut out = String::new();
+    let Some(header) = bm.get_header() else {
+        return out;
+    };
+    let text = header.text_size;
+    let data = header.data_size;
+    let bss = header.bss_size;
+    let text_offset = HEADER_SIZE as u32;
+    let data_offset = text_offset + text;
+
+    writeln!(
+        out,
+        "{:<6}{:<16}{:>8}  file offset",
+        "name", "address", "size"
+    )
+    .unwrap();
+    for (name, start, size, offset) in [
+        ("text", 0, text, Some(text_offset)),
+        ("data", 0, data, Some(data_offset)),
+        ("bss", data, bss, None),
+        ("syms", 0, header.syms, Some(data_offset + data)),
+    ] {
+        let range = match name {
+            "syms" => String::new(),
+            _ => format!("0x{start:04x}..0x{:04x}", start + size),
+        };
+        let offset = offset.map_or(String::new(), |o| format!("0x{o:04x}"));
+        let line = format!("{name:<6}{range:<16}{size:>8}  {offset}");
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    writeln!(
+        out,
+        "total {:#x} bytes of data, bss, heap and stack",
+        header.total
+    )
+    .unwrap();
+
+    let symbols = bm.get_symbols();
+    let view = DataView::new(bm.get_data(), bss as u16, &symbols);
+    writeln!(out, "\n{}", view.render().trim_end()).unwrap();
+    out
+}
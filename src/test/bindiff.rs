@@ -0,0 +1,78 @@
+use super::link_src;
+use crate::bindiff::{bindiff, Binary};
+use crate::patch::{Code, Patch};
+use std::fs;
+
+fn binary(path: &str) -> Binary {
+    Binary::new(fs::read(path).unwrap())
+}
+
+// シンボルのない実行ファイル
+fn stripped(src: &str) -> Binary {
+    Binary::new(link_src(src, &["--no-symbols"]))
+}
+
+#[test]
+fn bindiff_identical() {
+    assert_eq!(bindiff(&binary("bin/1c"), &binary("bin/1c")), "");
+}
+
+#[test]
+fn bindiff_patched() {
+    let original = fs::read("bin/1c").unwrap();
+    let mut patched = original.clone();
+    let patch = Patch {
+        address: 0x17,
+        code: Code::Asm("jmp 0028".to_owned()),
+        size: None,
+    };
+    patch.apply(&mut patched).unwrap();
+
+    let out = bindiff(&Binary::new(original), &Binary::new(patched));
+    // ヘッダは同じで，変わった関数だけが出る．飛び先は関数からの位置
+    assert!(
+        out.starts_with("function crtso changed (-1 +1)\n@@ "),
+        "{out}"
+    );
+    assert!(out.contains("\n-jnb crtso+0x28\n+jmp short crtso+0x28\n"));
+    assert_eq!(out.matches("function ").count(), 1);
+}
+
+#[test]
+fn bindiff_functions() {
+    let out = bindiff(&binary("bin/1c"), &binary("bin/2c"));
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines[..4],
+        [
+            "header text: 0x0140 -> 0x0150 (+16)",
+            "header data: 0x0014 -> 0x000c (-8)",
+            "header syms: 0x02c0 -> 0x02d0 (+16)",
+            "data segment contents differ",
+        ]
+    );
+    // 関数が後ろにずれても，呼び出しは名前で比べるので変わらない
+    assert!(lines.contains(&"function _main changed (-7 +3)"));
+    assert!(lines.contains(&"-call _write"));
+    assert!(lines.contains(&"+call _putchar"));
+    assert!(lines.contains(&" jmp .cret"));
+
+    // 2cにしかない関数は最後に追加として出る
+    let last = lines
+        .iter()
+        .rposition(|l| l.starts_with("function "))
+        .unwrap();
+    assert_eq!(lines[last], "function _putchar added (-0 +13)");
+}
+
+#[test]
+fn bindiff_without_symbols() {
+    let a = stripped("mov ax, 1\njmp .end\nnop\n.end: ret\n");
+    let b = stripped("mov ax, 1\nmov bx, 2\njmp .end\nnop\n.end: ret\n");
+
+    let out = bindiff(&a, &b);
+    assert!(out.starts_with("header text: "), "{out}");
+    assert!(out.contains("function text changed (-0 +1)\n"));
+    // 飛び先は命令からの相対位置なので，ずれても同じ行になる
+    assert!(out.contains("\n mov ax, 0001\n+mov bx, 0002\n jmp short .+0x3\n"));
+}
@@ -0,0 +1,49 @@
+use super::run_captured;
+use crate::arch::calls::{format_types, Type};
+use std::fs;
+
+fn call_trace(args: &[&str], words: usize) -> Vec<String> {
+    let bytes = fs::read(args[0]).unwrap();
+    let (_, _, out) = run_captured(bytes, args, |config, w| {
+        config.call_trace = Some(w);
+        config.call_args = words;
+    });
+    let out = String::from_utf8(out).unwrap();
+    out.lines().map(str::to_owned).collect()
+}
+
+#[test]
+fn calls_format_types() {
+    use Type::*;
+    assert_eq!(
+        format_types(b"%d %-5.2s %ld%% %c %*x %p", false),
+        [Int, Str, Long, Char, Int, Unsigned, Ptr]
+    );
+    assert_eq!(format_types(b"%d %s", true), [Ptr, Ptr]);
+    assert!(format_types(b"100%%\n", false).is_empty());
+}
+
+#[test]
+fn calls_trace() {
+    let lines = call_trace(&["bin/5c", "a", "b"], 3);
+    assert_eq!(lines[0], "_main(0x0003, 0xffda, 0xffe2)");
+    // 書式の%sは文字列として表示する
+    let i = lines
+        .iter()
+        .position(|l| l == "  _printf(\"argv[%d]=%s\\n\", 1, \"a\")")
+        .unwrap();
+    assert_eq!(lines[i + 1], "    __doprnt(0x0004, 0xffc8, 0x0024)");
+    // 戻り値は呼び出しと同じ深さに書く
+    let ret = lines[i + 1..]
+        .iter()
+        .find(|l| !l.starts_with("    "))
+        .unwrap();
+    assert_eq!(ret, "  _printf = 10");
+    assert!(lines.contains(&"_main = 0x000a".to_owned()));
+
+    let lines = call_trace(&["bin/3c"], 1);
+    assert_eq!(lines[0], "_main(0x0001)");
+    assert!(lines.contains(&"        _malloc(1024)".to_owned()));
+    assert!(lines.contains(&"        _malloc = 0x0276".to_owned()));
+    assert!(lines.contains(&"_exit(6)".to_owned()));
+}
@@ -0,0 +1,90 @@
+use crate::arch::assemble::assemble;
+use crate::arch::bin::BinaryManager;
+use crate::arch::function::{Edge, EdgeKind};
+use crate::arch::symbol::SymbolTable;
+use crate::cfg::Cfg;
+use std::fs;
+
+fn edge(from: u16, to: Option<u16>, kind: EdgeKind) -> Edge {
+    Edge { from, to, kind }
+}
+
+#[test]
+fn cfg_blocks_and_edges() {
+    let program = assemble(
+        "       call sub\n\
+                hlt\n\
+         sub:   mov cx, 3\n\
+         .loop: dec cx\n\
+                jne .loop\n\
+                jmp [bx]\n\
+                ret",
+        0,
+    )
+    .unwrap();
+    let symbols = SymbolTable::default();
+    let cfg = Cfg::build(&program.text, &symbols, 0, None).unwrap();
+
+    let names: Vec<&str> = cfg.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["L_0000", "L_0004"]);
+
+    let main = &cfg.functions[0];
+    assert_eq!(main.blocks.len(), 1);
+    assert_eq!(main.edges, [edge(0, Some(4), EdgeKind::Call)]);
+
+    // jmp [bx]の後のretは辿れない
+    let sub = &cfg.functions[1];
+    let blocks: Vec<(u16, u16)> = sub.blocks.iter().map(|b| (b.start, b.end())).collect();
+    assert_eq!(blocks, [(0x04, 0x07), (0x07, 0x0a), (0x0a, 0x0c)]);
+    assert_eq!(
+        sub.edges,
+        [
+            edge(0x04, Some(0x07), EdgeKind::Fallthrough),
+            edge(0x07, Some(0x07), EdgeKind::Conditional),
+            edge(0x07, Some(0x0a), EdgeKind::Fallthrough),
+            edge(0x0a, None, EdgeKind::Unconditional),
+        ]
+    );
+
+    let cfg = Cfg::build(&program.text, &symbols, 0, Some("L_0004")).unwrap();
+    let dot = cfg.to_dot();
+    assert!(dot.contains("    \"0007\" -> \"0007\" [label=\"conditional\", color=blue];\n"));
+    assert!(dot.contains("    \"000a\" -> \"(indirect)\" [label=\"unconditional\"];\n"));
+    assert!(dot.contains(
+        "        \"0007\" [label=\"L_0007:\\l0007: 49            dec cx\\l0008: 75fd          jne L_0007\\l\"];\n"
+    ));
+
+    assert!(Cfg::build(&program.text, &symbols, 0, Some("_main")).is_err());
+}
+
+#[test]
+fn cfg_tail_call_and_return() {
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let symbols = bm.get_symbols();
+    let cfg = Cfg::build(bm.get_text(), &symbols, 0, Some("_exit")).unwrap();
+    let exit = &cfg.functions[0];
+
+    // 他の関数へのjmpは関数の外への辺になる
+    assert_eq!(
+        exit.edges,
+        [
+            edge(0x6b, Some(0x51), EdgeKind::Call),
+            edge(0x6b, Some(0x7e), EdgeKind::Conditional),
+            edge(0x6b, Some(0x78), EdgeKind::Fallthrough),
+            edge(0x78, None, EdgeKind::Call),
+            edge(0x78, Some(0x7e), EdgeKind::Fallthrough),
+            edge(0x7e, Some(0x88), EdgeKind::Call),
+            edge(0x7e, Some(0x135), EdgeKind::Unconditional),
+        ]
+    );
+    assert!(cfg.to_dot().contains("\"007e\" -> \".cret\""));
+
+    let cfg = Cfg::build(bm.get_text(), &symbols, 0, Some(".cret")).unwrap();
+    assert_eq!(
+        cfg.functions[0].edges,
+        [edge(0x135, None, EdgeKind::Return)]
+    );
+    let json = cfg.to_json().to_string();
+    assert!(json.starts_with("{\"functions\":[{\"name\":\".cret\",\"start\":309,"));
+    assert!(json.ends_with("\"edges\":[{\"from\":309,\"to\":null,\"kind\":\"return\"}]}]}"));
+}
@@ -0,0 +1,317 @@
+use crate::arch::bin::BinaryManager;
+use crate::arch::cycles::Cpu;
+use crate::arch::trace::Format;
+use crate::cli::{parse, Command};
+use std::fs;
+use std::time::Duration;
+
+fn args(line: &str) -> Vec<String> {
+    std::iter::once("minix_vm")
+        .chain(line.split_whitespace())
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn cli_legacy_flags() {
+    let cli = parse(&args("-m ./bin/5c hoge fuga")).unwrap();
+    assert_eq!(cli.command, Command::Trace);
+    assert_eq!(cli.file, "./bin/5c");
+    assert_eq!(cli.args, ["./bin/5c", "hoge", "fuga"]);
+
+    let cli = parse(&args("-d ./bin/1c")).unwrap();
+    assert_eq!(cli.command, Command::Disasm);
+
+    let cli = parse(&args("./bin/1c -m")).unwrap();
+    assert_eq!(cli.command, Command::Run);
+    assert_eq!(cli.args, ["./bin/1c", "-m"]);
+}
+
+#[test]
+fn cli_subcommand_and_options() {
+    let cli = parse(&args(
+        "trace --trace-file=out.txt --max-steps 10 --env A=1 --env B=2 --quiet ./bin/1c x",
+    ))
+    .unwrap();
+
+    assert_eq!(cli.command, Command::Trace);
+    assert_eq!(cli.trace_file.as_deref(), Some("out.txt"));
+    assert_eq!(cli.max_steps, Some(10));
+    assert_eq!(cli.env, ["A=1", "B=2"]);
+    assert!(cli.quiet);
+    assert_eq!(cli.args, ["./bin/1c", "x"]);
+
+    let cli = parse(&args("disasm --labels --recursive ./bin/1c")).unwrap();
+    assert_eq!(cli.command, Command::Disasm);
+    assert!(cli.labels);
+    assert!(cli.recursive);
+
+    let cli = parse(&args("-d --syntax=nasm ./bin/1c")).unwrap();
+    assert_eq!(cli.syntax.as_deref(), Some("nasm"));
+    assert!(parse(&args("-d --syntax intel ./bin/1c")).is_err());
+
+    let cli = parse(&args(
+        "disasm --raw boot.bin --base 0x7c00 --start=0x7c3e --end 32320",
+    ))
+    .unwrap();
+    assert_eq!(cli.file, "boot.bin");
+    assert!(cli.raw);
+    assert_eq!(cli.base, Some(0x7c00));
+    assert_eq!(cli.start, Some(0x7c3e));
+    assert_eq!(cli.end, Some(0x7e40));
+
+    let cli = parse(&args("disasm --hex 31ed89e3")).unwrap();
+    assert_eq!(cli.hex.as_deref(), Some("31ed89e3"));
+    assert!(parse(&args("disasm --hex 31ed ./bin/1c")).is_err());
+    assert!(parse(&args("disasm --base 0x10000 ./bin/1c")).is_err());
+}
+
+#[test]
+fn cli_limits() {
+    let cli = parse(&args(
+        "--max-instructions 1000 --timeout=500ms --max-output-bytes 64 ./bin/1c",
+    ))
+    .unwrap();
+
+    assert_eq!(cli.max_instructions, Some(1000));
+    assert_eq!(cli.timeout, Some(Duration::from_millis(500)));
+    assert_eq!(cli.max_output_bytes, Some(64));
+
+    let cli = parse(&args("--timeout 2.5 ./bin/1c")).unwrap();
+    assert_eq!(cli.timeout, Some(Duration::from_millis(2500)));
+
+    let cli = parse(&args("--timeout 3s ./bin/1c")).unwrap();
+    assert_eq!(cli.timeout, Some(Duration::from_secs(3)));
+
+    assert!(parse(&args("--timeout soon ./bin/1c")).is_err());
+    assert!(parse(&args("--timeout -1 ./bin/1c")).is_err());
+    assert!(parse(&args("--max-output-bytes -1 ./bin/1c")).is_err());
+}
+
+#[test]
+fn cli_grade() {
+    let cli = parse(&args(
+        "grade spec/bin.toml --jobs 4 --junit out.xml --json=out.json",
+    ))
+    .unwrap();
+
+    assert_eq!(cli.command, Command::Grade);
+    assert_eq!(cli.file, "spec/bin.toml");
+    assert_eq!(cli.jobs, Some(4));
+    assert_eq!(cli.junit.as_deref(), Some("out.xml"));
+    assert_eq!(cli.json.as_deref(), Some("out.json"));
+
+    assert!(parse(&args("grade")).is_err());
+    assert!(parse(&args("grade a.toml b.toml")).is_err());
+}
+
+#[test]
+fn cli_golden() {
+    let cli = parse(&args("golden --bless")).unwrap();
+    assert_eq!(cli.command, Command::Golden);
+    assert_eq!(cli.file, ".");
+    assert!(cli.bless);
+
+    let cli = parse(&args("golden ./fixtures")).unwrap();
+    assert_eq!(cli.file, "./fixtures");
+    assert!(!cli.bless);
+}
+
+#[test]
+fn cli_link() {
+    let cli = parse(&args(
+        "link hello.s -o hello --entry _main --stack 0x400 --bss=32 --no-symbols",
+    ))
+    .unwrap();
+
+    assert_eq!(cli.command, Command::Link);
+    assert_eq!(cli.file, "hello.s");
+    assert_eq!(cli.output.as_deref(), Some("hello"));
+    assert_eq!(cli.entry.as_deref(), Some("_main"));
+    assert_eq!(cli.stack, Some(0x400));
+    assert_eq!(cli.bss, Some(32));
+    assert!(cli.no_symbols);
+
+    assert!(parse(&args("link")).is_err());
+    assert!(parse(&args("link a.s --stack 4k")).is_err());
+}
+
+#[test]
+fn cli_cfg() {
+    let cli = parse(&args("cfg ./bin/1c --function _main --format=json")).unwrap();
+    assert_eq!(cli.command, Command::Cfg);
+    assert_eq!(cli.file, "./bin/1c");
+    assert_eq!(cli.function.as_deref(), Some("_main"));
+    assert_eq!(cli.format.as_deref(), Some("json"));
+
+    assert!(parse(&args("cfg")).is_err());
+    assert!(parse(&args("cfg ./bin/1c --format xml")).is_err());
+}
+
+#[test]
+fn cli_sections() {
+    let cli = parse(&args("sections ./bin/3c")).unwrap();
+    assert_eq!(cli.command, Command::Sections);
+    assert_eq!(cli.file, "./bin/3c");
+    assert!(parse(&args("sections")).is_err());
+
+    let cli = parse(&args("disasm ./bin/3c --data")).unwrap();
+    assert!(cli.data);
+}
+
+#[test]
+fn cli_patch() {
+    let cli = parse(&args("patch ./bin/1c 0x0017 jmp -o out --size 3")).unwrap();
+    assert_eq!(cli.command, Command::Patch);
+    assert_eq!(cli.file, "./bin/1c");
+    assert_eq!(cli.args, ["0x0017", "jmp"]);
+    assert_eq!(cli.output.as_deref(), Some("out"));
+    assert_eq!(cli.size, Some(3));
+
+    let cli = parse(&args("patch --patches list.json ./bin/1c")).unwrap();
+    assert_eq!(cli.patches.as_deref(), Some("list.json"));
+    assert!(cli.args.is_empty());
+}
+
+#[test]
+fn cli_bindiff() {
+    let cli = parse(&args("bindiff ./bin/1c ./bin/2c")).unwrap();
+    assert_eq!(cli.command, Command::Bindiff);
+    assert_eq!(cli.file, "./bin/1c");
+    assert_eq!(cli.args, ["./bin/2c"]);
+}
+
+#[test]
+fn cli_stats() {
+    let cli = parse(&args("--stats ./bin/1c")).unwrap();
+    assert_eq!(cli.stats.as_deref(), Some("table"));
+    assert!(cli.vm_config().unwrap().stats);
+
+    let cli = parse(&args("run --stats=json ./bin/1c --stats")).unwrap();
+    assert_eq!(cli.stats.as_deref(), Some("json"));
+    assert_eq!(cli.args, ["./bin/1c", "--stats"]);
+
+    assert!(parse(&args("--stats=csv ./bin/1c")).is_err());
+    assert!(!parse(&args("./bin/1c")).unwrap().vm_config().unwrap().stats);
+
+    let cli = parse(&args("--profile --report-file prof.txt ./bin/1c")).unwrap();
+    assert_eq!(cli.profile.as_deref(), Some("flat"));
+    assert_eq!(cli.report_file.as_deref(), Some("prof.txt"));
+    assert!(cli.vm_config().unwrap().profile);
+    let cli = parse(&args("--profile=folded ./bin/1c")).unwrap();
+    assert_eq!(cli.profile.as_deref(), Some("folded"));
+    assert!(parse(&args("--profile=json ./bin/1c")).is_err());
+
+    let cli = parse(&args("--cycles ./bin/1c")).unwrap();
+    assert_eq!(cli.vm_config().unwrap().cycles, Some(Cpu::I8086));
+    let cli = parse(&args("--cycles=8088 ./bin/1c")).unwrap();
+    assert_eq!(cli.cycles, Some(Cpu::I8088));
+    assert!(parse(&args("--cycles=286 ./bin/1c")).is_err());
+
+    let cli = parse(&args("--coverage 1c.info ./bin/1c")).unwrap();
+    assert_eq!(cli.coverage.as_deref(), Some("1c.info"));
+    assert!(cli.vm_config().unwrap().coverage);
+    let cli = parse(&args("disasm --coverage 1c.info ./bin/1c")).unwrap();
+    assert_eq!(cli.coverage.as_deref(), Some("1c.info"));
+    assert!(parse(&args("./bin/1c --coverage"))
+        .unwrap()
+        .coverage
+        .is_none());
+
+    let cli = parse(&args("--mem-heatmap --mem-trace=/dev/null ./bin/1c")).unwrap();
+    assert_eq!(cli.mem_trace.as_deref(), Some("/dev/null"));
+    let config = cli.vm_config().unwrap();
+    assert!(config.mem_heatmap && config.mem_trace.is_some());
+    assert!(!cli.is_tracing());
+
+    let cli = parse(&args("--call-trace calls.txt --call-args 2 ./bin/1c")).unwrap();
+    assert_eq!(cli.call_trace.as_deref(), Some("calls.txt"));
+    assert_eq!(cli.call_args, Some(2));
+    assert!(parse(&args("--call-args many ./bin/1c")).is_err());
+
+    let cli = parse(&args("--stack-usage ./bin/1c")).unwrap();
+    assert!(cli.vm_config().unwrap().stack_usage);
+    assert!(!cli.is_tracing());
+}
+
+#[test]
+fn cli_errors() {
+    assert!(parse(&args("")).is_err());
+    assert!(parse(&args("run --max-steps")).is_err());
+    assert!(parse(&args("--env PATH ./bin/1c")).is_err());
+    assert!(parse(&args("--unknown ./bin/1c")).is_err());
+    assert_eq!(parse(&args("--help")).unwrap().command, Command::Help);
+}
+
+#[test]
+fn cli_trace_format() {
+    let cli = parse(&args("--trace-format=jsonl ./bin/1c")).unwrap();
+    assert_eq!(cli.command, Command::Run);
+    assert!(cli.is_tracing());
+    let config = cli.vm_config().unwrap();
+    assert_eq!(config.trace_format, Format::Jsonl);
+    assert!(config.trace.is_some());
+
+    let cli = parse(&args("trace --trace-format bin ./bin/1c")).unwrap();
+    assert_eq!(cli.trace_format.as_deref(), Some("bin"));
+    assert!(parse(&args("--trace-format=xml ./bin/1c")).is_err());
+    assert_eq!(
+        parse(&args("trace ./bin/1c"))
+            .unwrap()
+            .vm_config()
+            .unwrap()
+            .trace_format,
+        Format::Text
+    );
+
+    let cli = parse(&args("trace-dump 1c.bin --trace-format jsonl")).unwrap();
+    assert_eq!(cli.command, Command::TraceDump);
+    assert_eq!(cli.file, "1c.bin");
+    assert_eq!(cli.trace_format.as_deref(), Some("jsonl"));
+    assert!(parse(&args("trace-dump")).is_err());
+    // trace-dumpはbinを書かない
+    assert!(parse(&args("trace-dump 1c.bin --trace-format bin")).is_err());
+
+    let cli = parse(&args("--trace-compress ./bin/3c")).unwrap();
+    assert!(cli.is_tracing());
+    assert!(cli.vm_config().unwrap().trace_compress);
+    let cli = parse(&args("trace-dump 3c.bin --expand")).unwrap();
+    assert!(cli.expand && !cli.trace_compress);
+}
+
+#[test]
+fn cli_trace_filter() {
+    let cli = parse(&args(
+        "--trace-from _main --trace-until=0x48 --trace-range 0x10-0x100 --trace-steps ..20 --trace-only-calls ./bin/1c",
+    ))
+    .unwrap();
+    assert!(cli.is_tracing());
+    assert_eq!(cli.trace_range, Some((0x10, 0x100)));
+    assert_eq!(cli.trace_steps, Some((1, 20)));
+
+    let symbols = BinaryManager::new(fs::read("bin/1c").unwrap()).get_symbols();
+    let filter = cli.trace_filter(&symbols).unwrap();
+    assert_eq!(filter.from, Some(0x0039));
+    assert_eq!(filter.until, Some(0x0048));
+    assert!(filter.only_calls && !filter.only_syscalls);
+
+    let cli = parse(&args("--trace-steps 1000.. ./bin/1c")).unwrap();
+    assert_eq!(cli.trace_steps, Some((1000, usize::MAX)));
+    assert!(parse(&args("--trace-steps 10 ./bin/1c")).is_err());
+    assert!(parse(&args("--trace-range 0x10 ./bin/1c")).is_err());
+    // 逆順の範囲
+    assert_eq!(
+        parse(&args("--trace-range 0x200-0x100 ./bin/1c"))
+            .err()
+            .unwrap(),
+        "invalid value for '--trace-range': 0x200-0x100 (expected <start>-<end>)"
+    );
+    assert!(parse(&args("--trace-steps 5..3 ./bin/1c")).is_err());
+    let cli = parse(&args("--trace-from _nope ./bin/1c")).unwrap();
+    assert!(cli.trace_filter(&symbols).is_err());
+    assert!(parse(&args("./bin/1c"))
+        .unwrap()
+        .trace_filter(&symbols)
+        .unwrap()
+        .is_empty());
+}
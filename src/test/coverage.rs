@@ -0,0 +1,94 @@
+use super::run_vm;
+use crate::arch::bin::BinaryManager;
+use crate::arch::coverage::{parse_lcov, same_file, split_lcov, Coverage};
+use crate::arch::decode::disassemble;
+use crate::arch::vm::Config;
+use std::fs;
+
+fn run(path: &str) -> Coverage {
+    let bytes = fs::read(path).unwrap();
+    let config = Config {
+        output: None,
+        coverage: true,
+        ..Config::default()
+    };
+    let (vm, _) = run_vm(bytes, &["1c"], config);
+    vm.coverage().unwrap().clone()
+}
+
+#[test]
+fn coverage_run() {
+    let coverage = run("bin/1c");
+
+    assert_eq!(coverage.hits(0x0000), 1);
+    // mainから戻らずexitするのでhltは実行しない
+    assert_eq!(coverage.hits(0x0038), 0);
+    // jnb 0028は分岐しない，__callsのjlは分岐する
+    assert_eq!(coverage.branches[&0x0017], (0, 1));
+    assert!(coverage.branches.values().any(|&(yes, _)| yes > 0));
+    // 条件分岐以外は記録しない
+    assert!(!coverage.branches.contains_key(&0x0031));
+}
+
+#[test]
+fn coverage_lcov() {
+    let coverage = run("bin/1c");
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let code = disassemble(bm.get_text(), 0);
+    let lcov = coverage.to_lcov("bin/1c", &code, &bm.get_symbols());
+    // 直線的な逆アセンブルで命令の先頭にならないアドレスも実行していれば含める
+    let lines = code
+        .iter()
+        .map(|a| a.address)
+        .chain(coverage.hits.keys().copied())
+        .collect::<std::collections::BTreeSet<_>>();
+
+    assert!(lcov.starts_with("TN:\nSF:bin/1c\nFN:0,crtso\nFN:57,_main\n"));
+    assert!(lcov.contains("\nFNDA:1,_main\n"));
+    assert!(lcov.contains("\nFNDA:0,__send\n"));
+    assert!(lcov.contains("\nBRDA:23,0,0,0\nBRDA:23,0,1,1\n"));
+    assert!(lcov.contains("\nDA:56,0\n"));
+    assert!(lcov.contains(&format!("\nLF:{}\n", lines.len())));
+    assert!(lcov.ends_with("end_of_record\n"));
+
+    // 読み直すと同じになり，足し合わせると回数が倍になる
+    let records = parse_lcov(&lcov).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0], ("bin/1c".to_owned(), coverage.clone()));
+
+    let mut merged = records[0].1.clone();
+    merged.merge(&coverage);
+    assert_eq!(merged.hits(0), 2);
+    assert_eq!(merged.branches[&0x0017], (0, 2));
+
+    assert!(parse_lcov("SF:a\nDA:1\n").is_err());
+}
+
+#[test]
+fn coverage_comments() {
+    let coverage = run("bin/1c");
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let code = disassemble(bm.get_text(), 0);
+    let comments = coverage.comments(&code.iter().collect::<Vec<_>>());
+
+    assert_eq!(comments[&0x0000], "1 hits");
+    assert_eq!(comments[&0x0017], "1 hits, taken 0, not taken 1");
+    assert_eq!(comments[&0x0038], "never executed");
+}
+
+#[test]
+fn coverage_records() {
+    // 他のツールが書いたレコードもそのままの形で取り出す
+    let src = "TN:\nSF:bin/1c\nDA:0,1\nend_of_record\nTN:x\nSF:lib.c\nLH:1\nend_of_record\n";
+    assert_eq!(
+        split_lcov(src),
+        [
+            ("bin/1c", "TN:\nSF:bin/1c\nDA:0,1\nend_of_record\n"),
+            ("lib.c", "TN:x\nSF:lib.c\nLH:1\nend_of_record\n")
+        ]
+    );
+    assert_eq!(split_lcov("SF:a\nDA:0,1"), [("a", "SF:a\nDA:0,1")]);
+
+    assert!(same_file("./bin/1c", "bin/1c"));
+    assert!(!same_file("bin/2c", "bin/1c"));
+}
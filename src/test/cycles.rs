@@ -0,0 +1,94 @@
+use super::run_vm;
+use crate::arch::cycles::{cycles, ea_cycles, Cpu};
+use crate::arch::decode::disassemble;
+use crate::arch::operand::{Disp, EA};
+use crate::arch::vm::Config;
+use crate::disasm::parse_hex;
+use std::fs;
+
+fn clocks(cpu: Cpu, hex: &str, taken: bool, count: u16) -> u32 {
+    let asm = disassemble(&parse_hex(hex).unwrap(), 0).remove(0);
+    cycles(cpu, &asm, taken, count)
+}
+
+#[test]
+fn cycles_ea() {
+    assert_eq!(ea_cycles(&EA::DispOnly(Disp(0x10))), 6);
+    assert_eq!(ea_cycles(&EA::Bx(Disp(0))), 5);
+    assert_eq!(ea_cycles(&EA::Bp(Disp(4))), 9);
+    assert_eq!(ea_cycles(&EA::BxSi(Disp(0))), 7);
+    assert_eq!(ea_cycles(&EA::BpSi(Disp(0))), 8);
+    assert_eq!(ea_cycles(&EA::BpDi(Disp(-2))), 11);
+    assert_eq!(ea_cycles(&EA::BxDi(Disp(6))), 12);
+}
+
+#[test]
+fn cycles_8086() {
+    let table = [
+        ("89c3", 2),    // mov bx, ax
+        ("8b07", 13),   // mov ax, [bx]
+        ("a11000", 10), // mov ax, [0010]
+        ("8b4206", 20), // mov ax, [bp+si+6]
+        ("01c1", 3),    // add cx, ax
+        ("0107", 21),   // add [bx], ax
+        ("50", 11),     // push ax
+        ("e80000", 19), // call
+        ("c3", 8),      // ret
+        ("cd20", 51),   // int 20
+        ("f7e3", 118),  // mul bx
+        ("8d5702", 11), // lea dx, [bx+2]
+    ];
+    for (hex, expected) in table {
+        assert_eq!(clocks(Cpu::I8086, hex, false, 0), expected, "{hex}");
+    }
+
+    // 条件分岐は分岐したかどうかで異なる
+    assert_eq!(clocks(Cpu::I8086, "730f", true, 0), 16);
+    assert_eq!(clocks(Cpu::I8086, "730f", false, 0), 4);
+    // clによるシフトとrepは回数に比例する
+    assert_eq!(clocks(Cpu::I8086, "d3e0", false, 3), 20);
+    assert_eq!(clocks(Cpu::I8086, "d1e0", false, 0), 2);
+    assert_eq!(clocks(Cpu::I8086, "f3a4", false, 4), 9 + 17 * 4);
+}
+
+#[test]
+fn cycles_8088() {
+    // ワードの転送ごとに4クロック多い．バイトの転送は同じ
+    assert_eq!(clocks(Cpu::I8088, "8b07", false, 0), 13 + 4);
+    assert_eq!(clocks(Cpu::I8088, "0107", false, 0), 21 + 8);
+    assert_eq!(clocks(Cpu::I8088, "0007", false, 0), 21);
+    assert_eq!(clocks(Cpu::I8088, "01c1", false, 0), 3);
+    assert_eq!(clocks(Cpu::I8088, "50", false, 0), 15);
+    assert_eq!(clocks(Cpu::I8088, "cd20", false, 0), 51 + 20);
+}
+
+#[test]
+fn cycles_vm() {
+    let bytes = fs::read("bin/1c").unwrap();
+    let results: Vec<(u64, u64, u64)> = [Cpu::I8086, Cpu::I8088]
+        .into_iter()
+        .map(|cpu| {
+            let config = Config {
+                output: None,
+                profile: true,
+                cycles: Some(cpu),
+                ..Config::default()
+            };
+            let (vm, _) = run_vm(bytes.clone(), &["1c"], config);
+            let profile: u64 = vm
+                .profile()
+                .unwrap()
+                .functions()
+                .iter()
+                .map(|f| f.own)
+                .sum();
+            (vm.cycles().unwrap(), profile, vm.steps() as u64)
+        })
+        .collect();
+
+    let (i8086, profile, steps) = results[0];
+    // プロファイルはクロック数で数える
+    assert_eq!(profile, i8086);
+    assert!(i8086 > steps * 2);
+    assert!(results[1].0 > i8086);
+}
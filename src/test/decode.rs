@@ -0,0 +1,103 @@
+use crate::arch::decode::{disassemble, Decoder};
+use crate::arch::opcode::Opcode;
+
+fn listing(bytes: &[u8], base: u16) -> Vec<String> {
+    Decoder::new(bytes, base)
+        .map(|asm| format!("{:?}", asm))
+        .collect()
+}
+
+#[test]
+fn decoder_over_slice() {
+    assert_eq!(
+        listing(&[0x31, 0xed, 0x89, 0xe3, 0xc3], 0),
+        [
+            "0000: 31ed          xor bp, bp",
+            "0002: 89e3          mov bx, sp",
+            "0004: c3            ret",
+        ]
+    );
+}
+
+#[test]
+fn decoder_base_address() {
+    // 分岐先は命令のアドレスから計算される
+    assert_eq!(
+        listing(&[0xeb, 0x00], 0x100),
+        ["0100: eb00          jmp short 0102"]
+    );
+
+    let mut decoder = Decoder::at(&[0x90, 0xeb, 0xfe], 0x100, 0x101);
+    assert_eq!(decoder.next().unwrap().address, 0x101);
+    assert_eq!(decoder.ip, 0x103);
+    assert!(decoder.next().is_none());
+}
+
+#[test]
+fn decoder_truncated_instruction() {
+    // 途中で終わる命令は1byteずつ(undefined)になる
+    let asm: Vec<_> = Decoder::new(&[0x89, 0xe3, 0x8b], 0).collect();
+    assert_eq!(asm.len(), 2);
+    assert_eq!(asm[1].instruction.opcode, Opcode::Undefined);
+    assert_eq!(asm[1].address, 2);
+
+    assert_eq!(disassemble(&[0x00, 0x00, 0x00], 0).len(), 2);
+    assert!(Decoder::new(&[], 0).next().is_none());
+}
+
+#[test]
+fn decoder_mov_r8_immediate() {
+    // 8bitレジスタへの即値は1byteで，次の命令を食わない
+    assert_eq!(
+        listing(&[0xb0, 0x05, 0x90], 0),
+        [
+            "0000: b005          mov al, 0005",
+            "0002: 90            xchg ax, ax"
+        ]
+    );
+}
+
+#[test]
+fn decoder_disp8_index() {
+    // [bx+si]，[bx+di]，[bp+si]でもdisp8を読み，符号を拡張する
+    assert_eq!(
+        listing(&[0x8b, 0x40, 0x05, 0x89, 0x41, 0xfe, 0x01, 0x42, 0x05], 0),
+        [
+            "0000: 8b4005        mov ax, [bx+si+5]",
+            "0003: 8941fe        mov [bx+di-2], ax",
+            "0006: 014205        add [bp+si+5], ax",
+        ]
+    );
+}
+
+#[test]
+fn decoder_lea_mod00() {
+    assert_eq!(
+        listing(&[0x8d, 0x07, 0x8d, 0x06, 0x34, 0x12], 0),
+        [
+            "0000: 8d07          lea ax, [bx]",
+            "0002: 8d063412      lea ax, [1234]"
+        ]
+    );
+}
+
+#[test]
+fn decoder_sw01_direct_address() {
+    // sw = 01は直接アドレスでも即値が2byte
+    assert_eq!(
+        listing(&[0x81, 0x06, 0x34, 0x12, 0x78, 0x56, 0x90], 0),
+        [
+            "0000: 810634127856  add [1234], 5678",
+            "0006: 90            xchg ax, ax"
+        ]
+    );
+}
+
+#[test]
+fn decoder_short_jump_overflow() {
+    // 0x7fffを超える分岐先でオーバーフローしない
+    assert_eq!(
+        listing(&[0xeb, 0x7f], 0x7ffd),
+        ["7ffd: eb7f          jmp short 807e"]
+    );
+}
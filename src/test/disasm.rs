@@ -0,0 +1,88 @@
+use crate::arch::bin::BinaryManager;
+use crate::arch::data::DataView;
+use crate::arch::decode::disassemble;
+use crate::arch::explore::linear;
+use crate::cli::parse;
+use crate::disasm::{parse_hex, strings_used, Image};
+use std::{env, fs, process};
+
+fn load(line: &str) -> Result<Image, String> {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+    let cli = parse(&args).unwrap();
+    Image::load(&cli).unwrap()?.slice(cli.start, cli.end)
+}
+
+fn lines(image: &Image) -> Vec<String> {
+    disassemble(&image.bytes, image.base)
+        .iter()
+        .map(|a| format!("{a:?}"))
+        .collect()
+}
+
+#[test]
+fn disasm_hex_and_base() {
+    assert_eq!(parse_hex("31ed 89e3").unwrap(), [0x31, 0xed, 0x89, 0xe3]);
+    assert!(parse_hex("31e").is_err());
+    assert!(parse_hex("zz").is_err());
+
+    // 相対ジャンプの飛び先は--baseからのアドレスになる
+    let image = load("minix_vm disasm --hex 31edebfc --base 0x100").unwrap();
+    assert_eq!(image.entry, 0x100);
+    assert_eq!(
+        lines(&image),
+        [
+            "0100: 31ed          xor bp, bp",
+            "0102: ebfc          jmp short 0100"
+        ]
+    );
+}
+
+#[test]
+fn disasm_range() {
+    let image = load("minix_vm disasm ./bin/1c --start 0x39 --end 0x3c").unwrap();
+    assert_eq!(image.base, 0x39);
+    assert_eq!(
+        lines(&image),
+        [
+            "0039: 55            push bp",
+            "003a: 89e5          mov bp, sp"
+        ]
+    );
+    assert!(image.symbols.function_starting_at(0x39).is_some());
+
+    // --rawではa.outのヘッダもそのまま命令として読む
+    let image = load("minix_vm disasm --raw ./bin/1c --end 2").unwrap();
+    assert_eq!(image.bytes, [0x01, 0x03]);
+
+    assert!(load("minix_vm disasm ./bin/1c --start 0x500").is_err());
+    assert!(load("minix_vm disasm ./bin/1c --start 0x10 --end 0x8").is_err());
+
+    // a.outでなければエラーにする．--rawならそのまま読む
+    let path = env::temp_dir().join(format!("minix_vm_disasm_{}", process::id()));
+    fs::write(&path, [0x90; 8]).unwrap();
+    let name = path.to_str().unwrap();
+    assert_eq!(
+        load(&format!("minix_vm disasm {name}")).err().as_deref(),
+        Some("not a MINIX a.out file")
+    );
+    assert_eq!(
+        load(&format!("minix_vm disasm --raw {name}"))
+            .unwrap()
+            .bytes,
+        [0x90; 8]
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disasm_strings_used() {
+    let bm = BinaryManager::new(fs::read("bin/3c").unwrap());
+    let symbols = bm.get_symbols();
+    let view = DataView::new(bm.get_data(), 0, &symbols);
+    let chunks = linear(disassemble(bm.get_text(), 0));
+    let comments = strings_used(&chunks, &view);
+
+    assert_eq!(comments[&0x11fd], "\"Error: Division by 0 \\n\"");
+    // 演算の即値は文字列を指していても注釈しない
+    assert!(!comments.contains_key(&0x0155));
+}
@@ -0,0 +1,180 @@
+use crate::arch::assemble::assemble;
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode::{disassemble, Decoder};
+use crate::arch::encode::encode;
+use crate::arch::opcode::Opcode;
+use std::fs;
+
+fn listing(bytes: &[u8], base: u16) -> Vec<String> {
+    disassemble(bytes, base)
+        .iter()
+        .map(|asm| format!("{:?}", asm))
+        .collect()
+}
+
+// decode(encode(x)) == x で，元のエンコードより長くならない
+fn round_trip(bytes: &[u8], address: u16) -> bool {
+    let Some(asm) = Decoder::new(bytes, address).decode() else {
+        return false;
+    };
+    if asm.instruction.opcode == Opcode::Undefined {
+        return false;
+    }
+
+    let encoded = encode(&asm.instruction, address).unwrap_or_else(|err| panic!("{asm:?}: {err}"));
+    let mut padded = encoded.clone();
+    padded.push(0x90);
+    let decoded = Decoder::new(&padded, address).decode().unwrap();
+
+    assert_eq!(decoded.instruction, asm.instruction, "{asm:?}");
+    assert_eq!(decoded.size, encoded.len(), "{asm:?}");
+    assert!(encoded.len() <= asm.size, "{asm:?} became {encoded:02x?}");
+    true
+}
+
+#[test]
+fn encode_round_trip() {
+    // xorshiftで作ったバイト列の先頭の命令
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut count = 0;
+    for _ in 0..100_000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        if round_trip(&state.to_le_bytes(), (state >> 48) as u16) {
+            count += 1;
+        }
+    }
+    assert!(count > 50_000);
+
+    for name in ["1c", "2c", "3c", "4c", "5c", "6c", "7c", "nm"] {
+        let bm = BinaryManager::new(fs::read(format!("bin/{name}")).unwrap());
+        let text = bm.get_text();
+        for asm in Decoder::new(text, 0) {
+            round_trip(&text[asm.address as usize..], asm.address);
+        }
+    }
+}
+
+#[test]
+fn encode_shortest() {
+    let program = assemble(
+        "mov bx, [bx+si+4]\n\
+         mov ax, [bp]\n\
+         mov ax, [0010]\n\
+         add sp, 4\n\
+         add ax, 1234\n\
+         cmp al, 0\n\
+         mov cl, 1f\n\
+         lea si, [0100]\n\
+         jne 0000",
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(
+        listing(&program.text, 0),
+        [
+            "0000: 8b5804        mov bx, [bx+si+4]",
+            "0003: 8b4600        mov ax, [bp]",
+            "0006: a11000        mov ax, [0010]",
+            "0009: 83c404        add sp, 4",
+            "000c: 053412        add ax, 1234",
+            "000f: 3c00          cmp al, 0",
+            "0011: b11f          mov cl, 001f",
+            "0013: 8d360001      lea si, [0100]",
+            "0017: 75e7          jne 0000",
+        ]
+    );
+}
+
+#[test]
+fn assemble_labels_and_data() {
+    let src = r#"
+        ; 前方参照はshort，遠いjmpはnearになる
+        start:  mov bx, msg
+                jmp short next
+        next:   jmp done
+                db 1, "a;b", 'c', -1
+        table:  dw start, table+2
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        done:   int 20
+        msg:    db "hi", 0
+    "#;
+    let program = assemble(src, 0x100).unwrap();
+
+    let labels: Vec<(&str, u16)> = program
+        .labels
+        .iter()
+        .map(|label| (label.name.as_str(), label.address))
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            ("start", 0x100),
+            ("next", 0x105),
+            ("table", 0x10e),
+            ("done", 0x192),
+            ("msg", 0x194)
+        ]
+    );
+    assert_eq!(
+        &program.text[..0x12],
+        [
+            0xbb, 0x94, 0x01, 0xeb, 0x00, 0xe9, 0x8a, 0x00, 0x01, b'a', b';', b'b', b'c', 0xff,
+            0x00, 0x01, 0x10, 0x01
+        ]
+    );
+    assert_eq!(&program.text[0x92..], [0xcd, 0x20, b'h', b'i', 0]);
+}
+
+#[test]
+fn assemble_listing() {
+    // 逆アセンブル結果をそのまま組み立て直すと同じ機械語になる
+    for name in ["1c", "nm"] {
+        let bm = BinaryManager::new(fs::read(format!("bin/{name}")).unwrap());
+        let text = bm.get_text();
+        let lines = listing(text, 0);
+        let program = assemble(&lines.join("\n"), 0).unwrap();
+
+        assert_eq!(program.text, text[..program.text.len()]);
+    }
+
+    // 書き換えた行だけ最短の形にエンコードし直す
+    let program = assemble(
+        "0000: 81fb1400      cmp bx, 0014\n\
+         0004: 81fb1400      cmp bx, 14",
+        0,
+    )
+    .unwrap();
+    assert_eq!(program.text, [0x81, 0xfb, 0x14, 0x00, 0x83, 0xfb, 0x14]);
+}
+
+#[test]
+fn assemble_errors() {
+    assert_eq!(
+        assemble("nop\njne far\ndb 0", 0).err().as_deref(),
+        Some("line 2: undefined label `far`")
+    );
+    assert_eq!(
+        assemble("mov ax\n", 0).err().as_deref(),
+        Some("line 1: invalid operands for `mov`")
+    );
+    assert!(assemble("a: nop\na: nop", 0).is_err());
+    assert!(assemble("xor ax, 1", 0).is_err());
+    assert!(assemble("mov ax, [ax]", 0).is_err());
+    assert!(assemble("db 100", 0).is_err());
+    assert!(assemble("frob ax", 0).is_err());
+
+    let far = format!("jne end\n{}end: ret", "nop\n".repeat(200));
+    assert!(assemble(&far, 0)
+        .err()
+        .is_some_and(|err| err.contains("out of short range")));
+}
@@ -0,0 +1,155 @@
+use crate::arch::assemble::assemble;
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode::disassemble;
+use crate::arch::explore::explore;
+use crate::arch::format::{formatter, AsmFormatter, Att, JsonLines, Legacy, Nasm};
+use crate::arch::listing::Listing;
+use crate::disasm::parse_hex;
+use std::fs;
+
+const SOURCE: &str = "push bp\n\
+                      mov bp, sp\n\
+                      mov byte [bx+si+4], ff\n\
+                      add word [bp-6], 1\n\
+                      cmp ax, -2\n\
+                      out 83, al\n\
+                      in ax, dx\n\
+                      jmp [bx]\n\
+                      call [bx+2]\n\
+                      int 20\n\
+                      cbw\n\
+                      mov ax, [0100]\n\
+                      jne 0";
+
+fn lines(fmt: &dyn AsmFormatter) -> Vec<String> {
+    let program = assemble(SOURCE, 0).unwrap();
+    disassemble(&program.text, 0)
+        .iter()
+        .map(|asm| fmt.instruction(asm, None))
+        .collect()
+}
+
+#[test]
+fn format_nasm() {
+    assert_eq!(
+        lines(&Nasm),
+        [
+            "    push bp",
+            "    mov bp, sp",
+            "    mov byte [bx+si+4], 0xff",
+            "    add word [bp-6], 1",
+            "    cmp ax, -2",
+            "    out 0x83, al",
+            "    in ax, dx",
+            "    jmp [bx]",
+            "    call [bx+2]",
+            "    int 0x20",
+            "    cbw",
+            "    mov ax, [0x100]",
+            "    jne 0x0",
+        ]
+    );
+    assert_eq!(Nasm.prologue(0).unwrap(), "bits 16\ncpu 8086\norg 0x0\n");
+    assert_eq!(Nasm.label(".cret"), "L.cret:");
+}
+
+#[test]
+fn format_att() {
+    assert_eq!(
+        lines(&Att),
+        [
+            "    push %bp",
+            "    mov %sp, %bp",
+            "    movb $0xff, 4(%bx,%si)",
+            "    addw $1, -6(%bp)",
+            "    cmp $-2, %ax",
+            "    out %al, $0x83",
+            "    in (%dx), %ax",
+            "    jmp *(%bx)",
+            "    call *2(%bx)",
+            "    int $0x20",
+            "    cbtw",
+            "    mov 0x100, %ax",
+            "    jne 0x0",
+        ]
+    );
+    assert_eq!(Att.data(0, &[0, 0xff]), "    .byte 0x00, 0xff");
+}
+
+#[test]
+fn format_json() {
+    let out = lines(&JsonLines);
+    assert_eq!(
+        out[2],
+        "{\"address\":3,\"size\":4,\"bytes\":\"c64004ff\",\"mnemonic\":\"mov\",\"operands\":[\
+         {\"type\":\"memory\",\"base\":\"bx\",\"index\":\"si\",\"disp\":4,\"size\":8},\
+         {\"type\":\"immediate\",\"value\":255}],\"text\":\"mov byte [bx+si+4], ff\"}"
+    );
+    assert!(out[4].contains("{\"type\":\"immediate\",\"value\":-2}"));
+    assert!(out[12].contains("\"operands\":[{\"type\":\"address\",\"value\":0}]"));
+    assert_eq!(
+        JsonLines.data(0x13d, &[0, 0]),
+        "{\"address\":317,\"size\":2,\"bytes\":\"0000\",\"data\":true}"
+    );
+}
+
+#[test]
+fn format_listing() {
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let symbols = bm.get_symbols();
+    let entries: Vec<u16> = symbols.functions().map(|s| s.value as u16).collect();
+    let chunks = explore(bm.get_text(), 0, entries);
+    let listing = Listing::new(&chunks, &symbols, 0);
+
+    // legacyは既存の表示と同じ
+    let legacy = listing.render(&chunks, &Legacy);
+    assert!(legacy.contains("0017: 730f          jnb L_0028\n"));
+
+    let nasm = listing.render(&chunks, &Nasm);
+    assert!(nasm.contains(
+        "; function _main\n\
+         _main:              ; called from 0x0031\n\
+         \x20   push bp\n"
+    ));
+    assert!(nasm.contains("    jmp near L.cret\n"));
+    assert!(nasm.contains("\nL.cret:             ; jumped from"));
+    assert!(nasm.contains("    db 0x00, 0x00, 0x00\n"));
+
+    let att = listing.render(&chunks, &Att);
+    assert!(att.contains("_main:              # called from 0x0031\n"));
+
+    // JSONには空行やコメントを入れない
+    let json = listing.render(&chunks, formatter("json").unwrap().as_ref());
+    assert_eq!(json.lines().count(), chunks.len());
+    assert!(json.contains("\"text\":\"call 006b\",\"target\":\"_exit\"}"));
+}
+
+#[test]
+fn format_mnemonic() {
+    let asm = disassemble(&parse_hex("c6400400 eb02 7500").unwrap(), 0);
+    let names: Vec<_> = asm
+        .iter()
+        .map(|a| {
+            (
+                a.instruction.opcode.mnemonic(),
+                a.instruction.opcode.qualifier(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [("mov", Some("byte")), ("jmp", Some("short")), ("jne", None)]
+    );
+
+    // 飛び先だけをラベルにする
+    assert_eq!(asm[1].instruction.render(None), "jmp short 0008");
+    assert_eq!(asm[1].instruction.render(Some("_f")), "jmp short _f");
+    assert_eq!(
+        asm[0].instruction.render(Some("_f")),
+        asm[0].instruction.render(None)
+    );
+    assert_eq!(
+        asm[2].render(Some(".+0x2")),
+        "0006: 7500          jne .+0x2"
+    );
+}
@@ -0,0 +1,211 @@
+use crate::cli::parse;
+use crate::diff::{diff, unified, Edit};
+use crate::grade::{junit, run_all, Failure, Outcome, Spec};
+use crate::json::Json;
+use crate::toml::{self, Value};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+fn no_limits() -> crate::cli::Cli {
+    parse(&[
+        "minix_vm".to_owned(),
+        "grade".to_owned(),
+        "spec.toml".to_owned(),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn toml_subset() {
+    let table = toml::parse(
+        r#"
+        # comment
+        suite = "s"   # trailing comment
+        jobs = 0x10
+        ratio = 2.5
+        quiet = true
+        args = ["a", 'b\c',
+                "d\n"]
+        files = { "out.txt" = "expected.txt" }
+
+        [[test]]
+        name = "one"
+        stdout = """
+        line
+        """
+
+        [[test]]
+        name = "two"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(table["suite"], Value::String("s".to_owned()));
+    assert_eq!(table["jobs"], Value::Integer(16));
+    assert_eq!(table["ratio"], Value::Float(2.5));
+    assert_eq!(table["quiet"], Value::Boolean(true));
+    assert_eq!(
+        table["args"].as_array().unwrap(),
+        [
+            Value::String("a".to_owned()),
+            Value::String("b\\c".to_owned()),
+            Value::String("d\n".to_owned())
+        ]
+    );
+    assert_eq!(
+        table["files"].as_table().unwrap()["out.txt"].as_str(),
+        Some("expected.txt")
+    );
+
+    let tests = table["test"].as_array().unwrap();
+    assert_eq!(tests.len(), 2);
+    assert_eq!(
+        tests[0].as_table().unwrap()["stdout"].as_str(),
+        Some("        line\n        ")
+    );
+    assert_eq!(tests[1].as_table().unwrap()["name"].as_str(), Some("two"));
+}
+
+#[test]
+fn toml_errors() {
+    assert!(toml::parse("a = ").is_err());
+    assert!(toml::parse("a = 1\na = 2").is_err());
+    assert!(toml::parse("a = \"unterminated").is_err());
+    assert!(toml::parse("a = 1 b = 2").is_err());
+    assert_eq!(
+        toml::parse("\n\nx = [1,").unwrap_err(),
+        "line 3: expected a value"
+    );
+}
+
+#[test]
+fn diff_minimal_edits() {
+    let a = ["a", "b", "c", "d"];
+    let b = ["a", "x", "c", "d", "e"];
+
+    assert_eq!(
+        diff(&a, &b),
+        [
+            Edit::Equal(0, 0),
+            Edit::Delete(1),
+            Edit::Insert(1),
+            Edit::Equal(2, 2),
+            Edit::Equal(3, 3),
+            Edit::Insert(4)
+        ]
+    );
+    assert_eq!(diff::<&str>(&[], &[]), []);
+    assert_eq!(unified(&a, &a, 3), "");
+    assert_eq!(
+        unified(&a, &b, 1),
+        "@@ -1,4 +1,5 @@\n a\n-b\n+x\n c\n d\n+e\n"
+    );
+}
+
+#[test]
+fn json_output() {
+    let json = Json::object([
+        ("s", "a\"b\n".into()),
+        ("n", Json::from(vec![1usize, 2])),
+        ("none", Option::<i32>::None.into()),
+    ]);
+
+    assert_eq!(json.to_string(), r#"{"s":"a\"b\n","n":[1,2],"none":null}"#);
+    assert_eq!(
+        format!("{json:#}"),
+        "{\n  \"s\": \"a\\\"b\\n\",\n  \"n\": [\n    1,\n    2\n  ],\n  \"none\": null\n}"
+    );
+}
+
+#[test]
+fn grade_spec() {
+    let spec = Spec::load(Path::new("./spec/bin.toml"), &no_limits()).unwrap();
+
+    assert_eq!(spec.suite, "bin");
+    assert_eq!(spec.cases.len(), 3);
+    assert_eq!(spec.cases[1].args, ["hoge", "fuga", "piyo"]);
+    assert_eq!(spec.cases[1].timeout, Some(Duration::from_secs(5)));
+
+    let outcomes = run_all(&spec.cases, 2, |_| {});
+    for outcome in &outcomes {
+        assert!(outcome.passed(), "{} failed", outcome.name);
+    }
+}
+
+#[test]
+fn grade_failures() {
+    let src = r#"
+        binary = "bin/1c"
+
+        [[test]]
+        name = "wrong output"
+        stdout = "bye\n"
+        exit_code = 0
+
+        [[test]]
+        name = "limit"
+        max_instructions = 10
+    "#;
+    let spec = Spec::parse(src, Path::new("."), &no_limits()).unwrap();
+    let outcomes = run_all(&spec.cases, 1, |_| {});
+
+    let kinds: Vec<&str> = outcomes[0].failures.iter().map(|f| f.kind).collect();
+    assert_eq!(kinds, ["exit_code", "stdout"]);
+    assert!(outcomes[0].failures[1].diff.contains("-bye\n+hello\n"));
+
+    assert_eq!(outcomes[1].failures[0].kind, "limit");
+    assert_eq!(outcomes[1].instructions, 10);
+
+    assert!(Spec::parse("[[test]]\nbinary = 1", Path::new("."), &no_limits()).is_err());
+    assert!(Spec::parse("[[test]]\nbinry = \"x\"", Path::new("."), &no_limits()).is_err());
+}
+
+#[test]
+fn grade_stale_files() {
+    let root = env::temp_dir().join(format!("minix_vm_grade_{}", process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("expected.txt"), "old\n").unwrap();
+    // 前回の実行の出力が残っていても，今回書かなければ失敗する
+    fs::write(root.join("out.txt"), "old\n").unwrap();
+
+    let src = format!(
+        "[[test]]\nbinary = \"{}\"\nfiles = {{ \"out.txt\" = \"expected.txt\" }}\n",
+        env::current_dir().unwrap().join("bin/1c").display()
+    );
+    let spec = Spec::parse(&src, &root, &no_limits()).unwrap();
+    let outcomes = run_all(&spec.cases, 1, |_| {});
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(outcomes[0].failures.len(), 1);
+    assert!(outcomes[0].failures[0]
+        .message
+        .starts_with("file 'out.txt': "));
+}
+
+#[test]
+fn grade_junit_errors() {
+    let outcome = |name: &str, kind| Outcome {
+        name: name.to_owned(),
+        time: Duration::ZERO,
+        exit_code: None,
+        instructions: 0,
+        failures: vec![Failure {
+            kind,
+            message: format!("{kind}!"),
+            diff: String::new(),
+        }],
+    };
+    let outcomes = [
+        outcome("wrong", "stdout"),
+        outcome("panic", "error"),
+        outcome("fault", "fault"),
+    ];
+
+    let xml = junit("s", &outcomes, Duration::ZERO);
+    assert!(xml.contains(r#"tests="3" failures="1" errors="2""#));
+    assert!(xml.contains(r#"<failure type="stdout" message="stdout!">"#));
+    assert!(xml.contains(r#"<error type="fault" message="fault!"></error>"#));
+}
@@ -0,0 +1,148 @@
+use super::{link_cli as cli, link_src, run_captured};
+use crate::arch::assemble::{assemble, Section};
+use crate::arch::bin::BinaryManager;
+use crate::arch::header::AOutBuilder;
+use crate::arch::symbol::{section, Symbol, C_EXT};
+use crate::arch::vm::Halt;
+use crate::link::link;
+use std::env;
+use std::fs;
+
+const HELLO: &str = r#"
+        ; "hi\n"を書いてexit(3)する
+        .data
+msg:    db "hi", a
+wmsg:   dw 0, 4, 1, 3, 0, msg
+emsg:   dw 0, 1, 3
+        .bss
+buf:    .space 10
+
+        .text
+.hang:  jmp .hang
+_main:  mov bx, wmsg
+        int 20
+        mov bx, emsg
+        int 20
+        jmp .hang
+"#;
+
+fn run(bytes: Vec<u8>, args: &[&str]) -> (Halt, String) {
+    let (_, halt, out) = run_captured(bytes, args, |config, w| {
+        config.output = Some(w);
+        config.max_instructions = Some(1_000_000);
+    });
+    (halt, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn assemble_sections() {
+    let program = assemble(HELLO, 0).unwrap();
+
+    assert_eq!(program.text.len(), 0x0e);
+    assert_eq!(program.data.len(), 0x15);
+    assert_eq!(program.bss_size, 0x10);
+
+    let labels: Vec<(&str, Section, u16)> = program
+        .labels
+        .iter()
+        .map(|label| (label.name.as_str(), label.section, label.address))
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            ("msg", Section::Data, 0x00),
+            ("wmsg", Section::Data, 0x03),
+            ("emsg", Section::Data, 0x0f),
+            ("buf", Section::Bss, 0x15),
+            (".hang", Section::Text, 0x00),
+            ("_main", Section::Text, 0x02),
+        ]
+    );
+
+    assert!(assemble(".bss\nnop", 0)
+        .err()
+        .is_some_and(|err| err.contains(".bss")));
+}
+
+#[test]
+fn link_and_run() {
+    let bytes = link_src(HELLO, &["--entry", "_main", "--stack", "0x400"]);
+
+    let bm = BinaryManager::new(bytes.clone());
+    let header = bm.get_header().unwrap();
+    assert_eq!(
+        (header.text_size, header.data_size, header.bss_size),
+        (0x0e, 0x15, 0x10)
+    );
+    assert_eq!(header.entry_point, 2);
+    assert_eq!(header.total, 0x15 + 0x10 + 0x400);
+    assert_eq!(
+        format!("{:?}", bm.get_symbols().iter().collect::<Vec<_>>()),
+        "[00000000 D msg, 00000003 D wmsg, 0000000f D emsg, 00000015 B buf, 00000002 T _main]"
+    );
+
+    assert_eq!(run(bytes, &["hello"]), (Halt::Exit(3), "hi\n".to_owned()));
+
+    // 既定ではテキストの先頭から実行する
+    let bytes = link_src(HELLO, &["--no-symbols"]);
+    assert!(BinaryManager::new(bytes.clone()).get_symbols().is_empty());
+    assert_eq!(run(bytes, &["hello"]).0, Halt::InstructionLimit);
+}
+
+#[test]
+fn link_nm() {
+    // 組み立てた実行ファイルをVM上のnmで読める
+    let bytes = link_src(HELLO, &["--entry", "_main"]);
+    let path = env::temp_dir().join(format!("minix_vm_link_{}.out", std::process::id()));
+    fs::write(&path, bytes).unwrap();
+
+    let path = path.to_string_lossy().into_owned();
+    let nm = fs::read("bin/nm").unwrap();
+    let result = run(nm, &["nm", &path]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        result,
+        (
+            Halt::Exit(0),
+            format!(
+                "{path}:\n\
+                 00000002 T _main\n\
+                 00000015 B buf\n\
+                 0000000f D emsg\n\
+                 00000000 D msg\n\
+                 00000003 D wmsg\n"
+            )
+        )
+    );
+}
+
+#[test]
+fn builder_errors() {
+    let symbol = Symbol {
+        name: "too_long_name".to_owned(),
+        value: 0,
+        sclass: section::TEXT | C_EXT,
+        numaux: 0,
+        n_type: 0,
+    };
+    assert!(AOutBuilder::new()
+        .text(vec![0xc3])
+        .symbols(vec![symbol])
+        .build()
+        .is_err());
+    assert!(AOutBuilder::new()
+        .text(vec![0xc3])
+        .entry_point(1)
+        .build()
+        .is_err());
+    assert!(AOutBuilder::new()
+        .data(vec![0; 0x8000])
+        .bss_size(0x8000)
+        .stack_size(0x100)
+        .build()
+        .is_err());
+
+    assert!(link("jmp _start", &cli(&["--entry", "_start"])).is_err());
+    assert!(link(".data\nx: db 0\n.text\nret", &cli(&["--entry", "x"])).is_err());
+}
@@ -0,0 +1,130 @@
+use crate::arch::assemble::assemble;
+use crate::arch::bin::BinaryManager;
+use crate::arch::decode::disassemble;
+use crate::arch::explore::{explore, linear};
+use crate::arch::format::Legacy;
+use crate::arch::listing::Listing;
+use crate::arch::symbol::SymbolTable;
+use std::fs;
+
+#[test]
+fn listing_without_symbols() {
+    let program = assemble(
+        "       call sub\n\
+                hlt\n\
+         sub:   mov cx, 3\n\
+         .loop: dec cx\n\
+                jne .loop\n\
+                ret",
+        0,
+    )
+    .unwrap();
+    let asm = linear(disassemble(&program.text, 0));
+    let symbols = SymbolTable::default();
+
+    assert_eq!(
+        Listing::new(&asm, &symbols, 0).render(&asm, &Legacy),
+        "; function L_0000\n\
+         L_0000:\n\
+         0000: e80100        call L_0004\n\
+         0003: f4            hlt\n\
+         \n\
+         ; function L_0004\n\
+         L_0004:             ; called from 0x0000\n\
+         0004: b90300        mov cx, 0003\n\
+         \n\
+         L_0007:             ; jumped from 0x0008\n\
+         0007: 49            dec cx\n\
+         0008: 75fd          jne L_0007\n\
+         \n\
+         000a: c3            ret\n"
+    );
+}
+
+#[test]
+fn listing_with_symbols() {
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let asm = linear(disassemble(bm.get_text(), 0));
+    let symbols = bm.get_symbols();
+    let listing = Listing::new(&asm, &symbols, 0);
+    let out = listing.render(&asm, &Legacy);
+
+    assert!(out.contains(
+        "0013: 81fb1400      cmp bx, 0014\n\
+         0017: 730f          jnb L_0028\n\
+         \n\
+         0019: f6c301        test bl, 1\n"
+    ));
+    assert!(out.contains("L_0028:             ; jumped from 0x0017, 0x001c, 0x0022\n"));
+    assert!(out.contains(
+        "0038: f4            hlt\n\
+         \n\
+         ; function _main\n\
+         _main:              ; called from 0x0031\n\
+         0039: 55            push bp\n"
+    ));
+    assert!(out.contains("0048: e84100        call _write\n"));
+    assert!(out.contains("004e: e9e400        jmp .cret\n"));
+
+    // 命令の途中を指す飛び先にはラベルを付けない
+    let asm = linear(disassemble(&[0xeb, 0xff, 0xc3], 0));
+    let listing = Listing::new(&asm, &symbols, 0x10);
+    assert_eq!(
+        listing.line(asm[0].as_code().unwrap()),
+        "0000: ebff          jmp short 0001"
+    );
+}
+
+#[test]
+fn explore_code_and_data() {
+    let program = assemble(
+        "       jmp start\n\
+         msg:   db \"hello, world\", 0\n\
+         start: call sub\n\
+                jne bad\n\
+                ret\n\
+                db 0, 0\n\
+         sub:   ret\n\
+         bad:   db 0f\n",
+        0,
+    )
+    .unwrap();
+    let chunks = explore(&program.text, 0, [0]);
+
+    assert_eq!(
+        chunks.iter().map(|c| format!("{c:?}")).collect::<Vec<_>>(),
+        [
+            "0000: eb0d          jmp short 000f",
+            "0002: 68656c6c6f2c  db 68, 65, 6c, 6c, 6f, 2c",
+            "0008: 20776f726c64  db 20, 77, 6f, 72, 6c, 64",
+            "000e: 00            db 00",
+            "000f: e80500        call 0017",
+            "0012: 7504          jne 0018",
+            "0014: c3            ret",
+            "0015: 0000          db 00, 00",
+            "0017: c3            ret",
+            "0018: 0f            db 0f",
+        ]
+    );
+
+    // 線形に読むと関数の間の詰め物から命令の区切りがずれ，最後の(undefined)で止まる
+    let bm = BinaryManager::new(fs::read("bin/1c").unwrap());
+    let entries: Vec<u16> = bm
+        .get_symbols()
+        .functions()
+        .map(|s| s.value as u16)
+        .collect();
+    let lines: Vec<String> = explore(bm.get_text(), 0, entries)
+        .iter()
+        .map(|c| format!("{c:?}"))
+        .collect();
+    for line in [
+        "008b: 00            db 00",
+        "008c: e92100        jmp 00b0",
+        "0093: 83ec18        sub sp, 18",
+        "013d: 000000        db 00, 00, 00",
+    ] {
+        assert!(lines.iter().any(|l| l == line), "{line}");
+    }
+    assert_eq!(lines.last().unwrap(), "013d: 000000        db 00, 00, 00");
+}
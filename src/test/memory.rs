@@ -0,0 +1,57 @@
+use super::run_captured;
+use crate::arch::memory::{Layout, Region};
+use std::fs;
+
+// --mem-traceの出力と--mem-heatmapのレポート
+fn mem_trace(path: &str) -> (String, String) {
+    let bytes = fs::read(path).unwrap();
+    let (vm, _, trace) = run_captured(bytes, &[path], |config, w| {
+        config.mem_trace = Some(w);
+        config.mem_heatmap = true;
+    });
+    let report = vm.heatmap().unwrap().render(vm.layout(), &vm.symbols);
+    (String::from_utf8(trace).unwrap(), report)
+}
+
+#[test]
+fn memory_layout() {
+    let mut layout = Layout::new(0x0214, 0x0060);
+    assert_eq!(layout.region(0x0002), Region::Data);
+    assert_eq!(layout.region(0x0214), Region::Bss);
+    assert_eq!(layout.region(0x0274), Region::Stack);
+    layout.brk = 0x0a74;
+    assert_eq!(layout.region(0x0274), Region::Heap);
+    assert_eq!(layout.region(0xffe0), Region::Stack);
+}
+
+#[test]
+fn memory_trace() {
+    let (trace, report) = mem_trace("bin/1c");
+    let lines: Vec<&str> = trace.lines().collect();
+
+    // mov ax, [bx]
+    assert_eq!(lines[0], "       3 0004 r ffe0 2 0001 stack");
+    // leaはメモリを読まない
+    assert!(!lines.iter().any(|l| l.starts_with("       4 ")));
+    assert!(lines.contains(&"      17 002c w 0010 2 ffe6 data"));
+    assert!(lines.contains(&"      18 002e w ffde 2 ffe6 stack"));
+
+    assert!(
+        report.contains("\nffa0          12        16  stack  [stack]     ####################\n")
+    );
+    assert!(report.contains("\n0010           1         1  data   _environ    ##\n"));
+
+    // スタックへのアクセスの回数はトレースの行数と一致する
+    let stack = lines.iter().filter(|l| l.ends_with(" stack")).count();
+    assert_eq!(stack, 33 + 48);
+    assert!(report.contains("\n        33        48  [stack]\n"));
+}
+
+#[test]
+fn memory_heap() {
+    // mallocがbrkで広げた領域はヒープになる
+    let (trace, report) = mem_trace("bin/3c");
+    assert!(trace.contains("     297 0b8e w 0274 2 0000 heap"));
+    assert!(trace.lines().any(|l| l.ends_with(" bss")));
+    assert!(report.lines().any(|l| l.ends_with("  [heap]")));
+}
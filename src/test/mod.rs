@@ -1,221 +1,134 @@
-use std::{
-    fs::File,
-    io::{self, BufRead, BufReader},
-    path::Path,
-    process::Command,
-};
-
-#[test]
-fn dasm_1() {
-    exec_case("1c");
-}
-
-#[test]
-fn dasm_2() {
-    exec_case("2c");
-}
-
-#[test]
-fn dasm_3() {
-    exec_case("3c");
-}
-
-#[test]
-fn dasm_4() {
-    exec_case("4c");
-}
-
-#[test]
-fn dasm_5() {
-    exec_case("5c");
-}
-
-#[test]
-fn dasm_6() {
-    exec_case("6c");
-}
-
-#[test]
-fn dasm_7() {
-    exec_case("7c");
-}
-
-#[test]
-fn dasm_nm() {
-    exec_case("nm");
-}
-
-#[test]
-fn vm_1() {
-    exec_vm_case("1c");
-}
-
-#[test]
-fn vm_2() {
-    exec_vm_case("2c");
-}
-
-#[test]
-fn vm_3() {
-    exec_vm_case("3c");
-}
-
-#[test]
-fn vm_4() {
-    exec_vm_case("4c");
-}
-
-#[test]
-fn vm_5() {
-    exec_vm_case("5c");
-}
-
-#[test]
-fn vm_6() {
-    exec_vm_case("6c");
-}
-
-#[test]
-fn vm_7() {
-    exec_vm_case("7c");
-}
-
-#[test]
-fn vm_nm() {
-    exec_vm_case("nm");
-}
-
-#[test]
-fn default_1() {
-    exec_default_case("1c");
-}
-
-#[test]
-fn default_2() {
-    exec_default_case("2c");
-}
-
-#[test]
-fn default_3() {
-    exec_default_case("3c");
-}
-
-#[test]
-fn default_4() {
-    exec_default_case("4c");
-}
-
-#[test]
-fn default_5() {
-    exec_default_5c_case();
-}
-
-#[test]
-fn default_6() {
-    exec_default_case("6c");
-}
-
-#[test]
-fn default_7() {
-    exec_default_case("7c");
-}
-
-#[test]
-fn default_nm() {
-    exec_default_case("nm");
-}
-
-fn exec_case(filename: &str) {
-    exec_disassembler(filename);
-
-    let origin_asm = format!("./origin/asm/{}.txt", filename);
-    let output_asm = format!("./out/asm/{}.txt", filename);
-
-    compare_lines(&origin_asm, &output_asm);
-}
-
-fn exec_vm_case(filename: &str) {
-    exec_vm(filename);
-
-    let origin_vm = format!("./origin/vm/{}.txt", filename);
-    let output_vm = format!("./out/vm/{}.txt", filename);
-
-    compare_lines(&origin_vm, &output_vm);
-}
-
-fn exec_default_case(filename: &str) {
-    exec_default(filename);
-
-    let origin_vm = format!("./origin/default/{}.txt", filename);
-    let output_vm = format!("./out/default/{}.txt", filename);
-
-    compare_lines(&origin_vm, &output_vm);
-}
-
-fn exec_default_5c_case() {
-    let filename = "5c";
-    exec_default_5c();
-
-    let origin_vm = format!("./origin/default/{}.txt", filename);
-    let output_vm = format!("./out/default/{}.txt", filename);
-
-    compare_lines(&origin_vm, &output_vm);
-}
-
-fn exec_disassembler(filename: &str) {
-    Command::new("./src/test/shell/dasm.sh")
-        .args([&filename])
-        .output()
-        .expect("Failed to execute cargo run");
-}
-
-fn exec_vm(filename: &str) {
-    Command::new("./src/test/shell/vm.sh")
-        .args([&filename])
-        .output()
-        .expect("Failed to execute cargo run");
-}
-
-fn exec_default(filename: &str) {
-    Command::new("./src/test/shell/default.sh")
-        .args([&filename])
-        .output()
-        .expect("Failed to execute cargo run");
-}
-
-fn exec_default_5c() {
-    Command::new("./src/test/shell/default_5c.sh")
-        .output()
-        .expect("Failed to execute cargo run");
-}
-
-fn compare_lines(file1: &str, file2: &str) -> io::Result<()> {
-    let file1 = File::open(file1).unwrap();
-    let mut lines1 = BufReader::new(file1).lines();
-
-    let file2 = File::open(file2).unwrap();
-    let mut lines2 = BufReader::new(file2).lines();
-
-    let mut i = 1;
-    loop {
-        let line1 = lines1.next();
-        let line2 = lines2.next();
-
-        if line1.is_none() && line2.is_none() {
-            break;
+use crate::arch::vm::{Capture, Config, Halt, VM};
+use crate::cli::{parse, Cli};
+use crate::golden::{self, Verdict};
+use crate::link::link;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+mod bindiff;
+mod calls;
+mod cfg;
+mod cli;
+mod coverage;
+mod cycles;
+mod decode;
+mod disasm;
+mod encode;
+mod format;
+mod grade;
+mod link;
+mod listing;
+mod memory;
+mod patch;
+mod profile;
+mod sections;
+mod stack;
+mod stats;
+mod trace;
+mod vm;
+
+// 実行ファイルをconfigで停止するまで実行する
+fn run_vm(bytes: Vec<u8>, args: &[&str], config: Config) -> (VM, Halt) {
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    let mut vm = VM::with_config(bytes, &args, config).unwrap();
+    let halt = vm.run();
+    (vm, halt)
+}
+
+// setで設定の書き出し先の1つをCaptureにつないで実行し，書かれた内容を返す．
+// 標準出力はsetでつながなければ捨てる
+fn run_captured(
+    bytes: Vec<u8>,
+    args: &[&str],
+    set: impl FnOnce(&mut Config, Box<dyn Write>),
+) -> (VM, Halt, Vec<u8>) {
+    let capture = Capture::default();
+    let mut config = Config {
+        output: None,
+        ..Config::default()
+    };
+    set(&mut config, Box::new(capture.clone()));
+    let (vm, halt) = run_vm(bytes, args, config);
+    (vm, halt, capture.contents())
+}
+
+// `minix_vm link <options> test.s`のオプション
+fn link_cli(options: &[&str]) -> Cli {
+    let args: Vec<String> = ["minix_vm", "link"]
+        .iter()
+        .chain(options)
+        .chain(&["test.s"])
+        .map(|s| s.to_string())
+        .collect();
+    parse(&args).unwrap()
+}
+
+// アセンブリのソースから実行ファイルを作る
+fn link_src(src: &str, options: &[&str]) -> Vec<u8> {
+    link(src, &link_cli(options)).unwrap()
+}
+
+// bin/以下のすべての実行ファイルを，origin/{default,asm,vm}の期待する出力と比較する．
+// BLESS=1 cargo test golden でorigin/を現在の出力で作り直す
+#[test]
+fn golden() {
+    let bless = env::var_os("BLESS").is_some();
+    let results = golden::check_all(Path::new("."), bless).unwrap();
+
+    let failures: Vec<&str> = results
+        .iter()
+        .filter_map(|(_, verdict)| match verdict {
+            Verdict::Fail(report) => Some(report.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    for (case, verdict) in &results {
+        if let Verdict::Missing = verdict {
+            println!("skip {} (no {})", case.id(), case.expected.display());
         }
-
-        assert_eq!(
-            line1.unwrap_or(Ok("inconsistent".to_owned()))?,
-            line2.unwrap_or(Ok("inconsistent".to_owned()))?,
-            "line number: {i}"
-        );
-        i += 1;
     }
 
-    Ok(())
-}
-
-fn get_filename_from_path(path: &str) -> Option<&str> {
-    Path::new(path).file_name()?.to_str()
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// わざと1命令の結果を変えたトレースで，報告する位置と文脈を確かめる
+#[test]
+fn golden_divergence() {
+    let case = golden::Case {
+        name: "1c".to_owned(),
+        mode: golden::Mode::Vm,
+        binary: "bin/1c".into(),
+        args: Vec::new(),
+        expected: "origin/vm/1c.txt".into(),
+        actual: "out/vm/1c.txt".into(),
+    };
+    let expected = fs::read_to_string(&case.expected).unwrap();
+    let actual = expected.replacen(
+        "0001 ffe0 0000 ffe2 ffe0 0000 0000 0000 --Z- 0009",
+        "0001 ffe0 0000 ffe4 ffe0 0000 0000 0000 ---- 0009",
+        1,
+    );
+
+    let report = golden::first_divergence(&case, &expected, &actual).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        lines[0],
+        "vm/1c: first divergence at instruction 5 (line 6)"
+    );
+    assert_eq!(
+        lines[4],
+        "  - 0001 ffe0 0000 ffe2 ffe0 0000 0000 0000 --Z- 0009:8d4f04       lea cx, [bx+4] ;[ffe4]0000"
+    );
+    assert_eq!(
+        lines[6],
+        "  registers (expected != actual): DX ffe2 != ffe4, FLAGS --Z- != ----"
+    );
+    assert_eq!(
+        lines[7],
+        "  after executing: 0001 ffe0 0000 0000 ffe0 0000 0000 0000 --Z- 0006:8d5702       lea dx, [bx+2] ;[ffe2]ffea"
+    );
+    assert!(golden::first_divergence(&case, &expected, &expected).is_none());
 }
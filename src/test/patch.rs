@@ -0,0 +1,152 @@
+use crate::arch::bin::BinaryManager;
+use crate::json::{self, Json};
+use crate::patch::{Code, Patch};
+use std::fs;
+
+#[test]
+fn json_parse() {
+    let value = json::parse(r#" {"a": [1, -2.5, true, null], "s": "x\"\né😀", "o": {}} "#).unwrap();
+    assert_eq!(
+        value,
+        Json::object([
+            (
+                "a",
+                Json::Array(vec![
+                    Json::Int(1),
+                    Json::Float(-2.5),
+                    Json::Bool(true),
+                    Json::Null
+                ])
+            ),
+            ("s", Json::Str("x\"\né😀".to_owned())),
+            ("o", Json::Object(Vec::new())),
+        ])
+    );
+    assert_eq!(value.get("o"), Some(&Json::Object(Vec::new())));
+
+    assert!(json::parse("[1, 2").is_err());
+    assert!(json::parse("{\"a\" 1}").is_err());
+    assert!(json::parse("[1] 2").is_err());
+
+    assert_eq!(
+        json::parse(r#""\ud83d\ude00\u00e9""#).unwrap(),
+        Json::Str("😀é".to_owned())
+    );
+    // 下位サロゲートでない\u，16進数字でない\u
+    assert!(json::parse(r#""\ud83d\u0041""#).is_err());
+    assert!(json::parse(r#""\udc00""#).is_err());
+    assert!(json::parse(r#""\u+1ab""#).is_err());
+    assert!(json::parse(r#""\u12""#).is_err());
+}
+
+#[test]
+fn patch_in_place() {
+    let mut binary = fs::read("bin/1c").unwrap();
+    let original = binary.clone();
+
+    let patch = Patch {
+        address: 0x17,
+        code: Code::Asm("jmp 0028".to_owned()),
+        size: None,
+    };
+    let applied = patch.apply(&mut binary).unwrap();
+    assert_eq!(
+        format!("{:?}", applied.before),
+        "[0017: 730f          jnb 0028]"
+    );
+    assert_eq!(
+        format!("{:?}", applied.after),
+        "[0017: eb0f          jmp short 0028]"
+    );
+
+    // ヘッダ，データ，シンボルはそのまま
+    let changed: Vec<usize> = (0..binary.len())
+        .filter(|&i| binary[i] != original[i])
+        .collect();
+    assert_eq!(changed, [32 + 0x17]);
+    let count = |bytes: &[u8]| {
+        BinaryManager::new(bytes.to_vec())
+            .get_symbols()
+            .iter()
+            .count()
+    };
+    assert_eq!(count(&binary), count(&original));
+
+    // 収まらない命令は置き換えるバイト数を指定しない限り書き込まない
+    let patch = Patch {
+        address: 0x17,
+        code: Code::Asm("jmp 0100".to_owned()),
+        size: None,
+    };
+    assert!(patch.apply(&mut binary).unwrap_err().contains("3 bytes"));
+
+    let patch = Patch {
+        address: 0x13,
+        code: Code::Asm("xor bx, bx".to_owned()),
+        size: None,
+    };
+    patch.apply(&mut binary).unwrap();
+    assert_eq!(&binary[32 + 0x13..32 + 0x17], [0x31, 0xdb, 0x90, 0x90]);
+
+    // 命令の途中のアドレスは書き換えない
+    let before = binary.clone();
+    let patch = Patch {
+        address: 0x18,
+        code: Code::Bytes(vec![0x90]),
+        size: None,
+    };
+    assert_eq!(
+        patch.apply(&mut binary).unwrap_err(),
+        "0x0018 is inside the instruction at 0x0017"
+    );
+    assert_eq!(binary, before);
+}
+
+#[test]
+fn patch_list() {
+    let value = json::parse(
+        r#"[{"address": "0x17", "asm": "jmp 0028"},
+            {"address": 25, "bytes": "90", "size": 3}]"#,
+    )
+    .unwrap();
+    let patches = Patch::list_from_json(&value).unwrap();
+    assert_eq!(
+        patches,
+        [
+            Patch {
+                address: 0x17,
+                code: Code::Asm("jmp 0028".to_owned()),
+                size: None,
+            },
+            Patch {
+                address: 25,
+                code: Code::Bytes(vec![0x90]),
+                size: Some(3),
+            },
+        ]
+    );
+
+    let mut binary = fs::read("bin/1c").unwrap();
+    for patch in &patches {
+        patch.apply(&mut binary).unwrap();
+    }
+    assert_eq!(
+        &binary[32 + 0x17..32 + 0x1c],
+        [0xeb, 0x0f, 0x90, 0x90, 0x90]
+    );
+
+    let bad = json::parse(r#"[{"address": "0x17"}]"#).unwrap();
+    assert_eq!(
+        Patch::list_from_json(&bad).unwrap_err(),
+        "patch 0: missing \"asm\" or \"bytes\" string"
+    );
+    assert!(Patch::apply(
+        &Patch {
+            address: 0x1000,
+            code: Code::Bytes(vec![0x90]),
+            size: None,
+        },
+        &mut binary
+    )
+    .is_err());
+}
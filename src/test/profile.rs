@@ -0,0 +1,61 @@
+use super::run_vm;
+use crate::arch::profile::{Function, Profile};
+use crate::arch::vm::Config;
+use std::fs;
+
+fn function(name: &str, calls: usize, own: u64, total: u64) -> Function {
+    Function {
+        name: name.to_owned(),
+        calls,
+        own,
+        total,
+    }
+}
+
+#[test]
+fn profile_recursion() {
+    let mut profile = Profile::new("main".to_owned());
+    profile.count(1);
+    profile.call("f".to_owned());
+    profile.count(2);
+    profile.call("f".to_owned());
+    profile.count(3);
+    profile.ret(1);
+    profile.count(1);
+    profile.ret(1);
+    profile.count(1);
+
+    // 再帰呼び出しの命令数は呼び出し先を含む命令数に一度だけ数える
+    assert_eq!(
+        profile.functions(),
+        [function("f", 2, 6, 6), function("main", 1, 2, 8)]
+    );
+    assert_eq!(profile.folded(), "main 2\nmain;f 3\nmain;f;f 3\n");
+    assert_eq!(
+        profile.tree(),
+        "     total       self    calls  function\n\
+         \x20        8          2        1  main\n\
+         \x20        6          3        1    f\n\
+         \x20        3          3        1      f\n"
+    );
+}
+
+#[test]
+fn profile_binary() {
+    let bytes = fs::read("bin/1c").unwrap();
+    let config = Config {
+        output: None,
+        profile: true,
+        ..Config::default()
+    };
+    let (vm, _) = run_vm(bytes, &["1c"], config);
+    let profile = vm.profile().unwrap();
+    let (steps, functions, folded) = (vm.steps() as u64, profile.functions(), profile.folded());
+
+    let find = |name: &str| functions.iter().find(|f| f.name == name).unwrap();
+    assert_eq!(find("crtso").total, steps);
+    assert_eq!(functions.iter().map(|f| f.own).sum::<u64>(), steps);
+    assert_eq!(find("_write").calls, 1);
+    assert!(find("_main").total > find("_write").total);
+    assert!(folded.contains("\ncrtso;_main;_write;__syscal;__sendre "));
+}
@@ -0,0 +1,72 @@
+use super::link_src;
+use crate::arch::bin::BinaryManager;
+use crate::arch::data::DataView;
+use crate::sections::render;
+use std::fs;
+
+const PROGRAM: &str = r#"
+        .data
+count:  dw 0
+msg:    db "hi\n", 0
+table:  dw msg, _main, 1234
+        .bss
+buf:    .space 10
+tail:   .space 4
+
+        .text
+_main:  mov ax, msg
+        hlt
+"#;
+
+fn build() -> BinaryManager<std::vec::IntoIter<u8>> {
+    BinaryManager::new(link_src(PROGRAM, &["--entry", "_main"]))
+}
+
+#[test]
+fn data_view() {
+    let bm = build();
+    assert_eq!(
+        bm.get_data(),
+        [0, 0, b'h', b'i', b'\n', 0, 2, 0, 0, 0, 0x34, 0x12]
+    );
+    let bss = bm.get_bss().unwrap();
+    assert_eq!((bss.offset, bss.user_size), (0x0c, 0x14));
+
+    let symbols = bm.get_symbols();
+    let view = DataView::new(bm.get_data(), 0x14, &symbols);
+    assert_eq!(view.string_at(2).unwrap(), b"hi\n");
+    assert_eq!(view.pointer(2).as_deref(), Some("msg"));
+    assert_eq!(view.pointer(0).as_deref(), None);
+
+    assert_eq!(
+        view.render(),
+        "; data 0x0000..0x000c (12 bytes)\n\
+         count:\n\
+         0000: 00 00                    ..\n\
+         msg:\n\
+         0002: \"hi\\n\"\n\
+         table:\n\
+         0006: 02 00                    ..       ; 0x0002 -> msg\n\
+         0008: 00 00 34 12              ..4.\n\
+         \n\
+         ; bss 0x000c..0x0020 (20 bytes)\n\
+         000c: buf (16 bytes)\n\
+         001c: tail (4 bytes)\n"
+    );
+}
+
+#[test]
+fn sections_of_binary() {
+    let bm = BinaryManager::new(fs::read("bin/3c").unwrap());
+    let out = render(&bm);
+
+    assert!(out.starts_with(
+        "name  address             size  file offset\n\
+         text  0x0000..0x12c0      4800  0x0020\n\
+         data  0x0000..0x0214       532  0x12e0\n\
+         bss   0x0214..0x0274        96\n"
+    ));
+    assert!(out.contains(".Mdivz:\n0154: \"Error: Division by 0 \\n\"\n"));
+    assert!(out.contains("0128: 74 02                    t.       ; 0x0274 -> endbss\n"));
+    assert!(out.contains("0234: ___funct (64 bytes)\n"));
+}
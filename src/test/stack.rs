@@ -0,0 +1,60 @@
+use super::run_vm;
+use crate::arch::stack::{self, StackUsage, Usage};
+use crate::arch::vm::Config;
+use std::fs;
+
+#[test]
+fn stack_usage_frames() {
+    let mut stack = StackUsage::new("main".to_owned(), 0x1000);
+    stack.observe(0x0ffc, 1);
+    stack.call("f".to_owned(), 0x0ffa);
+    stack.observe(0x0ff0, 3);
+    stack.ret(1);
+    // 最初の関数からは戻らない
+    stack.ret(1);
+    stack.observe(0x0ffe, 5);
+    stack.call("f".to_owned(), 0x0ffc);
+    stack.observe(0x0ff8, 7);
+
+    assert_eq!(stack.lowest(), 0x0ff0);
+    assert_eq!(stack.chain(), ["main", "f"]);
+    let functions = stack.functions();
+    let usage = |calls, frame, total| Usage {
+        calls,
+        frame,
+        total,
+    };
+    assert_eq!(functions["main"], usage(1, 4, 16));
+    // 呼び出し先を含まないframeは自分のspだけで決まる
+    assert_eq!(functions["f"], usage(2, 12, 12));
+}
+
+#[test]
+fn stack_usage_run() {
+    let bytes = fs::read("bin/3c").unwrap();
+    let config = Config {
+        stack_usage: true,
+        output: None,
+        ..Config::default()
+    };
+    let (vm, _) = run_vm(bytes, &["bin/3c"], config);
+
+    let usage = vm.stack_usage().unwrap();
+    assert_eq!(usage.lowest(), 0xfb2e);
+    assert_eq!(
+        &usage.chain()[..4],
+        ["crtso", "_main", "_printf", "__doprnt"]
+    );
+    let functions = usage.functions();
+    assert_eq!(functions["_main"].calls, 1);
+    assert_eq!(functions["crtso"].total, 0xffe0 - 0xfb2e);
+
+    // push bpと引数のpushに戻りアドレスを加える
+    let estimates = stack::estimate(vm.ram.text(), &vm.symbols);
+    assert_eq!(estimates["_main"], 6);
+    assert_eq!(estimates["_printf"], functions["_printf"].frame);
+
+    let report = usage.render(vm.layout(), &estimates);
+    assert!(report.contains("lowest sp:  fb2e (1202 bytes)"));
+    assert!(report.contains("call chain at the lowest sp: crtso > _main > _printf"));
+}
@@ -0,0 +1,65 @@
+use super::{link_src, run_vm};
+use crate::arch::vm::Config;
+
+const LOOP: &str = r#"
+_main:  mov bx, wmsg
+        int 20
+        mov cx, 3
+.loop:  dec cx
+        jne .loop
+        mov bx, emsg
+        int 20
+
+        .data
+msg:    db "hi", a
+wmsg:   dw 0, 4, 1, 3, 0, msg
+emsg:   dw 0, 1, 0
+"#;
+
+fn binary() -> Vec<u8> {
+    link_src(LOOP, &[])
+}
+
+#[test]
+fn stats_counts() {
+    let config = Config {
+        output: None,
+        stats: true,
+        ..Config::default()
+    };
+    let (vm, _) = run_vm(binary(), &["loop"], config);
+    let stats = vm.stats().unwrap();
+    let (steps, opcodes, mnemonics, modes, syscalls) = (
+        vm.steps(),
+        stats.opcodes(),
+        stats.mnemonics(),
+        stats.modes(),
+        stats.syscalls(),
+    );
+
+    assert_eq!(steps, 11);
+    assert_eq!(
+        opcodes,
+        [
+            ("DecRegister", 3),
+            ("Jne", 3),
+            ("MovImmediate", 3),
+            ("IntTypeSpecified", 2),
+        ]
+    );
+    let mnemonics: Vec<(&str, usize)> = mnemonics.iter().map(|(m, n)| (m.as_str(), *n)).collect();
+    assert_eq!(mnemonics, [("dec", 3), ("jne", 3), ("mov", 3), ("int", 2)]);
+    assert_eq!(modes, [("register", 6), ("immediate", 5), ("relative", 3)]);
+    let syscalls: Vec<(&str, usize)> = syscalls.iter().map(|(s, n)| (s.as_str(), *n)).collect();
+    assert_eq!(syscalls, [("exit", 1), ("write", 1)]);
+}
+
+#[test]
+fn stats_disabled() {
+    let config = Config {
+        output: None,
+        ..Config::default()
+    };
+    let (vm, _) = run_vm(binary(), &[], config);
+    assert!(vm.stats().is_none());
+}
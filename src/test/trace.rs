@@ -0,0 +1,260 @@
+use super::run_captured;
+use crate::arch::compress::{Compressor, Entry, Step};
+use crate::arch::trace::{self, Filter, Format, Registers, Writer, MAGIC};
+use crate::arch::vm::Capture;
+use crate::json::{self, Json};
+use std::fs;
+
+fn run(format: Format) -> Vec<u8> {
+    run_filtered(format, Filter::default())
+}
+
+fn run_filtered(format: Format, filter: Filter) -> Vec<u8> {
+    record("bin/1c", format, filter, false)
+}
+
+fn record(path: &str, format: Format, filter: Filter, compress: bool) -> Vec<u8> {
+    let bytes = fs::read(path).unwrap();
+    let (_, _, trace) = run_captured(bytes, &[path], |config, w| {
+        config.trace = Some(w);
+        config.trace_format = format;
+        config.trace_filter = filter;
+        config.trace_compress = compress;
+    });
+    trace
+}
+
+fn jsonl() -> Vec<Json> {
+    let out = String::from_utf8(run(Format::Jsonl)).unwrap();
+    out.lines().map(|line| json::parse(line).unwrap()).collect()
+}
+
+fn get<'a>(json: &'a Json, path: &[&str]) -> &'a Json {
+    path.iter()
+        .fold(json, |json, key| json.get(key).unwrap_or(&Json::Null))
+}
+
+#[test]
+fn trace_jsonl() {
+    let records = jsonl();
+
+    assert_eq!(get(&records[0], &["step"]), &Json::Int(1));
+    assert_eq!(get(&records[0], &["ip"]), &Json::Int(0));
+    assert_eq!(
+        get(&records[0], &["instruction", "text"]),
+        &Json::Str("xor bp, bp".to_owned())
+    );
+    assert_eq!(get(&records[0], &["after", "ip"]), &Json::Int(2));
+
+    // mov ax, [bx]
+    let read = json::parse(r#"[{"addr":65504,"width":2,"value":1}]"#).unwrap();
+    assert_eq!(get(&records[2], &["reads"]), &read);
+    assert_eq!(get(&records[2], &["after", "ax"]), &Json::Int(1));
+    // leaはメモリを読まない
+    assert_eq!(get(&records[3], &["reads"]), &Json::Array(Vec::new()));
+    // push cx
+    let write = json::parse(r#"[{"addr":65502,"width":2,"value":65510}]"#).unwrap();
+    assert_eq!(get(&records[17], &["writes"]), &write);
+
+    let syscalls: Vec<&Json> = records
+        .iter()
+        .filter_map(|r| match get(r, &["syscalls"]) {
+            Json::Array(list) if !list.is_empty() => Some(&list[0]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(syscalls.len(), 2);
+    assert_eq!(get(syscalls[0], &["name"]), &Json::Str("write".to_owned()));
+    assert_eq!(get(syscalls[0], &["result"]), &Json::Int(6));
+    assert_eq!(
+        get(syscalls[0], &["data"]),
+        &Json::Str("68656c6c6f0a".to_owned())
+    );
+    assert_eq!(get(syscalls[1], &["name"]), &Json::Str("exit".to_owned()));
+    assert_eq!(get(syscalls[1], &["result"]), &Json::Null);
+}
+
+#[test]
+fn trace_bin() {
+    let bin = run(Format::Bin);
+    assert!(bin.starts_with(MAGIC));
+
+    // 版の後ろにフラグが付く
+    assert_eq!(bin[MAGIC.len()..MAGIC.len() + 2], [1, 0]);
+    let records = trace::read(&bin).unwrap().records;
+    let lines = jsonl();
+    assert_eq!(records.len(), lines.len());
+    for (record, line) in records.iter().zip(&lines) {
+        assert_eq!(&record.to_json(), line);
+    }
+
+    // 書き直すと同じバイト列になる
+    let mut encoded = bin[..MAGIC.len() + 2].to_vec();
+    for record in &records {
+        record.encode(&mut encoded);
+    }
+    assert_eq!(encoded, bin);
+
+    assert!(records[2]
+        .render()
+        .ends_with("mov ax, [bx]           ax=0001 r[ffe0]=0001"));
+    assert!(records[63]
+        .render()
+        .ends_with("\n         <write(0x0001, 0x0006, 0x0000, 0x0004) => 6> \"hello\\n\""));
+
+    assert!(trace::read(b"{\"step\":1}").is_err());
+    assert!(trace::read(&bin[..bin.len() - 1]).is_err());
+    let mut other = bin.clone();
+    other[MAGIC.len()] = 2;
+    assert!(trace::read(&other).is_err());
+}
+
+fn text(filter: Filter) -> Vec<String> {
+    let out = String::from_utf8(run_filtered(Format::Text, filter)).unwrap();
+    out.lines().map(str::to_owned).collect()
+}
+
+#[test]
+fn trace_filter() {
+    // _mainからcall _writeまで
+    let lines = text(Filter {
+        from: Some(0x0039),
+        until: Some(0x0048),
+        ..Filter::default()
+    });
+    assert_eq!(lines.len(), 1 + 9 + 1);
+    assert!(lines[1].ends_with("0039:55           push bp"));
+    assert!(lines[9].ends_with("0048:e84100       call 008c"));
+    // トレースの外の出力はそのまま書く
+    assert_eq!(lines[10], "hello");
+
+    let lines = text(Filter {
+        steps: Some((3, 5)),
+        ..Filter::default()
+    });
+    assert_eq!(lines.len(), 1 + 2 + 1);
+    assert!(lines[1].contains("0004:8b07"));
+
+    let lines = text(Filter {
+        range: Some((0x0000, 0x0006)),
+        ..Filter::default()
+    });
+    assert_eq!(lines.len(), 1 + 3 + 1);
+
+    let lines = text(Filter {
+        only_syscalls: true,
+        ..Filter::default()
+    });
+    assert_eq!(
+        lines[1..],
+        [
+            "0001 ffb4 0003 0004 ff9e ff9e ffb4 0000 -S-- 012f:cd20         int 20",
+            "<write(1, 0x0004, 6)hello",
+            " => 6>",
+            "0000 ffb6 0003 0001 ffa0 ffa0 ffb6 0000 --Z- 012f:cd20         int 20",
+            "<exit(6)>",
+        ]
+    );
+
+    // jsonl，binでは選んだ命令だけを記録する
+    let filter = Filter {
+        only_calls: true,
+        ..Filter::default()
+    };
+    let records = trace::read(&run_filtered(Format::Bin, filter))
+        .unwrap()
+        .records;
+    let steps: Vec<u64> = records.iter().map(|r| r.step).collect();
+    assert_eq!(steps[..4], [21, 30, 47, 57]);
+    assert!(records
+        .iter()
+        .all(|r| matches!(r.bytes[0], 0xe8 | 0xc3) && r.syscalls.is_empty()));
+    assert!(records[0].reads.is_empty());
+    assert_eq!(records[0].writes.len(), 1);
+}
+
+fn push_ips(compressor: &mut Compressor<u16>, ips: &[u16]) -> Vec<Entry<u16>> {
+    let mut entries = Vec::new();
+    for (i, &ip) in ips.iter().enumerate() {
+        let mut after = Registers::default();
+        // cxはループの度に1減る
+        after.reg[1] = 100 - (i / 3) as u16;
+        entries.extend(compressor.push(Step {
+            step: i as u64 + 1,
+            ip,
+            after,
+            item: ip,
+        }));
+    }
+    entries.extend(compressor.finish());
+    entries
+}
+
+#[test]
+fn trace_compressor() {
+    // 1, 2, 3の3命令のループを4回回って抜ける
+    let ips = [0, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3, 4, 5];
+    let entries = push_ips(&mut Compressor::default(), &ips);
+
+    let steps: Vec<u16> = entries
+        .iter()
+        .filter_map(|e| match e {
+            Entry::Step(ip) => Some(*ip),
+            Entry::Loop(_) => None,
+        })
+        .collect();
+    assert_eq!(steps, [0, 1, 2, 3, 4, 5]);
+    let Some(Entry::Loop(repeat)) = entries.get(4) else {
+        panic!("no loop");
+    };
+    assert_eq!(repeat.body, [1, 2, 3]);
+    assert_eq!(repeat.repeats, 3);
+    assert_eq!((repeat.first_step, repeat.last_step), (5, 13));
+    assert_eq!(repeat.deltas(), [("cx", -3)]);
+    assert_eq!(
+        repeat.render(),
+        "; loop 0001 0002 0003 repeated 3 more times (steps 5-13): cx=0060 (-3)"
+    );
+
+    // 2回だけの繰り返しはまとめない
+    let ips = [0, 1, 2, 1, 2, 3];
+    let entries = push_ips(&mut Compressor::default(), &ips);
+    assert_eq!(entries.len(), ips.len());
+    assert!(entries.iter().all(|e| matches!(e, Entry::Step(_))));
+}
+
+#[test]
+fn trace_compress() {
+    let full = record("bin/3c", Format::Text, Filter::default(), false);
+    let text = String::from_utf8(record("bin/3c", Format::Text, Filter::default(), true)).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines.len() < full.split(|&b| b == b'\n').count());
+    assert!(lines.contains(
+        &"; loop 1068 1069 103d 1040 1042 1044 1046 104b repeated 16 more times (steps 846-973): bx=0026 (+32) si=0013 (+16)"
+    ));
+
+    // binには全命令を記録し，読むときにまとめる
+    let bin = record("bin/3c", Format::Bin, Filter::default(), true);
+    let trace = trace::read(&bin).unwrap();
+    assert!(trace.compressed);
+    let expanded = trace::read(&record("bin/3c", Format::Bin, Filter::default(), false)).unwrap();
+    assert!(!expanded.compressed);
+    assert_eq!(trace.records, expanded.records);
+
+    let jsonl = record("bin/3c", Format::Jsonl, Filter::default(), true);
+    let capture = Capture::default();
+    let mut writer = Writer::new(Box::new(capture.clone()), Format::Jsonl, true);
+    for record in &trace.records {
+        writer.write(record).unwrap();
+    }
+    writer.flush().unwrap();
+    assert_eq!(capture.contents(), jsonl);
+    let summary = String::from_utf8(jsonl).unwrap();
+    let summary = summary
+        .lines()
+        .find(|l| l.starts_with("{\"loop\""))
+        .unwrap();
+    let summary = json::parse(summary).unwrap();
+    assert_eq!(get(&summary, &["loop", "repeats"]), &Json::Int(16));
+    assert_eq!(get(&summary, &["loop", "delta", "si"]), &Json::Int(16));
+}
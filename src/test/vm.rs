@@ -0,0 +1,162 @@
+use super::{link_src, run_captured, run_vm};
+use crate::arch::vm::{root_path, CallFrame, CallStack, Capture, Config, Halt, VM};
+use std::path::Path;
+use std::time::Duration;
+use std::{env, fs, process};
+
+#[test]
+fn vm_root_path() {
+    let root = Path::new("/srv/case");
+    let path = |name| root_path(root, name);
+    assert_eq!(path("/etc/passwd").unwrap(), root.join("etc/passwd"));
+    assert_eq!(path("a/../b/./c").unwrap(), root.join("b/c"));
+    assert_eq!(path("a/..").unwrap(), root);
+    // rootより上には出られない
+    assert!(path("../../etc/passwd").is_none());
+    assert!(path("/a/../../etc/passwd").is_none());
+}
+
+#[test]
+fn vm_call_stack() {
+    let mut stack = CallStack::default();
+    stack.call("f".to_owned(), 0x10);
+    stack.call("g".to_owned(), 0x20);
+    stack.call("h".to_owned(), 0x30);
+    // 戻りアドレスが一致しないretは無視する
+    assert_eq!(stack.ret(0x40), []);
+    assert_eq!(stack.depth(), 3);
+
+    // 一致した呼び出しより内側の呼び出しからもまとめて戻る
+    let frame = |name: &str, return_address| CallFrame {
+        name: name.to_owned(),
+        return_address,
+    };
+    assert_eq!(stack.ret(0x20), [frame("g", 0x20), frame("h", 0x30)]);
+    assert_eq!(stack.depth(), 1);
+}
+
+#[test]
+fn vm_truncated() {
+    let load = |bytes: &[u8]| VM::with_config(bytes.to_vec(), &[], Config::default()).err();
+    assert_eq!(load(&[]).as_deref(), Some("not a MINIX a.out file"));
+
+    // ヘッダはあるがテキストの途中で切れている
+    let bytes = fs::read("bin/1c").unwrap();
+    assert!(load(&bytes[..40]).is_some_and(|err| err.starts_with("truncated")));
+    assert!(load(&bytes).is_none());
+}
+
+#[test]
+fn vm_files() {
+    // "out"を作って"hi\n"を書き，標準出力と"out"を閉じる．結果はpadに書かれる
+    let src = r#"
+        .data
+pad:    dw 0, 0
+name:   db "out", 0
+msg:    db "hi", a
+cmsg:   dw 0, 8, 0, 1a4, name, 0
+wmsg:   dw 0, 4, 3, 3, 0, msg
+smsg:   dw 0, 6, 1
+xmsg:   dw 0, 6, 3
+ymsg:   dw 0, 6, 3
+emsg:   dw 0, 1, 0
+        .text
+        mov bx, cmsg
+        int 20
+        mov bx, wmsg
+        int 20
+        mov bx, smsg
+        int 20
+        mov bx, xmsg
+        int 20
+        mov bx, ymsg
+        int 20
+        mov bx, emsg
+        int 20
+"#;
+    let root = env::temp_dir().join(format!("minix_vm_files_{}", process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let (_, halt, trace) = run_captured(link_src(src, &[]), &["files"], |config, w| {
+        config.trace = Some(w);
+        config.root = Some(root.clone());
+    });
+    let written = fs::read(root.join("out")).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(halt, Halt::Exit(0));
+    assert_eq!(written, b"hi\n");
+    // fdはVMごとに3から割り当て，標準入出力はホストのものを閉じない
+    let trace = String::from_utf8(trace).unwrap();
+    let calls: Vec<&str> = trace
+        .lines()
+        .filter(|l| l.starts_with("<creat") || l.starts_with("<close"))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            "<creat(\"out\", 0644) => 3>",
+            "<close(1) => 0>",
+            "<close(3) => 0>",
+            "<close(3) => -1>"
+        ]
+    );
+}
+
+#[test]
+fn vm_fault() {
+    // exitせずにテキストセグメントの終わりに達する
+    let bytes = link_src("nop\nnop", &[]);
+    let config = Config {
+        output: None,
+        ..Config::default()
+    };
+    let (vm, halt) = run_vm(bytes, &["fault"], config);
+    assert_eq!(halt, Halt::Fault);
+    assert_eq!(halt.exit_status(), 132);
+    assert!(vm.summary(halt).contains("last ip: 0002"));
+}
+
+#[test]
+fn vm_timeout() {
+    let bytes = link_src(".hang: jmp .hang", &[]);
+    let config = Config {
+        output: None,
+        timeout: Some(Duration::from_millis(10)),
+        ..Config::default()
+    };
+    let (_, halt) = run_vm(bytes, &["hang"], config);
+    assert_eq!(halt, Halt::Timeout);
+    assert_eq!(halt.exit_status(), 124);
+}
+
+#[test]
+fn vm_output_limit() {
+    // "hi\n"を書き続ける
+    let src = r#"
+        .data
+msg:    db "hi", a
+wmsg:   dw 0, 4, 1, 3, 0, msg
+        .text
+.loop:  mov bx, wmsg
+        int 20
+        jmp .loop
+"#;
+    let capture = Capture::default();
+    let trace = Capture::default();
+    let config = Config {
+        output: Some(Box::new(capture.clone())),
+        trace: Some(Box::new(trace.clone())),
+        max_output_bytes: Some(5),
+        ..Config::default()
+    };
+    let (_, halt) = run_vm(link_src(src, &[]), &["yes"], config);
+    assert_eq!(halt, Halt::OutputLimit);
+    assert_eq!(halt.exit_status(), 122);
+    assert_eq!(capture.contents(), b"hi\nhi");
+
+    // 切った書き込みは書いたバイト数を返す
+    let trace = String::from_utf8(trace.contents()).unwrap();
+    let writes: Vec<&str> = trace.lines().filter(|l| l.starts_with("<write")).collect();
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[1], "<write(1, 0x0000, 3)hi => 2>");
+}
@@ -0,0 +1,491 @@
+use std::collections::BTreeMap;
+
+// 仕様ファイル用のTOMLのサブセット．日時型には対応しない
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(x) => Some(*x),
+            Value::Integer(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+pub fn parse(src: &str) -> Result<Table, String> {
+    let mut parser = Parser {
+        chars: src.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+
+    parser
+        .document()
+        .map_err(|err| format!("line {}: {err}", parser.line))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next() {
+            Some(x) if x == c => Ok(()),
+            Some(x) => Err(format!("expected '{c}', found '{x}'")),
+            None => Err(format!("expected '{c}', found end of file")),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    // 空白，改行，コメントを読み飛ばす
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(format!("unexpected '{c}' after value")),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        // 現在の[table]または[[array]]へのキーの並び
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_blank();
+            let Some(c) = self.peek() else {
+                return Ok(root);
+            };
+
+            if c == '[' {
+                self.next();
+                let is_array = self.peek() == Some('[');
+                if is_array {
+                    self.next();
+                }
+
+                self.skip_spaces();
+                let path = self.key()?;
+                self.skip_spaces();
+                self.expect(']')?;
+                if is_array {
+                    self.expect(']')?;
+                }
+                self.end_of_line()?;
+
+                let (last, parent) = path.split_last().unwrap();
+                let table = table_at(&mut root, parent)?;
+
+                if is_array {
+                    let entry = table
+                        .entry(last.clone())
+                        .or_insert_with(|| Value::Array(Vec::new()));
+                    match entry {
+                        Value::Array(items) => items.push(Value::Table(Table::new())),
+                        _ => return Err(format!("'{last}' is not an array of tables")),
+                    }
+                } else {
+                    match table.get(last) {
+                        None => {
+                            table.insert(last.clone(), Value::Table(Table::new()));
+                        }
+                        Some(Value::Table(_)) => {}
+                        Some(_) => return Err(format!("'{last}' is not a table")),
+                    }
+                }
+
+                current = path;
+            } else {
+                let path = self.key()?;
+                self.skip_spaces();
+                self.expect('=')?;
+                self.skip_spaces();
+                let value = self.value()?;
+                self.end_of_line()?;
+
+                let table = table_at(&mut root, &current)?;
+                insert(table, &path, value)?;
+            }
+        }
+    }
+
+    // ドットで区切られたキー
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => {
+                    self.next();
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.next();
+                    self.literal_string()?
+                }
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err("expected a key".to_owned());
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(part);
+
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        if self.starts_with("\"\"\"") {
+            self.pos += 3;
+            return self.multiline_string(true).map(Value::String);
+        }
+        if self.starts_with("'''") {
+            self.pos += 3;
+            return self.multiline_string(false).map(Value::String);
+        }
+
+        match self.peek() {
+            Some('"') => {
+                self.next();
+                self.basic_string().map(Value::String)
+            }
+            Some('\'') => {
+                self.next();
+                self.literal_string().map(Value::String)
+            }
+            Some('[') => {
+                self.next();
+                self.array()
+            }
+            Some('{') => {
+                self.next();
+                self.inline_table()
+            }
+            Some(_) if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Boolean(true))
+            }
+            Some(_) if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Boolean(false))
+            }
+            Some(_) => self.number(),
+            None => Err("expected a value".to_owned()),
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('e') => '\u{1b}',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ ('u' | 'U')) => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.next()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(format!("invalid unicode escape '\\{u}{hex}'"))?
+            }
+            Some(c) => return Err(format!("invalid escape '\\{c}'")),
+            None => return Err("unterminated string".to_owned()),
+        };
+        Ok(c)
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.escape()?),
+                Some('\n') | None => return Err("unterminated string".to_owned()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None => return Err("unterminated string".to_owned()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn multiline_string(&mut self, basic: bool) -> Result<String, String> {
+        let delimiter = if basic { "\"\"\"" } else { "'''" };
+        let mut s = String::new();
+
+        // 開始直後の改行は含めない
+        if self.starts_with("\r\n") {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+
+        loop {
+            if self.starts_with(delimiter) {
+                self.pos += 3;
+                return Ok(s);
+            }
+
+            match self.next() {
+                Some('\\') if basic => {
+                    // 行末のバックスラッシュは次の空白でない文字までを取り除く
+                    if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.next();
+                        }
+                    } else {
+                        s.push(self.escape()?);
+                    }
+                }
+                Some('\r') if self.peek() == Some('\n') => {}
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+
+            items.push(self.value()?);
+
+            self.skip_blank();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err("expected ',' or ']' in array".to_owned()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        let mut table = Table::new();
+
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Table(table));
+        }
+
+        loop {
+            let path = self.key()?;
+            self.skip_spaces();
+            self.expect('=')?;
+            self.skip_spaces();
+            let value = self.value()?;
+            insert(&mut table, &path, value)?;
+
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Table(table)),
+                _ => return Err("expected ',' or '}' in inline table".to_owned()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+            self.pos += 1;
+        }
+
+        let token: String = self.chars[start..self.pos].iter().collect();
+        let digits = token.replace('_', "");
+        let (sign, unsigned) = match digits.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+        };
+
+        let radix = match unsigned.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+
+        let integer = match radix {
+            Some(radix) => i64::from_str_radix(&unsigned[2..], radix).ok(),
+            None => unsigned.parse::<i64>().ok(),
+        };
+
+        if let Some(n) = integer {
+            return Ok(Value::Integer(sign * n));
+        }
+
+        match digits.parse::<f64>() {
+            Ok(x) if radix.is_none() && !token.is_empty() => Ok(Value::Float(x)),
+            _ if token.is_empty() => Err("expected a value".to_owned()),
+            _ => Err(format!("invalid value '{token}'")),
+        }
+    }
+}
+
+// ドット区切りのキーが指すテーブル．途中のテーブルがなければ作る
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        table = match entry {
+            Value::Table(t) => t,
+            // [[array]]の後に続くキーは，配列の最後の要素を指す
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("'{key}' is not a table")),
+            },
+            _ => return Err(format!("'{key}' is not a table")),
+        };
+    }
+
+    Ok(table)
+}
+
+fn insert(table: &mut Table, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parent) = path.split_last().unwrap();
+    let table = table_at(table, parent)?;
+
+    if table.contains_key(last) {
+        return Err(format!("duplicate key '{last}'"));
+    }
+
+    table.insert(last.clone(), value);
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::arch::trace::{self, Format, Writer};
+use crate::cli::Cli;
+use std::fs;
+use std::io;
+
+// `minix_vm trace-dump` の本体．--trace-format=binで書いたトレースを表示する．
+// --trace-compressで記録したトレースは，--expandがなければ繰り返しをまとめて表示する
+pub fn main(cli: &Cli) -> io::Result<i32> {
+    let trace = match trace::read(&fs::read(&cli.file)?) {
+        Ok(trace) => trace,
+        Err(err) => {
+            eprintln!("minix_vm: {}: {err}", cli.file);
+            return Ok(1);
+        }
+    };
+
+    let format = cli
+        .trace_format
+        .as_deref()
+        .and_then(Format::from_name)
+        .unwrap_or(Format::Text);
+    let compress = (trace.compressed || cli.trace_compress) && !cli.expand;
+    let mut writer = Writer::new(Box::new(io::stdout()), format, compress);
+    for record in &trace.records {
+        writer.write(record)?;
+    }
+    writer.flush()?;
+
+    Ok(0)
+}